@@ -21,26 +21,29 @@ final DynamicLibrary _lib = (() {
   }
 })();
 
-/// Rust Buffer struct - optimized with packed layout
-class Buffer extends Struct {
+/// Rust BufferCap struct - replies keep their pooled capacity, so free with all three fields
+class BufferCap extends Struct {
   external Pointer<Uint8> ptr;
 
   @Uint64()
   external int len;
+
+  @Uint64()
+  external int cap;
 }
 
 /// FFI bindings - C function signatures
 typedef init_http_client_c = Int8 Function();
-typedef execute_request_binary_c = Buffer Function(Pointer<Uint8> ptr, Uint64 len);
-typedef execute_requests_batch_binary_c = Buffer Function(Pointer<Uint8> ptr, Uint64 len);
-typedef free_buffer_c = Void Function(Pointer<Uint8> ptr, Uint64 len);
+typedef execute_request_binary_c = BufferCap Function(Pointer<Uint8> ptr, Uint64 len);
+typedef execute_requests_batch_binary_c = BufferCap Function(Pointer<Uint8> ptr, Uint64 len);
+typedef free_buffer_with_capacity_c = Void Function(Pointer<Uint8> ptr, Uint64 len, Uint64 cap);
 typedef shutdown_http_client_c = Void Function();
 
 /// Dart-friendly typedefs - optimized for performance
 typedef InitHttpClient = bool Function();
-typedef ExecuteRequestBinary = Buffer Function(Pointer<Uint8> ptr, int len);
-typedef ExecuteRequestsBatchBinary = Buffer Function(Pointer<Uint8> ptr, int len);
-typedef FreeBuffer = void Function(Pointer<Uint8> ptr, int len);
+typedef ExecuteRequestBinary = BufferCap Function(Pointer<Uint8> ptr, int len);
+typedef ExecuteRequestsBatchBinary = BufferCap Function(Pointer<Uint8> ptr, int len);
+typedef FreeBuffer = void Function(Pointer<Uint8> ptr, int len, int cap);
 typedef ShutdownHttpClient = void Function();
 
 /// Pre-cached function pointers for maximum performance
@@ -58,7 +61,7 @@ class _FFICache {
       _initHttpClientRaw = _lib.lookupFunction<init_http_client_c, int Function()>('init_http_client');
       _executeRequestBinary = _lib.lookupFunction<execute_request_binary_c, ExecuteRequestBinary>('execute_request_binary');
       _executeRequestsBatchBinary = _lib.lookupFunction<execute_requests_batch_binary_c, ExecuteRequestsBatchBinary>('execute_requests_batch_binary');
-      _freeBuffer = _lib.lookupFunction<free_buffer_c, FreeBuffer>('free_buffer');
+      _freeBuffer = _lib.lookupFunction<free_buffer_with_capacity_c, FreeBuffer>('free_buffer_with_capacity');
       _shutdownHttpClient = _lib.lookupFunction<shutdown_http_client_c, ShutdownHttpClient>('shutdown_http_client');
       _initialized = true;
     }
@@ -73,21 +76,21 @@ bool initHttpClient() {
 }
 
 @pragma('vm:prefer-inline')
-Buffer executeRequestBinary(Pointer<Uint8> ptr, int len) {
+BufferCap executeRequestBinary(Pointer<Uint8> ptr, int len) {
   _FFICache._ensureInitialized();
   return _FFICache._executeRequestBinary(ptr, len);
 }
 
 @pragma('vm:prefer-inline')
-Buffer executeRequestsBatchBinary(Pointer<Uint8> ptr, int len) {
+BufferCap executeRequestsBatchBinary(Pointer<Uint8> ptr, int len) {
   _FFICache._ensureInitialized();
   return _FFICache._executeRequestsBatchBinary(ptr, len);
 }
 
 @pragma('vm:prefer-inline')
-void freeBuffer(Pointer<Uint8> ptr, int len) {
+void freeBuffer(Pointer<Uint8> ptr, int len, int cap) {
   _FFICache._ensureInitialized();
-  _FFICache._freeBuffer(ptr, len);
+  _FFICache._freeBuffer(ptr, len, cap);
 }
 
 @pragma('vm:prefer-inline')
@@ -5,6 +5,7 @@ import 'dart:typed_data';
 import 'package:ffi/ffi.dart';
 
 import 'bindings.dart';
+import 'exceptions.dart';
 import 'dart:ffi';
 
 class _WorkerRequest {
@@ -65,7 +66,7 @@ void _workerIsolateEntry(SendPort mainSendPort) {
             final result = utf8.decode(buffer.ptr.asTypedList(buffer.len));
             mainSendPort.send(_WorkerResponse(message.id, result: result));
           } finally {
-            freeBuffer(buffer.ptr, buffer.len);
+            freeBuffer(buffer.ptr, buffer.len, buffer.cap);
           }
         } finally {
           malloc.free(ptr);
@@ -142,14 +143,23 @@ class FlutterRustHttp {
   Future<Map<String, dynamic>> request(Map<String, dynamic> payload) async {
     final jsonBytes = utf8.encode(jsonEncode(payload));
     final result = await _sendRequest('single', Uint8List.fromList(jsonBytes));
-    return jsonDecode(result) as Map<String, dynamic>;
+    return _throwIfError(jsonDecode(result)) as Map<String, dynamic>;
   }
 
+  /// Results are in request order; a failed request's entry is `{'error': {code, message, details}}`
   @pragma('vm:prefer-inline')
   Future<List<Map<String, dynamic>>> requestBatch(List<Map<String, dynamic>> payloads) async {
     final jsonBytes = utf8.encode(jsonEncode(payloads));
     final result = await _sendRequest('batch', Uint8List.fromList(jsonBytes));
-    return (jsonDecode(result) as List).cast<Map<String, dynamic>>();
+    return (_throwIfError(jsonDecode(result)) as List).cast<Map<String, dynamic>>();
+  }
+
+  /// Failed calls come back as `{"error": {code, message, details}}` instead of a response
+  dynamic _throwIfError(dynamic decoded) {
+    if (decoded is Map<String, dynamic> && decoded['error'] is Map<String, dynamic>) {
+      throw HttpException.fromError(decoded['error'] as Map<String, dynamic>);
+    }
+    return decoded;
   }
 
   @pragma('vm:prefer-inline')
@@ -2,19 +2,36 @@ class HttpException implements Exception {
   final String message;
   final int? statusCode;
   final dynamic response;
+  /// Error code from the native client, e.g. `TIMEOUT`, `DNS`, `TLS`, `CONNECT`
+  final String? code;
+  final dynamic details;
 
-  HttpException(this.message, {this.statusCode, this.response});
+  HttpException(this.message, {this.statusCode, this.response, this.code, this.details});
+
+  /// Builds the matching exception from the native `{"error": {code, message, details}}` reply
+  factory HttpException.fromError(Map<String, dynamic> error) {
+    final code = error['code'] as String? ?? 'UNKNOWN';
+    final message = error['message'] as String? ?? code;
+    final details = error['details'];
+    if (code.endsWith('TIMEOUT')) {
+      return TimeoutException(message, code: code, details: details);
+    }
+    if (code == 'CONNECT' || code == 'DNS' || code == 'TLS' || code == 'PROXY') {
+      return NetworkException(message, code: code, details: details);
+    }
+    return HttpException(message, code: code, details: details);
+  }
 
   @override
-  String toString() => 'HttpException: $message${statusCode != null ? ' ($statusCode)' : ''}';
+  String toString() => 'HttpException: ${code != null ? '[$code] ' : ''}$message${statusCode != null ? ' ($statusCode)' : ''}';
 }
 
 class NetworkException extends HttpException {
-  NetworkException(String message) : super(message);
+  NetworkException(String message, {String? code, dynamic details}) : super(message, code: code, details: details);
 }
 
 class TimeoutException extends HttpException {
-  TimeoutException(String message) : super(message);
+  TimeoutException(String message, {String? code, dynamic details}) : super(message, code: code, details: details);
 }
 
 class ClientException extends HttpException {
@@ -23,4 +40,4 @@ class ClientException extends HttpException {
 
 class ServerException extends HttpException {
   ServerException(String message, int statusCode) : super(message, statusCode: statusCode);
-}
\ No newline at end of file
+}
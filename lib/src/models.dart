@@ -58,6 +58,8 @@ class HttpResponse {
   final String version;
   final String url;
   final int elapsedMs;
+  /// Every `Set-Cookie` value; `headers['set-cookie']` only holds the last one
+  final List<String> setCookies;
 
   HttpResponse({
     required this.statusCode,
@@ -66,6 +68,7 @@ class HttpResponse {
     this.version = '1.1',
     this.url = '',
     this.elapsedMs = 0,
+    this.setCookies = const [],
   });
 
   factory HttpResponse.fromJson(Map<String, dynamic> json) => HttpResponse(
@@ -75,6 +78,7 @@ class HttpResponse {
     version: json['version'] ?? '1.1',
     url: json['url'] ?? '',
     elapsedMs: json['elapsed_ms'] ?? 0,
+    setCookies: List<String>.from(json['set_cookies'] ?? const []),
   );
 
   dynamic get json {
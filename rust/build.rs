@@ -10,14 +10,28 @@ fn main() {
         .join("src");
 
     cbindgen::Builder::new()
-        .with_crate(crate_dir)
+        .with_crate(&crate_dir)
         .with_language(cbindgen::Language::C)
         .generate()
         .expect("Unable to generate bindings")
         .write_to_file(output_path.join("bindings.h"));
 
+    // Resolved versions for `build_info`, read from the lockfile rather than kept by hand
+    let lockfile = std::fs::read_to_string(PathBuf::from(&crate_dir).join("Cargo.lock")).unwrap_or_default();
+    for (package, var) in [("reqwest", "REQWEST_VERSION"), ("rustls", "RUSTLS_VERSION")] {
+        println!("cargo:rustc-env={}={}", var, locked_version(&lockfile, package).unwrap_or("unknown"));
+    }
+
     // Only link to external libraries if needed
     if env::var("TARGET").unwrap().contains("android") {
         println!("cargo:rustc-link-lib=dylib=log");
     }
 }
+
+/// Version of `package` in a `Cargo.lock`, the first one when several are locked
+fn locked_version<'a>(lockfile: &'a str, package: &str) -> Option<&'a str> {
+    let name = format!("name = \"{}\"", package);
+    let mut lines = lockfile.lines();
+    lines.find(|line| *line == name)?;
+    lines.next()?.strip_prefix("version = \"")?.strip_suffix('"')
+}
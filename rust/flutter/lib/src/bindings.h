@@ -3,19 +3,76 @@
 #include <stdint.h>
 #include <stdlib.h>
 
+#define FORMAT_VERSION 1
+
+/**
+ * First byte of an error reply; never a format version
+ */
+#define ERROR_MARKER 255
+
+/**
+ * Preview length used when `preview_chars` is not set
+ */
+#define DEFAULT_PREVIEW_CHARS 256
+
+/**
+ * First retry delay used when `retry_base_delay_ms` is not set
+ */
+#define DEFAULT_RETRY_BASE_DELAY_MS 200
+
+#define SHARED_POOL_MAX_IDLE_PER_HOST 100
+
+#define SHARED_MAX_REDIRECTS 5
+
+/**
+ * Limit until `set_max_concurrency` is called
+ */
+#define DEFAULT_MAX_CONCURRENCY 128
+
+/**
+ * A Rust-owned buffer handed to Dart. Replies are written into pooled buffers with spare
+ * capacity, so they must be freed with `free_buffer_with_capacity` and all three fields.
+ */
 typedef struct BufferCap {
   uint8_t *ptr;
   uintptr_t len;
   uintptr_t cap;
 } BufferCap;
 
-typedef struct Buffer {
-  uint8_t *ptr;
-  uintptr_t len;
-} Buffer;
+/**
+ * Result of `execute_request_raw_body`, each half freed separately with
+ * `free_buffer_with_capacity`. A failed request has an `{"error": ...}` reply in `meta`
+ * and an empty `body`.
+ */
+typedef struct RawResponse {
+  /**
+   * JSON `HttpResponse` with an empty `body` and a `body_preview`
+   */
+  struct BufferCap meta;
+  /**
+   * Undecoded body bytes
+   */
+  struct BufferCap body;
+} RawResponse;
 
 bool init_http_client(void);
 
+/**
+ * Pre-touches the pooled response buffers (e.g. when resuming after idle, before a
+ * latency-critical request). Raise `pretouch_buffer_bytes` too so the grown buffers
+ * stay pooled after being freed.
+ */
+void pretouch_response_buffers(uintptr_t bytes);
+
+/**
+ * Replace the app-wide `ClientSettings` from a JSON object (missing fields use defaults).
+ * Returns false if the JSON, `accept_language`, `alpn_protocols` or a logging
+ * `redact_header_patterns` entry is invalid; the previous settings are kept in that case.
+ * Client-level knobs (socket options, ...) only affect clients built afterwards,
+ * so call this before `init_http_client`.
+ */
+bool configure_http_client(const uint8_t *config_ptr, uintptr_t config_len);
+
 /**
  * Allocate a writable buffer in Rust and return pointer+capacity.
  * Dart will write UTF-8 JSON bytes into it.
@@ -31,19 +88,422 @@ void set_buffer_len(uint8_t *ptr, uintptr_t len, uintptr_t cap);
 /**
  * Execute a single request taking ownership of the buffer (NO COPY).
  */
-struct Buffer execute_request_binary_from_owned(uint8_t *ptr, uintptr_t len, uintptr_t cap);
+struct BufferCap execute_request_binary_from_owned(uint8_t *ptr, uintptr_t len, uintptr_t cap);
+
+/**
+ * Execute a single request taking ownership of the buffer, streaming the file at
+ * `path` as the body instead of reading it into Dart (overrides `body_file` in the JSON).
+ */
+struct BufferCap execute_request_with_file_body(uint8_t *ptr,
+                                                uintptr_t len,
+                                                uintptr_t cap,
+                                                const uint8_t *path_ptr,
+                                                uintptr_t path_len);
+
+/**
+ * Execute a single request taking ownership of the buffer and stream a 200/206 body into
+ * the file at `path` instead of memory. The JSON response carries a `download` object
+ * (`bytes_written`, `file_size`, `resumed`) and an empty `body`; other statuses leave the
+ * file alone and return their body as usual. Set `resume_download` to continue a partial file.
+ */
+struct BufferCap execute_download_to_file(uint8_t *ptr,
+                                          uintptr_t len,
+                                          uintptr_t cap,
+                                          const uint8_t *path_ptr,
+                                          uintptr_t path_len);
+
+/**
+ * Execute a single request taking ownership of the buffer, returning the response in the
+ * compact binary layout documented in `binary_format.rs` instead of JSON.
+ */
+struct BufferCap execute_request_binary_format(uint8_t *ptr, uintptr_t len, uintptr_t cap);
+
+/**
+ * Same as `execute_request_binary_format`, but the body is returned exactly as received
+ * (flag bit 2 set) instead of as UTF-8 text, so images, protobuf and other binary payloads
+ * survive intact. The response cache holds decoded text, so these requests bypass it.
+ */
+struct BufferCap execute_request_binary_format_raw(uint8_t *ptr, uintptr_t len, uintptr_t cap);
+
+/**
+ * Sends the request retained under `replay_id` `id` again, returning its JSON response.
+ * Returns null if `id` was never retained or has expired (see `replay::RequestReplay`).
+ */
+struct BufferCap resend_request(int64_t id);
+
+/**
+ * Releases the request retained under `replay_id` `id`; returns false if there was none
+ */
+bool forget_request(int64_t id);
+
+/**
+ * Execute a single request taking ownership of the buffer, sending the caller-owned
+ * `body_ptr..body_ptr+body_len` (e.g. an mmap'ed file) as the body without copying it.
+ *
+ * Lifetime contract: the region must stay mapped and unmodified until this call returns.
+ * The call does not return before every reference to the body is dropped, even when the
+ * response arrives before the upload finished or the request fails or times out, so
+ * unmapping right after the call is safe.
+ */
+struct BufferCap execute_request_with_borrowed_body(uint8_t *ptr,
+                                                    uintptr_t len,
+                                                    uintptr_t cap,
+                                                    const uint8_t *body_ptr,
+                                                    uintptr_t body_len);
 
 /**
- * Execute a batch taking ownership of the buffer (NO COPY).
+ * Execute a single request taking ownership of the buffer, returning the body as raw bytes
+ * next to a JSON envelope with a decoded preview (`preview_chars`, default 256).
  */
-struct Buffer execute_requests_batch_binary_from_owned(uint8_t *ptr, uintptr_t len, uintptr_t cap);
+struct RawResponse execute_request_raw_body(uint8_t *ptr, uintptr_t len, uintptr_t cap);
 
-struct Buffer execute_request_binary(const uint8_t *request_ptr, uintptr_t request_len);
+/**
+ * Execute a batch taking ownership of the buffer (NO COPY). Returns a JSON array in request
+ * order holding each response or, for a failed request, `{"error": {...}}`.
+ */
+struct BufferCap execute_requests_batch_binary_from_owned(uint8_t *ptr,
+                                                          uintptr_t len,
+                                                          uintptr_t cap);
+
+/**
+ * `execute_requests_batch_binary_from_owned` that also posts
+ * `[progress_id, PortEvent::BatchProgress, {completed, total, succeeded, failed}]`
+ * after every request; the last event has `completed == total`.
+ */
+struct BufferCap execute_requests_batch_with_progress(uint8_t *ptr,
+                                                      uintptr_t len,
+                                                      uintptr_t cap,
+                                                      int64_t progress_id);
+
+/**
+ * Runs a batch in the background, posting each result to the Dart port as it completes
+ * (see `batch_stream.rs`). At most `concurrency` requests run at once (0 = the same
+ * size-based default as the blocking batch). Takes ownership of the buffer and returns
+ * immediately; false if no Dart port is registered, in which case nothing is sent.
+ */
+bool execute_requests_batch_streaming(uint8_t *ptr,
+                                      uintptr_t len,
+                                      uintptr_t cap,
+                                      int64_t batch_id,
+                                      uintptr_t concurrency);
+
+/**
+ * Inline path: skips the `http-ffi-worker` thread and runs the request straight on the
+ * runtime, posting `[request_id, PortEvent::Response, json]` or
+ * `[request_id, PortEvent::Error, json]` to the Dart port (a JSON request that does not
+ * parse posts `INVALID_REQUEST`). Takes ownership of the buffer and returns immediately;
+ * false if no Dart port is registered, in which case nothing is sent. Requests run
+ * concurrently rather than one at a time as on the worker; `queue_wait_ms` is the wait
+ * for a runtime thread. Saves the channel hop and `block_on` hand-off of the blocking
+ * calls, about 15-20µs per request against a keep-alive local server on a Linux x86_64
+ * dev box (~105µs vs ~120µs median, result delivery included). Rust callers should use
+ * `HttpClient::execute_request` directly.
+ */
+bool execute_request_inline(uint8_t *ptr, uintptr_t len, uintptr_t cap, int64_t request_id);
+
+/**
+ * `execute_request_inline` for the compact layout of `execute_request_binary_format`: posts
+ * `[request_id, PortEvent::Response, bytes]` in the `binary_format.rs` layout, or
+ * `[request_id, PortEvent::Error, json]`, so Dart needs neither a helper isolate nor a JSON
+ * parse. Returns immediately; false if no Dart port is registered, in which case nothing is sent.
+ */
+bool execute_request_binary_async(uint8_t *ptr, uintptr_t len, uintptr_t cap, int64_t request_id);
+
+/**
+ * `execute_request_inline` that hands the body over as it arrives instead of buffering it:
+ * posts every chunk as `[stream_id, PortEvent::BodyChunk, bytes]`, then ends the stream with
+ * `[stream_id, PortEvent::Response, json]` (status and headers, empty `body`, total in
+ * `bytes_received`) or `[stream_id, PortEvent::Error, json]` (see `response_stream.rs`).
+ * The next chunk is read only once the previous one is posted. Returns immediately; false
+ * if no Dart port is registered, in which case nothing is sent.
+ */
+bool execute_streaming_request(uint8_t *ptr, uintptr_t len, uintptr_t cap, int64_t stream_id);
+
+/**
+ * `execute_request_inline` that returns a handle for `cancel_request` at submission, so a
+ * superseded request (e.g. search-as-you-type) can be cancelled while queued or in flight;
+ * it then posts `[request_id, PortEvent::Error, json]` with code `CANCELLED`. Returns 0 if
+ * no Dart port is registered, in which case nothing is sent.
+ */
+uint64_t execute_request_cancellable(uint8_t *ptr,
+                                     uintptr_t len,
+                                     uintptr_t cap,
+                                     int64_t request_id);
+
+/**
+ * Execute a single request taking ownership of the request buffer and write the
+ * serialized response into a caller-owned output buffer. The request bytes are not
+ * copied; the reply is serialized in Rust and copied once into `out_ptr`, so Dart has
+ * nothing to free.
+ *
+ * Returns the number of bytes written, `-(required_len)` if `out_cap` is too small, or `0`
+ * on failure. A reply that does not fit is kept for this thread: fetch it with
+ * `take_pending_reply` instead of calling again, which would send the request twice.
+ */
+intptr_t execute_request_into(uint8_t *req_ptr,
+                              uintptr_t req_len,
+                              uintptr_t req_cap,
+                              uint8_t *out_ptr,
+                              uintptr_t out_cap);
+
+/**
+ * Copies this thread's pending `execute_request_into` reply into `out_ptr` without
+ * re-executing the request. Returns the bytes written, `-(required_len)` if `out_cap` is
+ * still too small (the reply stays pending), or `0` if there is no pending reply.
+ */
+intptr_t take_pending_reply(uint8_t *out_ptr, uintptr_t out_cap);
+
+struct BufferCap execute_request_binary(const uint8_t *request_ptr, uintptr_t request_len);
 
-struct Buffer execute_requests_batch_binary(const uint8_t *requests_ptr, uintptr_t requests_len);
+struct BufferCap execute_requests_batch_binary(const uint8_t *requests_ptr, uintptr_t requests_len);
 
+/**
+ * Frees a `BufferCap` returned by this library (replies and `allocate_request_buffer`),
+ * returning small buffers to the response pool
+ */
 void free_buffer_with_capacity(uint8_t *ptr, uintptr_t len, uintptr_t cap);
 
-void free_buffer(uint8_t *ptr, uintptr_t len);
+/**
+ * Forces re-resolution on the next request: clears the DNS cache and all host overrides.
+ * Always succeeds (a no-op when nothing is cached).
+ */
+bool flush_dns(void);
+
+/**
+ * Probes `connectivity_check_url` and returns JSON `{status, probe_url, status_code,
+ * portal_url, elapsed_ms, error}` with `status` one of `online`, `captive_portal` or
+ * `offline` (see `connectivity::Connectivity`). Blocks for up to 5 s; free with `free_buffer_with_capacity`.
+ */
+struct BufferCap check_connectivity(void);
+
+/**
+ * Diagnostic DNS lookup through the client's resolver (overrides, cache, system); sends
+ * no HTTP traffic. Returns JSON `{host, addresses, source, ttl_ms, elapsed_us, error}`
+ * (`ttl_ms` is our cache lifetime, record TTLs are not available); free with `free_buffer_with_capacity`.
+ */
+struct BufferCap resolve_host(const uint8_t *host_ptr,
+                              uintptr_t host_len);
+
+/**
+ * Installs `env_logger` (configured via `RUST_LOG`) as the `log` sink; only needed when
+ * the host app has no logger of its own. Returns false if a logger is already set.
+ */
+bool init_debug_logging(void);
+
+/**
+ * Pins a host to a JSON list of IPs (e.g. `["10.0.0.5", "::1"]`); an empty list removes the pin.
+ * Affects every client, including ones built before the call.
+ */
+bool set_dns_override(const uint8_t *host_ptr,
+                      uintptr_t host_len,
+                      const uint8_t *addrs_ptr,
+                      uintptr_t addrs_len);
+
+/**
+ * Registers Basic credentials from JSON `{"host", "username", "password"}`; `host` may be
+ * `*.example.com`. Used by requests to a matching host that carry no `Authorization` header.
+ */
+bool add_credential(const uint8_t *ptr, uintptr_t len);
+
+/**
+ * Removes the credentials registered for exactly this host pattern
+ */
+bool remove_credential(const uint8_t *host_ptr, uintptr_t host_len);
+
+void clear_credentials(void);
+
+/**
+ * JSON list of `{"host", "username"}` (passwords are not returned)
+ */
+struct BufferCap list_credentials(void);
+
+/**
+ * Cookies of the shared cookie jar as a JSON array for `import_cookies`, session cookies
+ * included; free with `free_buffer_with_capacity`
+ */
+struct BufferCap export_cookies(void);
+
+/**
+ * Replaces the shared cookie jar with an `export_cookies` array, e.g. on app start.
+ * Returns the number of cookies loaded, or -1 if the JSON does not parse.
+ */
+int64_t import_cookies(const uint8_t *ptr, uintptr_t len);
+
+void clear_cookies(void);
+
+/**
+ * Request counters and latency histogram as Prometheus text; free with `free_buffer_with_capacity`
+ */
+struct BufferCap metrics_prometheus(void);
+
+/**
+ * Recent request timings as a Chrome Trace Event JSON array (see `trace::Trace`), `[]`
+ * while `trace_buffer_requests` is 0; free with `free_buffer_with_capacity`
+ */
+struct BufferCap export_trace(void);
+
+/**
+ * Per-host connection usage as JSON `{host: {in_use, peak_in_use, requests, connections_opened}}`;
+ * free with `free_buffer_with_capacity`
+ */
+struct BufferCap connection_stats(void);
+
+/**
+ * Compiled-in features, dependency versions and runtime sizing as JSON (see
+ * `build_info::BuildInfo`); free with `free_buffer_with_capacity`
+ */
+struct BufferCap build_info(void);
+
+/**
+ * Live configuration as JSON: current settings, the settings the shared client was built
+ * with and its fixed options (see `effective_config::EffectiveConfig`); free with `free_buffer_with_capacity`
+ */
+struct BufferCap get_effective_config(void);
+
+/**
+ * Opens Dart-pushed request body `id` for a request's `body_stream_id`.
+ * Returns false if `id` is already open.
+ */
+bool upload_stream_open(int64_t id);
+
+/**
+ * Copies `len` bytes into upload stream `id`. Returns false if it is closed or unknown.
+ */
+bool upload_stream_write(int64_t id, const uint8_t *data_ptr, uintptr_t data_len);
+
+/**
+ * Ends upload stream `id` after the chunks already written
+ */
+bool upload_stream_close(int64_t id);
+
+/**
+ * Call when the app goes to the background: stops HTTP/2 keep-alive pings and TCP
+ * keepalive probes by moving to a fresh client. Idle connections are closed; requests in
+ * flight finish on their old connection.
+ */
+void pause_connections(void);
+
+/**
+ * Call when the app returns to the foreground; restores the normal keep-alive settings.
+ * The first request per host afterwards opens a new connection.
+ */
+void resume_connections(void);
+
+/**
+ * Call when the OS reports a network change: cancels every in-flight request (they fail
+ * with `NETWORK_CHANGED`), flushes DNS and swaps in a fresh connection pool, in that order
+ * and without letting new requests reach the old pool. See `HttpClient::on_network_change`.
+ * Returns how many requests were cancelled.
+ */
+uintptr_t on_network_change(void);
+
+/**
+ * Trusts the CA certificates in a PEM bundle, or one DER certificate, on top of the webpki
+ * roots and rebuilds the clients, so new connections verify against them while requests in
+ * flight keep theirs. Returns how many certificates were added, or -1 (nothing added) if
+ * the data holds no certificate or one is not a valid trust anchor.
+ */
+int64_t add_root_certificate(const uint8_t *pem_ptr, uintptr_t pem_len);
+
+/**
+ * Forgets every `add_root_certificate` certificate and rebuilds the clients
+ */
+void clear_root_certificates(void);
+
+/**
+ * Staging aid: accept any server certificate (self-signed, expired, wrong host) and rebuild
+ * the clients. Only builds with the `dangerous-tls` feature honour it; others return false
+ * and keep verifying.
+ */
+bool set_accept_invalid_certs(bool accept);
+
+/**
+ * Caps the network attempts in flight across the whole app (single, batch and streaming
+ * requests, hedges and retries alike) at `n`; the rest wait for a slot. Default
+ * `concurrency_limit::DEFAULT_MAX_CONCURRENCY`. Returns false for 0, leaving the limit
+ * unchanged.
+ */
+bool set_max_concurrency(uintptr_t n);
+
+/**
+ * QA aid: delays every request by `latency_ms` before it is sent and paces response bodies
+ * to `bandwidth_kbps` (0 = unthrottled). Pass 0 for both to turn it off (the default);
+ * the current values are reported by `get_effective_config`.
+ */
+void set_network_simulation(uint64_t latency_ms, uint64_t bandwidth_kbps);
+
+/**
+ * Offline mode: requests fail fast with `OFFLINE` instead of waiting for a connect timeout.
+ * With `cancel_in_flight`, running requests are aborted with `OFFLINE` too.
+ */
+void set_offline(bool offline, bool cancel_in_flight);
+
+/**
+ * Cancels the request `execute_request_cancellable` returned `handle` for; it replies with a
+ * `CANCELLED` error. False if no such request is queued or in flight.
+ */
+bool cancel_request(uint64_t handle);
+
+/**
+ * Cancels every in-flight request whose `group` equals the given UTF-8 string; each one
+ * fails with `CANCELLED`. Returns how many requests were cancelled.
+ */
+uintptr_t cancel_group(const uint8_t *group_ptr, uintptr_t group_len);
+
+/**
+ * Pauses the download writing to the given UTF-8 path: the file is flushed and kept, and
+ * the request succeeds with `download.paused`. Returns false if no download targets it.
+ */
+bool pause_download(const uint8_t *path_ptr, uintptr_t path_len);
+
+/**
+ * Copies up to `len` bytes of body `handle` starting at `offset` into `out_ptr` (which must
+ * be valid for `len` writable bytes). Returns the number copied, short at the end of the
+ * body and 0 past it, or -1 for an unknown or freed handle. See `body_as_handle`.
+ */
+intptr_t read_body_slice(uint64_t handle, uint64_t offset, uintptr_t len, uint8_t *out_ptr);
+
+/**
+ * Releases body `handle`; call exactly once per handle. Returns false if it was unknown.
+ */
+bool free_body_handle(uint64_t handle);
+
+/**
+ * Drops every cached GET response (see `cache_mode` on requests)
+ */
+void clear_response_cache(void);
 
 void shutdown_http_client(void);
+
+/**
+ * Initializes the Dart DL API from `NativeApi.initializeApiDLData`.
+ * Returns 0 on success and -1 if the data is missing or from an incompatible Dart SDK.
+ */
+intptr_t init_dart_api_dl(void *data);
+
+/**
+ * Registers the `ReceivePort.sendPort.nativePort` that async results and events go to.
+ */
+void register_dart_port(int64_t port);
+
+/**
+ * Opens a connection to `host:port` (TLS with SNI = `host` when `use_tls`).
+ * Returns false if the host is not UTF-8 or `socket_id` is already in use;
+ * connection failures are reported asynchronously through `RawClosed`.
+ */
+bool raw_socket_open(const uint8_t *host_ptr,
+                     uintptr_t host_len,
+                     uint16_t port,
+                     bool use_tls,
+                     int64_t socket_id);
+
+/**
+ * Queues bytes to be written verbatim. Returns false if the socket is not open.
+ */
+bool raw_socket_write(int64_t socket_id, const uint8_t *data_ptr, uintptr_t data_len);
+
+/**
+ * Half-closes the socket: pending writes are flushed and the write side is shut down.
+ * Reading continues until the peer closes, so the response is still delivered.
+ */
+bool raw_socket_close(int64_t socket_id);
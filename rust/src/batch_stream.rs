@@ -0,0 +1,109 @@
+//! Streaming batches: every request is reported on its own as soon as it finishes.
+//!
+//! Each result is posted as `[batch_id, PortEvent::BatchItem, json]` where `json` is
+//! `{"index": i, "response": {...}}` or `{"index": i, "error": {...}}`, `i` being the
+//! request's position in the submitted array. A final
+//! `[batch_id, PortEvent::BatchComplete, {"count": n, "errors": n}]` follows the last item;
+//! if the batch JSON cannot be parsed it carries `"error"` and no items are sent.
+
+use crate::dart_dl_ffi::{self, PortEvent};
+use crate::http_client::HttpClient;
+use crate::json;
+use crate::models::{HttpError, HttpRequest, HttpResponse};
+
+use futures_util::StreamExt;
+use serde::Serialize;
+
+#[derive(Serialize)]
+struct BatchItemEvent<'a> {
+    index: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response: Option<&'a HttpResponse>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<HttpError>,
+}
+
+#[derive(Serialize)]
+struct BatchCompleteEvent {
+    count: usize,
+    errors: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+pub struct BatchStream;
+
+impl BatchStream {
+    /// Runs `requests_bytes` (a JSON request array) with at most `concurrency` requests in
+    /// flight (0 = the blocking batch's default), posting results in completion order
+    pub async fn run(client: &HttpClient, requests_bytes: Vec<u8>, batch_id: i64, concurrency: usize) {
+        let mut document = json::Document::new(requests_bytes);
+        let requests: Vec<HttpRequest<'_>> = match document.deserialize() {
+            Ok(requests) => requests,
+            Err(e) => {
+                Self::complete(batch_id, BatchCompleteEvent { count: 0, errors: 0, error: Some(e.to_string()) });
+                return;
+            }
+        };
+
+        let count = requests.len();
+        let concurrency = match concurrency {
+            0 => crate::batch_concurrency(count),
+            limit => limit,
+        };
+        let mut errors = 0;
+        // Futures are built up front (they are lazy): a `.map` closure over borrowed requests
+        // held across awaits trips the `Send` check for `spawn`
+        let pending: Vec<_> = requests.into_iter().enumerate()
+            .map(|(index, request)| Self::execute(client, index, request))
+            .collect();
+        let mut results = futures_util::stream::iter(pending).buffer_unordered(concurrency);
+
+        while let Some((index, result)) = results.next().await {
+            let event = match &result {
+                Ok(response) => BatchItemEvent { index, response: Some(response), error: None },
+                Err(e) => {
+                    errors += 1;
+                    BatchItemEvent { index, response: None, error: Some(HttpError::from_error(e)) }
+                }
+            };
+            if let Ok(payload) = json::to_vec(&event) {
+                dart_dl_ffi::post_message(batch_id, PortEvent::BatchItem, &payload);
+            }
+        }
+
+        Self::complete(batch_id, BatchCompleteEvent { count, errors, error: None });
+    }
+
+    async fn execute<'a>(client: &HttpClient, index: usize, request: HttpRequest<'a>) -> (usize, anyhow::Result<HttpResponse>) {
+        (index, client.execute_request(request).await)
+    }
+
+    fn complete(batch_id: i64, event: BatchCompleteEvent) {
+        if let Ok(payload) = json::to_vec(&event) {
+            dart_dl_ffi::post_message(batch_id, PortEvent::BatchComplete, &payload);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::request_json;
+    use crate::transport::tests::counting;
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn keeps_at_most_concurrency_requests_in_flight() {
+        let (transport, peak) = counting(Duration::from_millis(10));
+        let transport = Arc::new(transport);
+        let client = HttpClient::with_transport(transport.clone());
+        let requests: Vec<Vec<u8>> = (0..20).map(|i| request_json(&format!("http://mock.test/{}", i), 5_000)).collect();
+        let batch = [b"[".to_vec(), requests.join(&b","[..]), b"]".to_vec()].concat();
+        BatchStream::run(&client, batch, 1, 3).await;
+        assert_eq!(transport.requests().len(), 20);
+        assert!(peak.load(Ordering::SeqCst) <= 3, "peak {}", peak.load(Ordering::SeqCst));
+    }
+}
@@ -47,12 +47,12 @@ async fn test_single_request_latency(client: &Arc<HttpClient>, url: &str) {
     let test_runs = 10;
 
     for _ in 0..warmup_runs {
-        let _ = make_request(client, url, rand::thread_rng().gen::<u32>()).await;
+        let _ = make_request(client, url, rand::rng().random::<u32>()).await;
     }
 
     for i in 0..test_runs {
         let start = Instant::now();
-        match make_request(client, url, rand::thread_rng().gen::<u32>()).await {
+        match make_request(client, url, rand::rng().random::<u32>()).await {
             Ok(response) => {
                 let duration = start.elapsed();
                 latencies.push(duration);
@@ -83,7 +83,7 @@ async fn test_sequential_requests(client: &Arc<HttpClient>, urls: &[&str]) {
 
     for (i, url) in urls.iter().enumerate() {
         let request_start = Instant::now();
-        match make_request(client, url, rand::thread_rng().gen::<u32>()).await {
+        match make_request(client, url, rand::rng().random::<u32>()).await {
             Ok(response) => {
                 let duration = request_start.elapsed();
                 println!("Request {}: {}ms, Status: {}, Size: {} bytes",
@@ -108,7 +108,7 @@ async fn test_concurrent_requests(client: &Arc<HttpClient>, urls: &[&str], concu
         let client = Arc::clone(client); // clone Arc
         let url = url.to_string();
         let permit = semaphore.clone().acquire_owned().await.unwrap();
-        let random_val = rand::thread_rng().gen::<u32>();
+        let random_val = rand::rng().random::<u32>();
 
         tasks.push(tokio::spawn(async move {
             let request_start = Instant::now();
@@ -149,7 +149,7 @@ async fn test_throughput(client: &Arc<HttpClient>, url: &str, count: usize) {
     let mut total_bytes = 0;
 
     for i in 0..count {
-        match make_request(client, url, rand::thread_rng().gen::<u32>()).await {
+        match make_request(client, url, rand::rng().random::<u32>()).await {
             Ok(response) => {
                 successful += 1;
                 total_bytes += response.body.len();
@@ -182,7 +182,7 @@ async fn test_different_payload_sizes(client: &Arc<HttpClient>) {
 
     for (name, url) in endpoints {
         let start = Instant::now();
-        match make_request(client, url, rand::thread_rng().gen::<u32>()).await {
+        match make_request(client, url, rand::rng().random::<u32>()).await {
             Ok(response) => {
                 println!("{}: {}ms, Size: {} bytes",
                     name, start.elapsed().as_millis(), response.body.len());
@@ -211,6 +211,7 @@ async fn make_request(client: &Arc<HttpClient>, url: &str, random_val: u32) -> R
         auto_referer: true,
         decompress: true,
         http3_only: false,
+        ..Default::default()
     };
 
     client.execute_request(request).await
@@ -0,0 +1,110 @@
+//! Compact binary framing of `HttpResponse`, read from Dart with `ByteData` views
+//! instead of a JSON parse.
+//!
+//! All integers are little-endian and unaligned:
+//!
+//! ```text
+//! u8   format version (1)
+//! u16  status_code
+//! u8   HTTP version: 9 = 0.9, 10 = 1.0, 11 = 1.1, 20 = 2, 30 = 3, 0 = unknown
+//! u8   flags: bit 0 = served_stale, bit 1 = truncated, bit 2 = raw body
+//! u32  elapsed_ms     (saturating)
+//! u32  queue_wait_ms  (saturating)
+//! u32  network_ms     (saturating)
+//! u32  url length, then the URL bytes (UTF-8)
+//! u32  header count, then per header:
+//!        u32 name length, u32 value length, name bytes, value bytes
+//!      (`set-cookie` appears once per cookie, see `HttpResponse::set_cookies`)
+//! u64  body length, then the body bytes, running to the end of the buffer: UTF-8 text,
+//!      or with the raw body flag exactly as received (`execute_request_binary_format_raw`)
+//! ```
+//!
+//! A failed request is instead `ERROR_MARKER` (0xFF) followed by the `HttpError` as JSON
+//! (`code`, `message`, `details`, `metadata`) to the end of the buffer, so check the
+//! first byte before reading a response.
+
+use crate::json;
+use crate::models::{HttpError, HttpResponse};
+
+pub const FORMAT_VERSION: u8 = 1;
+/// First byte of an error reply; never a format version
+pub const ERROR_MARKER: u8 = 0xFF;
+
+const FLAG_SERVED_STALE: u8 = 1 << 0;
+const FLAG_TRUNCATED: u8 = 1 << 1;
+const FLAG_RAW_BODY: u8 = 1 << 2;
+
+pub struct BinaryResponse;
+
+impl BinaryResponse {
+    /// Appends `response` to `out` in the layout above
+    pub fn encode(response: &HttpResponse, out: &mut Vec<u8>) {
+        let body = match &response.raw_body {
+            Some(raw) => raw.as_ref(),
+            None => response.body.as_bytes(),
+        };
+        let headers: Vec<(&str, &str)> = Self::headers(response).collect();
+        let headers_len: usize = headers.iter().map(|(k, v)| 8 + k.len() + v.len()).sum();
+        out.reserve(32 + response.url.len() + headers_len + body.len());
+
+        out.push(FORMAT_VERSION);
+        out.extend_from_slice(&response.status_code.to_le_bytes());
+        out.push(Self::version_code(&response.version));
+
+        let mut flags = 0;
+        if response.served_stale { flags |= FLAG_SERVED_STALE; }
+        if response.truncated { flags |= FLAG_TRUNCATED; }
+        if response.raw_body.is_some() { flags |= FLAG_RAW_BODY; }
+        out.push(flags);
+
+        for millis in [response.elapsed_ms, response.queue_wait_ms, response.network_ms] {
+            out.extend_from_slice(&(millis.min(u32::MAX as u128) as u32).to_le_bytes());
+        }
+
+        Self::put_bytes_u32(out, response.url.as_bytes());
+
+        out.extend_from_slice(&(headers.len() as u32).to_le_bytes());
+        for (name, value) in headers {
+            out.extend_from_slice(&(name.len() as u32).to_le_bytes());
+            out.extend_from_slice(&(value.len() as u32).to_le_bytes());
+            out.extend_from_slice(name.as_bytes());
+            out.extend_from_slice(value.as_bytes());
+        }
+
+        out.extend_from_slice(&(body.len() as u64).to_le_bytes());
+        out.extend_from_slice(body);
+    }
+
+    /// Appends `error` to `out` as an error reply
+    pub fn encode_error(error: &HttpError, out: &mut Vec<u8>) -> anyhow::Result<()> {
+        out.push(ERROR_MARKER);
+        json::to_writer(&mut *out, error)
+    }
+
+    /// `headers`, with its single `set-cookie` entry expanded to every cookie
+    fn headers(response: &HttpResponse) -> impl Iterator<Item = (&str, &str)> {
+        let expand = !response.set_cookies.is_empty();
+        response
+            .headers
+            .iter()
+            .filter(move |(name, _)| !(expand && name.as_str() == "set-cookie"))
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .chain(response.set_cookies.iter().map(|cookie| ("set-cookie", cookie.as_str())))
+    }
+
+    fn put_bytes_u32(out: &mut Vec<u8>, bytes: &[u8]) {
+        out.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+        out.extend_from_slice(bytes);
+    }
+
+    fn version_code(version: &str) -> u8 {
+        match version {
+            "HTTP/0.9" => 9,
+            "HTTP/1.0" => 10,
+            "HTTP/1.1" => 11,
+            "HTTP/2" => 20,
+            "HTTP/3" => 30,
+            _ => 0,
+        }
+    }
+}
@@ -0,0 +1,42 @@
+use bytes::Bytes;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+static HANDLES: Lazy<Mutex<HashMap<u64, Bytes>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Handles start at 1 so 0 never names a body
+static NEXT_HANDLE: AtomicU64 = AtomicU64::new(1);
+
+/// Response bodies kept on the Rust side for `body_as_handle` requests, read by Dart in
+/// slices. A body lives until `free_body_handle` (or `shutdown_http_client`): every handle
+/// returned in `HttpResponse::body_handle` must be freed exactly once, or it leaks.
+pub struct BodyHandles;
+
+impl BodyHandles {
+    pub fn register(body: Bytes) -> u64 {
+        let handle = NEXT_HANDLE.fetch_add(1, Ordering::Relaxed);
+        HANDLES.lock().insert(handle, body);
+        handle
+    }
+
+    /// Copies the bytes at `offset..` into `out`, returning how many were copied (fewer than
+    /// `out.len()` at the end of the body, 0 past it), or `None` for an unknown handle
+    pub fn read(handle: u64, offset: u64, out: &mut [u8]) -> Option<usize> {
+        // Clone out of the lock so the copy does not block other readers
+        let body = HANDLES.lock().get(&handle)?.clone();
+        let start = usize::try_from(offset).unwrap_or(usize::MAX).min(body.len());
+        let len = out.len().min(body.len() - start);
+        out[..len].copy_from_slice(&body[start..start + len]);
+        Some(len)
+    }
+
+    pub fn free(handle: u64) -> bool {
+        HANDLES.lock().remove(&handle).is_some()
+    }
+
+    pub fn free_all() {
+        HANDLES.lock().clear();
+    }
+}
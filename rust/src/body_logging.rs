@@ -0,0 +1,135 @@
+use crate::client_config::{ClientConfig, LoggingSettings};
+use crate::json::{self, OwnedValue};
+use crate::models::HttpResponse;
+
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use regex::{RegexSet, RegexSetBuilder};
+use reqwest::Request;
+use std::fmt::Write;
+
+const MASK: &str = "***";
+const LOG_TARGET: &str = "flutter_rust_http";
+const REGEX_PREFIX: &str = "regex:";
+
+/// `LoggingSettings::redact_header_patterns`, compiled
+static HEADER_PATTERNS: Lazy<RwLock<RegexSet>> = Lazy::new(|| RwLock::new(RegexSet::empty()));
+
+/// Redacted request/response logging through the `log` facade (`debug` level),
+/// active when `ClientSettings::logging.enabled` is set
+pub struct BodyLogger;
+
+impl BodyLogger {
+    /// Returns the settings if logging is on and a `debug` record would be emitted
+    fn active() -> Option<LoggingSettings> {
+        let settings = ClientConfig::settings();
+        (settings.logging.enabled && log::log_enabled!(target: LOG_TARGET, log::Level::Debug))
+            .then(|| settings.logging.clone())
+    }
+
+    /// Compiles and installs the header redaction patterns; on error the previous set stays
+    pub fn set_header_patterns(patterns: &[String]) -> Result<(), regex::Error> {
+        let sources = patterns.iter().map(|pattern| match pattern.strip_prefix(REGEX_PREFIX) {
+            Some(regex) => format!("^(?:{})$", regex),
+            None => format!("^{}$", pattern.split('*').map(regex::escape).collect::<Vec<_>>().join(".*")),
+        });
+        let compiled = RegexSetBuilder::new(sources).case_insensitive(true).build()?;
+        *HEADER_PATTERNS.write() = compiled;
+        Ok(())
+    }
+
+    /// Logs the request as it will be sent; streamed bodies (files, multipart) are not read
+    pub fn log_request(request: &Request) {
+        let Some(settings) = Self::active() else { return };
+        let headers = request.headers().iter().map(|(name, value)| (name.as_str(), value.to_str().unwrap_or("<binary>")));
+
+        let mut line = format!("--> {} {}", request.method(), request.url());
+        Self::write_headers(&mut line, headers, &settings);
+        if let Some(body) = request.body() {
+            match body.as_bytes() {
+                Some(bytes) => Self::write_body(&mut line, bytes, &settings),
+                None => line.push_str("\n<streamed body>"),
+            }
+        }
+        log::debug!(target: LOG_TARGET, "{}", line);
+    }
+
+    pub fn log_response(response: &HttpResponse) {
+        let Some(settings) = Self::active() else { return };
+        let headers = response.headers.iter().map(|(name, value)| (name.as_str(), value.as_str()));
+
+        let mut line = format!("<-- {} {} ({}ms)", response.status_code, response.url, response.elapsed_ms);
+        Self::write_headers(&mut line, headers, &settings);
+        match &response.raw_body {
+            Some(raw) => Self::write_body(&mut line, raw, &settings),
+            None => Self::write_body(&mut line, response.body.as_bytes(), &settings),
+        }
+        log::debug!(target: LOG_TARGET, "{}", line);
+    }
+
+    fn write_headers<'a>(line: &mut String, headers: impl Iterator<Item = (&'a str, &'a str)>, settings: &LoggingSettings) {
+        let patterns = HEADER_PATTERNS.read();
+        for (name, value) in headers {
+            let redacted = settings.redact_headers.iter().any(|h| h.eq_ignore_ascii_case(name)) || patterns.is_match(name);
+            let _ = write!(line, "\n{}: {}", name, if redacted { MASK } else { value });
+        }
+    }
+
+    fn write_body(line: &mut String, body: &[u8], settings: &LoggingSettings) {
+        if body.is_empty() {
+            return;
+        }
+        let text = Self::redact_body(body, &settings.redact_fields);
+        line.push('\n');
+        if text.len() > settings.max_body_bytes {
+            let mut end = settings.max_body_bytes;
+            while !text.is_char_boundary(end) {
+                end -= 1;
+            }
+            let _ = write!(line, "{}... ({} bytes total)", &text[..end], body.len());
+        } else {
+            line.push_str(&text);
+        }
+    }
+
+    /// JSON bodies get `redact_fields` masked; anything else is logged as (lossy) text
+    fn redact_body(body: &[u8], fields: &[String]) -> String {
+        if !fields.is_empty() {
+            let mut scratch = body.to_vec();
+            if let Ok(mut value) = json::to_owned_value(&mut scratch) {
+                for field in fields {
+                    let path: Vec<&str> = field.split('.').collect();
+                    Self::mask(&mut value, &path, path.len() == 1);
+                }
+                return json::to_string(&value).unwrap_or_default();
+            }
+        }
+        String::from_utf8_lossy(body).into_owned()
+    }
+
+    /// Masks `path` below `value`; arrays are walked transparently.
+    /// A single-segment path (`anywhere`) matches that key at any depth.
+    fn mask(value: &mut OwnedValue, path: &[&str], anywhere: bool) {
+        match value {
+            OwnedValue::Object(map) => {
+                for (key, child) in map.iter_mut() {
+                    if key.as_str() == path[0] {
+                        if path.len() == 1 {
+                            *child = OwnedValue::from(MASK);
+                            continue;
+                        }
+                        Self::mask(child, &path[1..], false);
+                    } else if anywhere {
+                        Self::mask(child, path, true);
+                    }
+                }
+            }
+            OwnedValue::Array(items) => {
+                for item in items.iter_mut() {
+                    Self::mask(item, path, anywhere);
+                }
+            }
+            _ => {}
+        }
+    }
+}
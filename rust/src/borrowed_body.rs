@@ -0,0 +1,42 @@
+use bytes::Bytes;
+use crossbeam_channel::{bounded, Receiver, Sender};
+
+/// Caller-owned memory (e.g. an mmap'ed file) exposed as a `Bytes` without copying.
+/// Dropping the last `Bytes` clone signals the paired receiver.
+struct BorrowedRegion {
+    ptr: *const u8,
+    len: usize,
+    released: Sender<()>,
+}
+
+// SAFETY: the region is only read, and the caller keeps it valid and unmodified until
+// `released` fires (see `BorrowedBody::wrap`).
+unsafe impl Send for BorrowedRegion {}
+unsafe impl Sync for BorrowedRegion {}
+
+impl AsRef<[u8]> for BorrowedRegion {
+    fn as_ref(&self) -> &[u8] {
+        // SAFETY: see `BorrowedBody::wrap`
+        unsafe { std::slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+impl Drop for BorrowedRegion {
+    fn drop(&mut self) {
+        let _ = self.released.try_send(());
+    }
+}
+
+pub struct BorrowedBody;
+
+impl BorrowedBody {
+    /// Wraps `ptr..ptr+len` as a zero-copy request body. The receiver gets a message once
+    /// the body has been dropped everywhere (sent, failed or abandoned).
+    ///
+    /// # Safety
+    /// The memory must stay valid and unmodified until the receiver has fired.
+    pub unsafe fn wrap(ptr: *const u8, len: usize) -> (Bytes, Receiver<()>) {
+        let (released, on_release) = bounded(1);
+        (Bytes::from_owner(BorrowedRegion { ptr, len, released }), on_release)
+    }
+}
@@ -0,0 +1,59 @@
+use serde::Serialize;
+
+/// Compile-time facts about this build, for bug reports (see the `build_info` FFI)
+#[derive(Debug, Serialize)]
+pub struct BuildInfo {
+    pub version: &'static str,
+    pub debug_build: bool,
+    pub target_os: &'static str,
+    pub target_arch: &'static str,
+    /// Versions resolved in Cargo.lock at build time (`unknown` when built without one)
+    pub reqwest_version: &'static str,
+    pub rustls_version: &'static str,
+    pub features: Features,
+    pub runtime_worker_threads: usize,
+    pub runtime_max_blocking_threads: usize,
+}
+
+/// Optional capabilities. HTTP/3 and certificate-verification bypass (`set_accept_invalid_certs`)
+/// follow the `http3` and `dangerous-tls` cargo features.
+#[derive(Debug, Serialize)]
+pub struct Features {
+    pub http2: bool,
+    pub http3: bool,
+    pub gzip: bool,
+    pub brotli: bool,
+    pub zstd: bool,
+    pub deflate: bool,
+    pub socks: bool,
+    pub dangerous_tls: bool,
+    pub debug_logging: bool,
+    pub raw_socket: bool,
+}
+
+impl BuildInfo {
+    pub fn current(runtime_worker_threads: usize, runtime_max_blocking_threads: usize) -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            debug_build: cfg!(debug_assertions),
+            target_os: std::env::consts::OS,
+            target_arch: std::env::consts::ARCH,
+            reqwest_version: env!("REQWEST_VERSION"),
+            rustls_version: env!("RUSTLS_VERSION"),
+            features: Features {
+                http2: true,
+                http3: cfg!(feature = "http3"),
+                gzip: true,
+                brotli: cfg!(feature = "brotli"),
+                zstd: cfg!(feature = "zstd"),
+                deflate: true,
+                socks: true,
+                dangerous_tls: cfg!(feature = "dangerous-tls"),
+                debug_logging: cfg!(feature = "debug-logging"),
+                raw_socket: cfg!(feature = "raw-socket"),
+            },
+            runtime_worker_threads,
+            runtime_max_blocking_threads,
+        }
+    }
+}
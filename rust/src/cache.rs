@@ -0,0 +1,239 @@
+use crate::models::HttpResponse;
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION, IF_MODIFIED_SINCE, IF_NONE_MATCH};
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::time::{Duration, Instant};
+
+/// Max cached GET responses; the least recently stored entry is evicted first
+const MAX_ENTRIES: usize = 256;
+
+/// How a GET request uses the response cache. In both modes an entry still fresh per
+/// `Cache-Control: max-age` (minus `Age`) is served without contacting the server;
+/// the mode only governs stale entries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CacheMode {
+    /// Revalidate with `If-None-Match` / `If-Modified-Since`; a 304 serves the cached body
+    Etag,
+    /// Serve the cached body immediately and revalidate it in the background
+    StaleWhileRevalidate,
+}
+
+/// Where the bytes of a response came from, reported as `HttpResponse::source`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResponseSource {
+    /// Fetched from the server (also the case for requests that bypass the cache)
+    #[default]
+    Network,
+    /// Cached body confirmed by a `304 Not Modified`
+    NetworkRevalidated,
+    /// Cached entry still fresh per `Cache-Control`, no request sent
+    CacheFresh,
+    /// Stale entry served under `stale_while_revalidate`
+    CacheStale,
+}
+
+impl ResponseSource {
+    pub fn from_cache(self) -> bool {
+        self != Self::Network
+    }
+}
+
+struct CacheEntry {
+    response: HttpResponse,
+    stored_at: Instant,
+    /// `max-age` minus `Age` at store time; zero means revalidate on every use
+    freshness: Duration,
+    revalidating: bool,
+}
+
+/// The `Cache-Control` / `Age` response directives the cache honors
+#[derive(Debug, Default, Clone, Copy)]
+struct CacheControl {
+    no_store: bool,
+    private: bool,
+    no_cache: bool,
+    max_age: Option<u64>,
+    age: u64,
+}
+
+impl CacheControl {
+    fn parse(headers: &HashMap<String, String>) -> Self {
+        let mut control = Self {
+            age: headers.get("age").and_then(|age| age.trim().parse().ok()).unwrap_or(0),
+            ..Self::default()
+        };
+        for directive in headers.get("cache-control").map(String::as_str).unwrap_or_default().split(',') {
+            let directive = directive.trim();
+            let (name, value) = directive.split_once('=').unwrap_or((directive, ""));
+            match name.trim().to_ascii_lowercase().as_str() {
+                "no-store" => control.no_store = true,
+                "private" => control.private = true,
+                "no-cache" => control.no_cache = true,
+                "max-age" => control.max_age = value.trim().trim_matches('"').parse().ok(),
+                _ => {}
+            }
+        }
+        control
+    }
+
+    /// How long the response may be served without revalidation
+    fn freshness(&self) -> Duration {
+        match self.max_age {
+            Some(max_age) if !self.no_cache => Duration::from_secs(max_age.saturating_sub(self.age)),
+            _ => Duration::ZERO,
+        }
+    }
+}
+
+static CACHE: Lazy<Mutex<HashMap<String, CacheEntry>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Request headers each URL's last stored response named in `Vary`, lowercased and sorted
+static VARY: Lazy<Mutex<HashMap<String, Vec<String>>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Entries are keyed by URL plus the request's `Authorization` and the request headers the
+/// URL's responses vary on, so a response is only served to requests it was made for.
+pub struct ResponseCache;
+
+impl ResponseCache {
+    /// Key of the entry `url` (query included) would have for a request with `headers`
+    pub fn key(url: &str, headers: &HeaderMap) -> String {
+        let vary = VARY.lock().get(url).cloned().unwrap_or_default();
+        Self::variant_key(url, &vary, headers)
+    }
+
+    /// `url` and a digest of the varying request header values, so tokens stay out of keys
+    fn variant_key(url: &str, vary: &[String], headers: &HeaderMap) -> String {
+        let mut hasher = DefaultHasher::new();
+        for name in std::iter::once(AUTHORIZATION.as_str()).chain(vary.iter().map(String::as_str)) {
+            name.hash(&mut hasher);
+            for value in headers.get_all(name) {
+                value.as_bytes().hash(&mut hasher);
+            }
+            0xffu8.hash(&mut hasher);
+        }
+        format!("{} {:016x}", url, hasher.finish())
+    }
+
+    /// Header names of a `Vary` value; `None` for `Vary: *`, which matches no later request
+    fn vary(response: &HttpResponse) -> Option<Vec<String>> {
+        let mut names = Vec::new();
+        for name in response.headers.get("vary").map(String::as_str).unwrap_or_default().split(',') {
+            let name = name.trim().to_ascii_lowercase();
+            match name.as_str() {
+                "*" => return None,
+                "" | "authorization" => {}
+                _ => names.push(name),
+            }
+        }
+        names.sort_unstable();
+        names.dedup();
+        Some(names)
+    }
+
+    /// Cached response for `key`, if any, and whether it is still fresh (`max-age` not
+    /// yet reached and no `no-cache`), i.e. can be served without asking the server
+    pub fn get(key: &str) -> Option<(HttpResponse, bool)> {
+        CACHE.lock().get(key).map(|entry| (entry.response.clone(), entry.stored_at.elapsed() < entry.freshness))
+    }
+
+    /// Stores a 200 response to a request for `url` with `request_headers` that carries a
+    /// validator or a positive `max-age`. `Cache-Control: no-store` or `private` and
+    /// `Vary: *` responses are never stored and evict the request's earlier entry.
+    pub fn store(url: &str, request_headers: &HeaderMap, response: &HttpResponse) {
+        let control = CacheControl::parse(&response.headers);
+        let Some(vary) = Self::vary(response).filter(|_| !control.no_store && !control.private) else {
+            CACHE.lock().remove(&Self::key(url, request_headers));
+            return;
+        };
+        let freshness = control.freshness();
+        if response.status_code != 200 || response.raw_body.is_some() || (Self::validators(response).is_empty() && freshness.is_zero()) {
+            return;
+        }
+
+        let key = &Self::variant_key(url, &vary, request_headers);
+        {
+            // Forgetting a URL's `Vary` only turns later lookups into misses
+            let mut vary_by_url = VARY.lock();
+            if vary_by_url.len() >= MAX_ENTRIES && !vary_by_url.contains_key(url) {
+                vary_by_url.clear();
+            }
+            vary_by_url.insert(url.to_owned(), vary);
+        }
+
+        let mut cache = CACHE.lock();
+        if cache.len() >= MAX_ENTRIES && !cache.contains_key(key) {
+            let oldest = cache.iter().min_by_key(|(_, entry)| entry.stored_at).map(|(k, _)| k.clone());
+            if let Some(oldest) = oldest {
+                cache.remove(&oldest);
+            }
+        }
+        cache.insert(key.to_owned(), CacheEntry {
+            response: response.clone(),
+            stored_at: Instant::now(),
+            freshness,
+            revalidating: false,
+        });
+    }
+
+    /// Marks an entry as revalidated after a 304, taking the new freshness from the 304's
+    /// `Cache-Control` / `Age` when it sends them
+    pub fn touch(key: &str, not_modified: &HttpResponse) {
+        if let Some(entry) = CACHE.lock().get_mut(key) {
+            entry.stored_at = Instant::now();
+            entry.revalidating = false;
+            if not_modified.headers.contains_key("cache-control") {
+                entry.freshness = CacheControl::parse(&not_modified.headers).freshness();
+            }
+        }
+    }
+
+    /// Claims the background revalidation slot; false if one is already running
+    pub fn begin_revalidation(key: &str) -> bool {
+        match CACHE.lock().get_mut(key) {
+            Some(entry) if !entry.revalidating => {
+                entry.revalidating = true;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Releases the revalidation slot without touching the entry (e.g. network error)
+    pub fn end_revalidation(key: &str) {
+        if let Some(entry) = CACHE.lock().get_mut(key) {
+            entry.revalidating = false;
+        }
+    }
+
+    pub fn clear() {
+        CACHE.lock().clear();
+        VARY.lock().clear();
+    }
+
+    /// Adds `If-None-Match` / `If-Modified-Since` built from the cached response
+    pub fn apply_validators(headers: &mut HeaderMap, cached: &HttpResponse) {
+        for (name, value) in Self::validators(cached) {
+            if let Ok(value) = HeaderValue::from_str(value) {
+                headers.insert(name, value);
+            }
+        }
+    }
+
+    fn validators(response: &HttpResponse) -> Vec<(reqwest::header::HeaderName, &str)> {
+        let mut validators = Vec::with_capacity(2);
+        if let Some(etag) = response.headers.get("etag") {
+            validators.push((IF_NONE_MATCH, etag.as_str()));
+        }
+        if let Some(last_modified) = response.headers.get("last-modified") {
+            validators.push((IF_MODIFIED_SINCE, last_modified.as_str()));
+        }
+        validators
+    }
+}
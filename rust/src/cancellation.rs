@@ -0,0 +1,189 @@
+use crate::models::HttpError;
+
+use futures::future::{AbortHandle, AbortRegistration, Abortable, Aborted};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+struct Registry {
+    handles: HashMap<u64, AbortHandle>,
+    /// Registrations of submitted handles whose request has not started yet
+    pending: HashMap<u64, AbortRegistration>,
+    groups: HashMap<String, Vec<u64>>,
+    /// Error code a cancelled request fails with, when not `CANCELLED`
+    reasons: HashMap<u64, &'static str>,
+}
+
+static REGISTRY: Lazy<Mutex<Registry>> = Lazy::new(|| {
+    Mutex::new(Registry {
+        handles: HashMap::new(),
+        pending: HashMap::new(),
+        groups: HashMap::new(),
+        reasons: HashMap::new(),
+    })
+});
+
+static NEXT_TOKEN: AtomicU64 = AtomicU64::new(1);
+
+/// Abort handles of every in-flight request, plus `group -> tokens`
+pub struct Cancellation;
+
+impl Cancellation {
+    /// Registers a request at submission, so `cancel` works while it is still queued. Pass
+    /// `Ticket::handle` as its `cancel_handle` and hold the ticket until the request is done.
+    pub fn submit() -> Ticket {
+        let token = NEXT_TOKEN.fetch_add(1, Ordering::Relaxed);
+        let (handle, registration) = AbortHandle::new_pair();
+        let mut registry = REGISTRY.lock();
+        registry.handles.insert(token, handle);
+        registry.pending.insert(token, registration);
+        Ticket { token }
+    }
+
+    /// Runs `future` under an abort handle (the submitted `cancel_handle`, else a fresh
+    /// one), tagged with `group` if given. A cancelled future resolves to a `CANCELLED`
+    /// error; the entry is removed either way.
+    pub async fn run<F, T>(group: Option<&str>, cancel_handle: Option<u64>, future: F) -> anyhow::Result<T>
+    where
+        F: Future<Output = anyhow::Result<T>>,
+    {
+        let (token, registration) = {
+            let mut registry = REGISTRY.lock();
+            let submitted = cancel_handle.and_then(|token| registry.pending.remove(&token).map(|registration| (token, registration)));
+            let (token, registration) = submitted.unwrap_or_else(|| {
+                let token = NEXT_TOKEN.fetch_add(1, Ordering::Relaxed);
+                let (handle, registration) = AbortHandle::new_pair();
+                registry.handles.insert(token, handle);
+                (token, registration)
+            });
+            // A submitted request cancelled while queued has no handle left to tag
+            if let Some(group) = group.filter(|_| registry.handles.contains_key(&token)) {
+                registry.groups.entry(group.to_owned()).or_default().push(token);
+            }
+            (token, registration)
+        };
+        let _entry = Entry { token, group };
+
+        match Abortable::new(future, registration).await {
+            Ok(result) => result,
+            Err(Aborted) => {
+                let code = REGISTRY.lock().reasons.remove(&token).unwrap_or("CANCELLED");
+                Err(HttpError::new(code, "Request was cancelled").into())
+            }
+        }
+    }
+
+    /// Cancels every in-flight request; they fail with `code`. Returns how many were cancelled.
+    pub fn cancel_all(code: &'static str) -> usize {
+        let mut registry = REGISTRY.lock();
+        registry.groups.clear();
+        let handles: Vec<(u64, AbortHandle)> = registry.handles.drain().collect();
+        for (token, handle) in &handles {
+            registry.reasons.insert(*token, code);
+            handle.abort();
+        }
+        handles.len()
+    }
+
+    /// Cancels the request submitted with `handle`, or makes it fail with `CANCELLED` as soon
+    /// as it starts if it is still queued. False if `handle` is unknown or its request is done.
+    pub fn cancel(handle: u64) -> bool {
+        REGISTRY.lock().handles.remove(&handle).map(|abort| abort.abort()).is_some()
+    }
+
+    /// Cancels every in-flight request tagged with `group`; returns how many were cancelled
+    pub fn cancel_group(group: &str) -> usize {
+        let mut registry = REGISTRY.lock();
+        let Some(tokens) = registry.groups.remove(group) else { return 0 };
+        tokens
+            .iter()
+            .filter_map(|token| registry.handles.remove(token))
+            .map(|handle| handle.abort())
+            .count()
+    }
+}
+
+/// Drops the registry entry when the request finishes, is cancelled or is dropped
+struct Entry<'a> {
+    token: u64,
+    group: Option<&'a str>,
+}
+
+impl Drop for Entry<'_> {
+    fn drop(&mut self) {
+        let mut registry = REGISTRY.lock();
+        registry.handles.remove(&self.token);
+        registry.reasons.remove(&self.token);
+        if let Some(group) = self.group {
+            if let Some(tokens) = registry.groups.get_mut(group) {
+                tokens.retain(|&token| token != self.token);
+                if tokens.is_empty() {
+                    registry.groups.remove(group);
+                }
+            }
+        }
+    }
+}
+
+/// A submitted request's handle; dropping it forgets the handle, also for requests that
+/// never reached `Cancellation::run` (e.g. their JSON did not parse)
+pub struct Ticket {
+    token: u64,
+}
+
+impl Ticket {
+    pub fn handle(&self) -> u64 {
+        self.token
+    }
+}
+
+impl Drop for Ticket {
+    fn drop(&mut self) {
+        let mut registry = REGISTRY.lock();
+        registry.handles.remove(&self.token);
+        registry.pending.remove(&self.token);
+        registry.reasons.remove(&self.token);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registered(token: u64) -> bool {
+        let registry = REGISTRY.lock();
+        registry.handles.contains_key(&token) || registry.pending.contains_key(&token) || registry.reasons.contains_key(&token)
+    }
+
+    fn code(result: anyhow::Result<()>) -> String {
+        HttpError::code_of(&result.expect_err("request should be cancelled")).to_owned()
+    }
+
+    #[tokio::test]
+    async fn cancel_before_start_fails_the_request() {
+        let ticket = Cancellation::submit();
+        assert!(Cancellation::cancel(ticket.handle()));
+        let result = Cancellation::run(Some("search"), Some(ticket.handle()), async { Ok(()) }).await;
+        assert_eq!(code(result), "CANCELLED");
+        assert!(!REGISTRY.lock().groups.contains_key("search"));
+    }
+
+    #[tokio::test]
+    async fn finished_request_leaves_no_entry() {
+        let ticket = Cancellation::submit();
+        let token = ticket.handle();
+        Cancellation::run(None, Some(token), async { Ok(()) }).await.unwrap();
+        drop(ticket);
+        assert!(!registered(token));
+        assert!(!Cancellation::cancel(token));
+    }
+
+    #[test]
+    fn dropped_ticket_leaves_no_entry() {
+        let token = Cancellation::submit().handle();
+        assert!(!registered(token));
+        assert!(!Cancellation::cancel(token));
+    }
+}
@@ -1,54 +1,580 @@
-use reqwest::Client;
+use crate::connect_timeout::ConnectTimeout;
+use crate::connection_events::ConnectionEvents;
+use crate::cookie_jar::CookieJar;
+use crate::dns::DnsResolver;
+use crate::models::HttpError;
+use crate::redirects::Redirects;
+
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use parking_lot::{RwLock, RwLockReadGuard};
+use reqwest::{Client, ClientBuilder};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::CertificateDer;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
 use std::time::Duration;
 
+/// App-wide settings, replaced as a whole by the `configure_http_client` FFI call
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClientSettings {
+    /// Content-Type sent with a body or body file when the request sets none (e.g.
+    /// `application/json`); `None`, the default, sends none
+    pub default_content_type: Option<String>,
+    /// `Accept-Language` sent when the request sets neither the header nor `accept_language`
+    pub accept_language: Option<String>,
+    /// Minimum gap between two upload progress events for the same request
+    pub progress_interval_ms: u64,
+    /// `TCP_USER_TIMEOUT` for every new socket (Linux/Android only, `None` keeps the OS default).
+    /// Applied when a client is built, so set it before `init_http_client`.
+    /// `SO_RCVBUF`/`SO_SNDBUF` are not configurable: reqwest dials the socket itself and a
+    /// connector layer can only wrap its connection, after the handshake has sized the window.
+    pub tcp_user_timeout_ms: Option<u64>,
+    /// How long successful DNS lookups are reused (0 disables the cache, the default)
+    pub dns_cache_ttl_ms: u64,
+    /// Upper bound for one system lookup (0 = unbounded, the default). Expiry fails the
+    /// request with `DNS_TIMEOUT`; the blocked getaddrinfo thread is left to finish on its own.
+    pub dns_timeout_ms: u64,
+    /// Connect timeout (DNS + TCP + TLS) by lowercase host, `*.example.com` covering its
+    /// subdomains; 0 means unbounded. Used when the request's `connect_timeout_ms` is 0,
+    /// falling back to the client's own (5 s shared, 8 s isolated). Redirects to another
+    /// host use that host's entry. Expiry fails the request with `CONNECT_TIMEOUT`.
+    pub connect_timeouts_by_host: HashMap<String, u64>,
+    /// Address family tried first on dual-stack hosts (`None` keeps the resolver's order).
+    /// The connector races the other family after hyper-util's fixed 300 ms Happy Eyeballs
+    /// delay; `HttpResponse::connected_family` reports the winner.
+    pub preferred_family: Option<AddressFamily>,
+    /// Happy Eyeballs (RFC 8305) stickiness: when set, the family each host last connected
+    /// over is tried first for 10 minutes, ahead of `preferred_family`. The stagger before the
+    /// second family starts stays hyper-util's 300 ms, as reqwest builds the `HttpConnector`
+    /// itself and does not expose its `set_happy_eyeballs_timeout`, so the value is not
+    /// applied. Connect times per family are in `metrics_prometheus`.
+    pub happy_eyeballs_delay_ms: Option<u64>,
+    /// Upper bound for the whole of a `background` request (0 = none beyond `total_timeout_ms`).
+    /// The default fits inside iOS's ~30 s background execution window.
+    pub background_deadline_ms: u64,
+    /// Probed by `check_connectivity`; must answer `204 No Content` when nothing intercepts it.
+    /// Plain `http://` on purpose, since portals can only rewrite unencrypted traffic.
+    pub connectivity_check_url: String,
+    /// Posts connection `opened` / `failed` events under this id (`None` = off, the default),
+    /// see `ConnectionEvents`. Read per connection, so it can be toggled at any time.
+    pub connection_events_id: Option<i64>,
+    /// Pre-touch pooled response buffers to this size at init (0 = off); buffers up to
+    /// this capacity are also kept pooled instead of the default 8 KiB limit
+    pub pretouch_buffer_bytes: usize,
+    /// Largest decoded response header block accepted over HTTP/2 (`None` keeps hyper's
+    /// 16 KiB default); bigger ones fail with `HEADERS_TOO_LARGE`. reqwest exposes no such
+    /// knob for HTTP/1.1, where hyper's fixed limits (100 headers, ~400 KiB head) apply and
+    /// also fail with `HEADERS_TOO_LARGE`.
+    pub max_response_header_bytes: Option<u32>,
+    /// Most response header fields accepted (`None` = no limit beyond hyper's); checked
+    /// once the head is parsed, failing with `HEADERS_TOO_LARGE`
+    pub max_response_headers: Option<usize>,
+    /// Cap on response body bytes buffered in memory across all in-flight requests
+    /// (0 = unlimited, the default), see `MemoryBudget`
+    pub memory_budget_bytes: u64,
+    /// How long a body waits for budget to free up before failing with
+    /// `MEMORY_BUDGET_EXCEEDED` (0 = fail at once)
+    pub memory_budget_wait_ms: u64,
+    /// Most recent requests kept for `export_trace`, the oldest dropped once full
+    /// (0 = tracing off, the default)
+    pub trace_buffer_requests: usize,
+    /// ALPN protocols offered in the TLS handshake, in preference order (`None` lets reqwest
+    /// offer `h2` and `http/1.1`). Tokens must be 1-255 bytes. Without `h2` in the list
+    /// requests are no longer forced onto HTTP/2. Applied when a client is built.
+    pub alpn_protocols: Option<Vec<String>>,
+    /// HTTP/2 tuning for new clients (`None` keeps hyper's defaults), see `Http2Settings`
+    pub http2: Http2Settings,
+    /// Redacted request/response logging (needs the `debug-logging` feature)
+    pub logging: LoggingSettings,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AddressFamily {
+    Ipv4,
+    Ipv6,
+}
+
+impl AddressFamily {
+    pub fn of(addr: &SocketAddr) -> Self {
+        if addr.is_ipv4() { AddressFamily::Ipv4 } else { AddressFamily::Ipv6 }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AddressFamily::Ipv4 => "ipv4",
+            AddressFamily::Ipv6 => "ipv6",
+        }
+    }
+}
+
+/// Body/header logging, emitted at `debug` level under the `flutter_rust_http` target
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LoggingSettings {
+    pub enabled: bool,
+    /// Header names (case-insensitive) whose values are masked
+    pub redact_headers: Vec<String>,
+    /// Header name patterns whose values are masked, matched case-insensitively against the
+    /// whole name: globs where `*` matches any run of characters (`x-*-token`), or regexes
+    /// prefixed with `regex:`. Compiled once by `configure_http_client`, which rejects bad ones.
+    pub redact_header_patterns: Vec<String>,
+    /// JSON body fields to mask: `user.password` is anchored at the root, while a bare
+    /// `password` matches that key at any depth. Arrays are walked transparently.
+    pub redact_fields: Vec<String>,
+    /// Logged bodies are cut after this many bytes (after redaction)
+    pub max_body_bytes: usize,
+}
+
+impl Default for LoggingSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            redact_headers: ["authorization", "proxy-authorization", "cookie", "set-cookie"]
+                .map(String::from)
+                .to_vec(),
+            redact_header_patterns: Vec::new(),
+            redact_fields: vec!["password".to_owned()],
+            max_body_bytes: 4096,
+        }
+    }
+}
+
+/// HTTP/2 flow-control knobs sent in our initial SETTINGS, and how many connections a host
+/// gets once its streams run out.
+///
+/// The server's `SETTINGS_MAX_CONCURRENT_STREAMS` caps each connection and the pool keeps
+/// a single HTTP/2 connection per origin, queueing requests on it once the cap is hit; our
+/// own SETTINGS value would only limit server push, which is off. `max_concurrent_streams`
+/// with `max_connections_per_host` spreads requests over more connections instead (see
+/// `StreamLanes`). Chatty apps gain the most from larger windows here.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Http2Settings {
+    /// `SETTINGS_INITIAL_WINDOW_SIZE` per stream
+    pub initial_stream_window_size: Option<u32>,
+    /// Connection-level receive window
+    pub initial_connection_window_size: Option<u32>,
+    /// Grow windows from measured BDP (overrides both window sizes)
+    pub adaptive_window: bool,
+    /// `SETTINGS_MAX_FRAME_SIZE` (16 KiB - 16 MiB)
+    pub max_frame_size: Option<u32>,
+    /// Requests in flight on one of a host's HTTP/2 connections before the next one goes
+    /// out on another connection; keep it at or below the server's limit. Applies to
+    /// requests that force HTTP/2 (the default) on the `HttpClient`'s own client.
+    pub max_concurrent_streams: Option<u32>,
+    /// Connections per host that `max_concurrent_streams` spreads requests over (0 or 1
+    /// keeps one, the default). Read per request. Each extra connection comes from a pool
+    /// of its own, so `pool_max_idle_per_host` applies to each pool separately.
+    pub max_connections_per_host: u32,
+}
+
+impl Default for ClientSettings {
+    fn default() -> Self {
+        Self {
+            default_content_type: None,
+            accept_language: None,
+            progress_interval_ms: 100,
+            tcp_user_timeout_ms: None,
+            dns_cache_ttl_ms: 0,
+            dns_timeout_ms: 0,
+            connect_timeouts_by_host: HashMap::new(),
+            preferred_family: None,
+            happy_eyeballs_delay_ms: None,
+            background_deadline_ms: 25_000,
+            connectivity_check_url: "http://connectivitycheck.gstatic.com/generate_204".to_owned(),
+            connection_events_id: None,
+            pretouch_buffer_bytes: 0,
+            max_response_header_bytes: None,
+            max_response_headers: None,
+            memory_budget_bytes: 0,
+            memory_budget_wait_ms: 0,
+            trace_buffer_requests: 0,
+            alpn_protocols: None,
+            http2: Http2Settings::default(),
+            logging: LoggingSettings::default(),
+        }
+    }
+}
+
+static SETTINGS: Lazy<RwLock<ClientSettings>> = Lazy::new(|| RwLock::new(ClientSettings::default()));
+
+/// Trust added at runtime (see `ClientConfig::add_root_certificates`), read whenever a client is built
+#[derive(Default)]
+struct TlsTrust {
+    roots: Vec<CertificateDer<'static>>,
+    accept_invalid_certs: bool,
+}
+
+static TLS_TRUST: Lazy<RwLock<TlsTrust>> = Lazy::new(|| RwLock::new(TlsTrust::default()));
+
+/// Settings in effect when the shared client currently in use was built
+static SHARED_CLIENT_SETTINGS: Lazy<RwLock<Option<ClientSettings>>> = Lazy::new(|| RwLock::new(None));
+
+/// Fixed options of the shared client, also reported by `effective_config::SharedClientConfig`
+pub const SHARED_POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(600);
+pub const SHARED_POOL_MAX_IDLE_PER_HOST: usize = 100;
+pub const SHARED_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+pub const SHARED_TIMEOUT: Duration = Duration::from_secs(15);
+pub const SHARED_MAX_REDIRECTS: usize = 5;
+/// Keepalives of the shared and mobile clients, off while paused
+pub const TCP_KEEPALIVE: Duration = Duration::from_secs(15);
+pub const HTTP2_KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(10);
+pub const HTTP2_KEEP_ALIVE_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Builds the reqwest clients used by the crate.
+///
+/// Connections are pooled per (scheme, host:port) by hyper-util, so HTTP/2 connections
+/// are never coalesced across hostnames, even when the hosts share an IP and a
+/// certificate: each origin always gets its own connection.
 pub struct ClientConfig;
 
 impl ClientConfig {
+    /// Current app-wide settings (cheap read lock, do not hold across awaits)
+    pub fn settings() -> RwLockReadGuard<'static, ClientSettings> {
+        SETTINGS.read()
+    }
+
+    /// Replaces the app-wide settings
+    pub fn set_settings(settings: ClientSettings) {
+        *SETTINGS.write() = settings;
+    }
+
+    /// Settings the shared client was built with (`None` until it is first built); client-level
+    /// knobs changed since then only apply once it is rebuilt
+    pub fn shared_client_settings() -> Option<ClientSettings> {
+        SHARED_CLIENT_SETTINGS.read().clone()
+    }
+
+    /// Resolver, HTTP/2 and socket-level options from `ClientSettings`, left at defaults unless opted in
+    fn apply_socket_options(builder: ClientBuilder) -> ClientBuilder {
+        let builder = Self::apply_http2_options(builder.dns_resolver(Arc::new(DnsResolver)));
+        #[cfg(any(target_os = "android", target_os = "linux"))]
+        if let Some(timeout_ms) = Self::settings().tcp_user_timeout_ms {
+            return builder.tcp_user_timeout(Duration::from_millis(timeout_ms));
+        }
+        builder
+    }
+
+    fn apply_http2_options(mut builder: ClientBuilder) -> ClientBuilder {
+        let http2 = Self::settings().http2.clone();
+        if http2.adaptive_window {
+            builder = builder.http2_adaptive_window(true);
+        }
+        if let Some(size) = http2.initial_stream_window_size {
+            builder = builder.http2_initial_stream_window_size(size);
+        }
+        if let Some(size) = http2.initial_connection_window_size {
+            builder = builder.http2_initial_connection_window_size(size);
+        }
+        if let Some(size) = http2.max_frame_size {
+            builder = builder.http2_max_frame_size(size);
+        }
+        if let Some(size) = Self::settings().max_response_header_bytes {
+            builder = builder.http2_max_header_list_size(size);
+        }
+        builder
+    }
+
+    /// An ALPN list is non-empty and every token is 1-255 bytes (RFC 7301)
+    pub fn is_valid_alpn(protocols: &[String]) -> bool {
+        !protocols.is_empty() && protocols.iter().all(|protocol| (1..=255).contains(&protocol.len()))
+    }
+
+    /// Whether clients offer `h2`, i.e. requests may be forced onto HTTP/2
+    pub fn offers_h2() -> bool {
+        Self::settings().alpn_protocols.as_ref().is_none_or(|protocols| protocols.iter().any(|protocol| protocol == "h2"))
+    }
+
+    /// Trusts the certificates in `bytes`, a PEM bundle or a single DER certificate, on top of
+    /// the webpki roots. Only clients built afterwards see them (see
+    /// `HttpClient::rebuild_clients`). Returns how many were added; `INVALID_CERTIFICATE` if
+    /// there is no certificate or one is not a usable trust anchor, in which case none is added.
+    pub fn add_root_certificates(bytes: &[u8]) -> Result<usize> {
+        let invalid = |reason: String| HttpError::new("INVALID_CERTIFICATE", format!("Root certificate rejected: {}", reason));
+        let certs: Vec<CertificateDer<'static>> = if bytes.trim_ascii_start().starts_with(b"-----BEGIN") {
+            CertificateDer::pem_slice_iter(bytes).collect::<Result<_, _>>().map_err(|e| invalid(e.to_string()))?
+        } else {
+            vec![CertificateDer::from(bytes.to_vec())]
+        };
+        if certs.is_empty() {
+            return Err(invalid("no CERTIFICATE block in the PEM".to_owned()).into());
+        }
+        let mut scratch = rustls::RootCertStore::empty();
+        for cert in &certs {
+            scratch.add(cert.clone()).map_err(|e| invalid(e.to_string()))?;
+        }
+        let added = certs.len();
+        TLS_TRUST.write().roots.extend(certs);
+        Ok(added)
+    }
+
+    /// Drops every certificate added by `add_root_certificates`, for clients built afterwards
+    pub fn clear_root_certificates() {
+        TLS_TRUST.write().roots.clear();
+    }
+
+    pub fn root_certificate_count() -> usize {
+        TLS_TRUST.read().roots.len()
+    }
+
+    /// Skips certificate and hostname verification for clients built afterwards. For staging
+    /// servers with self-signed certificates only; compiled in with the `dangerous-tls` feature.
+    #[cfg(feature = "dangerous-tls")]
+    pub fn set_accept_invalid_certs(accept: bool) {
+        TLS_TRUST.write().accept_invalid_certs = accept;
+    }
+
+    pub fn accepts_invalid_certs() -> bool {
+        TLS_TRUST.read().accept_invalid_certs
+    }
+
+    /// rustls with TLS 1.2+, webpki roots and any added roots; with `alpn_protocols` set the
+    /// config is built here, since reqwest only lets a preconfigured config carry its own ALPN list
+    fn apply_tls(builder: ClientBuilder) -> ClientBuilder {
+        let trust = TLS_TRUST.read();
+        let Some(protocols) = Self::settings().alpn_protocols.clone() else {
+            let mut builder = builder.use_rustls_tls().min_tls_version(reqwest::tls::Version::TLS_1_2);
+            for root in &trust.roots {
+                if let Ok(cert) = reqwest::Certificate::from_der(root) {
+                    builder = builder.add_root_certificate(cert);
+                }
+            }
+            return builder.danger_accept_invalid_certs(trust.accept_invalid_certs);
+        };
+        let mut roots = rustls::RootCertStore { roots: webpki_roots::TLS_SERVER_ROOTS.to_vec() };
+        // Already checked by `add_root_certificates`
+        roots.add_parsable_certificates(trust.roots.iter().cloned());
+        let mut tls = rustls::ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_protocol_versions(&[&rustls::version::TLS13, &rustls::version::TLS12])
+            .expect("ring supports TLS 1.2 and 1.3")
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        tls.alpn_protocols = protocols.into_iter().map(String::into_bytes).collect();
+        #[cfg(feature = "dangerous-tls")]
+        if trust.accept_invalid_certs {
+            tls.dangerous().set_certificate_verifier(Arc::new(AcceptAnyCertificate));
+        }
+        builder.use_preconfigured_tls(tls)
+    }
+
+    /// Content codings this build can decode, in the order they are advertised
+    pub fn supported_encodings() -> &'static [&'static str] {
+        &[
+            "gzip",
+            #[cfg(feature = "brotli")]
+            "br",
+            #[cfg(feature = "zstd")]
+            "zstd",
+            "deflate",
+        ]
+    }
+
+    /// Turns on every compiled-in decoder, so reqwest's `Accept-Encoding` lists exactly
+    /// `supported_encodings` and a server is never invited to send one we cannot decode
+    fn apply_decoders(builder: ClientBuilder) -> ClientBuilder {
+        let builder = builder.gzip(true).deflate(true);
+        #[cfg(feature = "brotli")]
+        let builder = builder.brotli(true);
+        #[cfg(feature = "zstd")]
+        let builder = builder.zstd(true);
+        builder
+    }
+
     /// Mobile client for isolated use
     pub fn build_mobile_client() -> Client {
-        Client::builder()
+        Self::mobile_client_builder()
+            .build()
+            .expect("Failed to build mobile client")
+    }
+
+    /// One-off mobile client that connects to `addr` for `host` while keeping `host` for
+    /// SNI and `Host`. It has its own pool, so the first request pays a fresh handshake.
+    pub fn build_pinned_client(host: &str, addr: SocketAddr, passthrough: bool, title_case: bool) -> Result<Client> {
+        let mut builder = Self::with_cookies(Self::mobile_client_builder()).resolve(host, addr);
+        if passthrough {
+            builder = Self::without_decoding(builder);
+        }
+        if title_case {
+            builder = builder.http1_title_case_headers();
+        }
+        Ok(builder.build()?)
+    }
+
+    /// Mobile client that sends every request through `proxy` (see `proxy_url`)
+    pub fn build_proxy_client(proxy: reqwest::Proxy, passthrough: bool, title_case: bool) -> Result<Client> {
+        let mut builder = Self::with_cookies(Self::mobile_client_builder()).proxy(proxy);
+        if passthrough {
+            builder = Self::without_decoding(builder);
+        }
+        if title_case {
+            builder = builder.http1_title_case_headers();
+        }
+        Ok(builder.build()?)
+    }
+
+    /// Mobile client that never decodes bodies or rewrites encoding headers (see `passthrough`)
+    pub fn build_passthrough_client() -> Client {
+        Self::without_decoding(Self::with_cookies(Self::mobile_client_builder()))
+            .build()
+            .expect("Failed to build passthrough client")
+    }
+
+    /// Mobile client that writes HTTP/1.1 header names Title-Cased (see `preserve_header_case`)
+    pub fn build_title_case_client() -> Client {
+        Self::with_cookies(Self::mobile_client_builder())
+            .http1_title_case_headers()
+            .build()
+            .expect("Failed to build title-case client")
+    }
+
+    /// Mobile client that speaks only HTTP/3 (see `http3_only`). reqwest offers QUIC the `h3`
+    /// ALPN only with prior knowledge, so this ignores `alpn_protocols`. The QUIC endpoint
+    /// belongs to the runtime current at build time, hence the shared one is entered.
+    #[cfg(feature = "http3")]
+    pub fn build_http3_client() -> Client {
+        let runtime = crate::runtime_handle();
+        let _entered = runtime.enter();
+        Self::with_cookies(Self::mobile_client_builder())
+            .use_rustls_tls()
+            .http3_prior_knowledge()
+            .build()
+            .expect("Failed to build HTTP/3 client")
+    }
+
+    /// Shares `CookieJar` with the shared client; isolated and probe clients keep no cookies
+    fn with_cookies(builder: ClientBuilder) -> ClientBuilder {
+        builder.cookie_provider(Arc::new(CookieJar))
+    }
+
+    fn without_decoding(builder: ClientBuilder) -> ClientBuilder {
+        builder.no_gzip().no_deflate().no_brotli().no_zstd()
+    }
+
+    fn mobile_client_builder() -> ClientBuilder {
+        Self::apply_decoders(Self::apply_tls(Self::apply_socket_options(Client::builder())))
             .pool_idle_timeout(Duration::from_secs(300))    // Keep connections alive 5 min
             .pool_max_idle_per_host(50)                     // High reuse
-            .tcp_keepalive(Duration::from_secs(15))         // Fast dead detection
+            .tcp_keepalive(TCP_KEEPALIVE)                   // Fast dead detection
             .tcp_nodelay(true)
-            .http2_keep_alive_interval(Duration::from_secs(10))
-            .http2_keep_alive_timeout(Duration::from_secs(20))
+            .http2_keep_alive_interval(HTTP2_KEEP_ALIVE_INTERVAL)
+            .http2_keep_alive_timeout(HTTP2_KEEP_ALIVE_TIMEOUT)
             .http2_keep_alive_while_idle(true)
-            .connect_timeout(Duration::from_secs(8))
+            .connector_layer(ConnectTimeout::layer(Duration::from_secs(8)))
+            .connector_layer(ConnectionEvents::layer())
             .timeout(Duration::from_secs(20))
-            .use_rustls_tls()
-            .min_tls_version(reqwest::tls::Version::TLS_1_2)
             .no_proxy()
-            .gzip(true)
-            .deflate(true)
-            .brotli(true)
-            .redirect(reqwest::redirect::Policy::limited(3))
+            .redirect(Redirects::policy(3))
             .referer(false)
-            .build()
-            .expect("Failed to build mobile client")
     }
 
     /// Shared mobile client for app-wide use
     pub fn build_shared_mobile_client() -> Client {
-        Client::builder()
-            .pool_idle_timeout(Duration::from_secs(600))    // Keep alive 10 min
-            .pool_max_idle_per_host(100)                    // Max reuse
-            .tcp_keepalive(Duration::from_secs(15))
+        *SHARED_CLIENT_SETTINGS.write() = Some(Self::settings().clone());
+        Self::shared_mobile_client_builder()
+            .build()
+            .expect("Failed to build shared mobile client")
+    }
+
+    /// Shared client while the app is in the background: no HTTP/2 pings and no TCP
+    /// keepalive probes, so idle connections stop waking the radio
+    pub fn build_paused_mobile_client() -> Client {
+        *SHARED_CLIENT_SETTINGS.write() = Some(Self::settings().clone());
+        Self::shared_mobile_client_builder()
+            .tcp_keepalive(None)
+            .http2_keep_alive_interval(None)
+            .http2_keep_alive_while_idle(false)
+            .build()
+            .expect("Failed to build paused mobile client")
+    }
+
+    /// Client for `background` requests: no HTTP/2 pings, no TCP keepalive and no idle pool,
+    /// so no connection outlives the request that opened it
+    pub fn build_background_client() -> Client {
+        Self::shared_mobile_client_builder()
+            .tcp_keepalive(None)
+            .http2_keep_alive_interval(None)
+            .http2_keep_alive_while_idle(false)
+            .pool_max_idle_per_host(0)
+            .build()
+            .expect("Failed to build background client")
+    }
+
+    /// Client for connectivity probes: redirects are reported rather than followed (a portal
+    /// announces itself with one) and nothing is pooled, so every probe uses a new connection
+    pub fn build_probe_client(timeout: Duration) -> Client {
+        Self::apply_socket_options(Client::builder())
+            .redirect(reqwest::redirect::Policy::none())
+            .pool_max_idle_per_host(0)
+            .timeout(timeout)
+            .no_proxy()
+            .build()
+            .expect("Failed to build probe client")
+    }
+
+    /// Options `effective_config::SharedClientConfig` reports come from the `SHARED_*` consts
+    fn shared_mobile_client_builder() -> ClientBuilder {
+        Self::with_cookies(Self::apply_decoders(Self::apply_tls(Self::apply_socket_options(Client::builder()))))
+            .pool_idle_timeout(SHARED_POOL_IDLE_TIMEOUT)
+            .pool_max_idle_per_host(SHARED_POOL_MAX_IDLE_PER_HOST)
+            .tcp_keepalive(TCP_KEEPALIVE)
             .tcp_nodelay(true)
-            .http2_keep_alive_interval(Duration::from_secs(10))
-            .http2_keep_alive_timeout(Duration::from_secs(20))
+            .http2_keep_alive_interval(HTTP2_KEEP_ALIVE_INTERVAL)
+            .http2_keep_alive_timeout(HTTP2_KEEP_ALIVE_TIMEOUT)
             .http2_keep_alive_while_idle(true)
-            .connect_timeout(Duration::from_secs(5))
-            .timeout(Duration::from_secs(15))
-            .use_rustls_tls()
-            .min_tls_version(reqwest::tls::Version::TLS_1_2)
+            .connector_layer(ConnectTimeout::layer(SHARED_CONNECT_TIMEOUT))
+            .connector_layer(ConnectionEvents::layer())
+            .timeout(SHARED_TIMEOUT)
             .no_proxy()
-            .gzip(true)
-            .deflate(true)
-            .brotli(true)
-            .redirect(reqwest::redirect::Policy::limited(5))
+            .redirect(Redirects::policy(SHARED_MAX_REDIRECTS))
             .referer(false)
-            .build()
-            .expect("Failed to build shared mobile client")
+    }
+}
+
+/// Verifier for `set_accept_invalid_certs` on preconfigured TLS: any certificate for any
+/// name is accepted, handshake signatures are still checked
+#[cfg(feature = "dangerous-tls")]
+#[derive(Debug)]
+struct AcceptAnyCertificate;
+
+#[cfg(feature = "dangerous-tls")]
+impl rustls::client::danger::ServerCertVerifier for AcceptAnyCertificate {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &rustls::pki_types::ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: rustls::pki_types::UnixTime,
+    ) -> Result<rustls::client::danger::ServerCertVerified, rustls::Error> {
+        Ok(rustls::client::danger::ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &rustls::DigitallySignedStruct,
+    ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
+        let algorithms = rustls::crypto::ring::default_provider().signature_verification_algorithms;
+        rustls::crypto::verify_tls12_signature(message, cert, dss, &algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &rustls::DigitallySignedStruct,
+    ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
+        let algorithms = rustls::crypto::ring::default_provider().signature_verification_algorithms;
+        rustls::crypto::verify_tls13_signature(message, cert, dss, &algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<rustls::SignatureScheme> {
+        rustls::crypto::ring::default_provider().signature_verification_algorithms.supported_schemes()
     }
 }
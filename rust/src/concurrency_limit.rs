@@ -0,0 +1,74 @@
+use parking_lot::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+/// Limit until `set_max_concurrency` is called
+pub const DEFAULT_MAX_CONCURRENCY: usize = 128;
+
+static PERMITS: Semaphore = Semaphore::const_new(DEFAULT_MAX_CONCURRENCY);
+/// Current limit; the lock also serializes resizes
+static LIMIT: Mutex<usize> = Mutex::new(DEFAULT_MAX_CONCURRENCY);
+/// Permits still to be retired after a shrink, taken from requests as they finish
+static SHRINK_DEBT: AtomicUsize = AtomicUsize::new(0);
+
+static IN_FLIGHT: AtomicUsize = AtomicUsize::new(0);
+
+/// App-wide cap on network attempts in flight, shared by single, batch and streaming
+/// requests. Each attempt holds a slot from just before it is sent until its response is
+/// read, so every hedge, retry and fallback URL takes one of its own, as do
+/// stale-while-revalidate refreshes and `prewarm` connections. Cache hits take none. Waiting
+/// for a slot counts towards `total_timeout_ms` and ends early on cancellation.
+pub struct ConcurrencyLimit;
+
+impl ConcurrencyLimit {
+    pub async fn acquire() -> Permit {
+        let permit = PERMITS.acquire().await.expect("the semaphore is never closed");
+        IN_FLIGHT.fetch_add(1, Ordering::Relaxed);
+        Permit { permit: Some(permit) }
+    }
+
+    /// Changes the limit; false (and unchanged) for 0. Raising it lets waiting requests
+    /// through at once; lowering it never interrupts running ones, new requests just wait
+    /// until enough of them have finished.
+    pub fn set(limit: usize) -> bool {
+        if limit == 0 {
+            return false;
+        }
+        let mut current = LIMIT.lock();
+        if limit > *current {
+            // Permits still owed from a shrink are cancelled before new ones are added
+            let grow = limit - *current;
+            let owed = SHRINK_DEBT.fetch_update(Ordering::AcqRel, Ordering::Acquire, |debt| Some(debt - debt.min(grow))).unwrap_or(0);
+            PERMITS.add_permits(grow - owed.min(grow));
+        } else {
+            let surplus = *current - limit;
+            let forgotten = PERMITS.forget_permits(surplus);
+            SHRINK_DEBT.fetch_add(surplus - forgotten, Ordering::AcqRel);
+        }
+        *current = limit;
+        true
+    }
+
+    pub fn limit() -> usize {
+        *LIMIT.lock()
+    }
+
+    pub fn in_flight() -> usize {
+        IN_FLIGHT.load(Ordering::Relaxed)
+    }
+}
+
+/// A slot of the limit, handed back (or retired after a shrink) on drop
+pub struct Permit {
+    permit: Option<SemaphorePermit<'static>>,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        IN_FLIGHT.fetch_sub(1, Ordering::Relaxed);
+        let Some(permit) = self.permit.take() else { return };
+        if SHRINK_DEBT.fetch_update(Ordering::AcqRel, Ordering::Acquire, |debt| debt.checked_sub(1)).is_ok() {
+            permit.forget();
+        }
+    }
+}
@@ -0,0 +1,190 @@
+use crate::client_config::ClientConfig;
+use crate::json::json;
+use crate::models::HttpError;
+
+use futures_util::future::BoxFuture;
+use parking_lot::Mutex;
+use reqwest::Url;
+use std::future::Future;
+use std::io;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tower::{Layer, Service};
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Host and port being connected to and the request's own `connect_timeout_ms`
+struct Target {
+    host: String,
+    port: u16,
+    request_timeout_ms: u64,
+}
+
+tokio::task_local! {
+    // Like the redirect log, read by the connector from inside the `send()` future
+    static TARGET: Arc<Mutex<Target>>;
+}
+
+/// Per-connection timeout (DNS + TCP + TLS), in place of reqwest's client-wide
+/// `connect_timeout`: the request's `connect_timeout_ms`, else the
+/// `connect_timeouts_by_host` entry, else the client default. Expiry fails the request
+/// with `CONNECT_TIMEOUT`, which counts as a connect error for `connect_attempts`.
+pub struct ConnectTimeout;
+
+impl ConnectTimeout {
+    /// Runs `future` (a `send()` to `url`) so connections it opens see the request's timeout
+    pub async fn scope<F: Future>(url: &Url, request_timeout_ms: u64, future: F) -> F::Output {
+        let target = Target {
+            host: url.host_str().unwrap_or_default().to_owned(),
+            port: url.port_or_known_default().unwrap_or_default(),
+            request_timeout_ms,
+        };
+        TARGET.scope(Arc::new(Mutex::new(target)), future).await
+    }
+
+    /// Called by the redirect policy, so a hop to another host gets that host's timeout
+    pub fn redirected(url: &Url) {
+        let _ = TARGET.try_with(|target| {
+            let mut target = target.lock();
+            target.host = url.host_str().unwrap_or_default().to_owned();
+            target.port = url.port_or_known_default().unwrap_or_default();
+        });
+    }
+
+    /// Host of the connection being opened, when called from inside `scope`
+    pub fn current_host() -> Option<String> {
+        TARGET.try_with(|target| target.lock().host.clone()).ok()
+    }
+
+    /// Host and port of the connection being opened, when called from inside `scope`
+    pub fn current_target() -> Option<(String, u16)> {
+        TARGET.try_with(|target| {
+            let target = target.lock();
+            (target.host.clone(), target.port)
+        })
+        .ok()
+    }
+
+    /// `connect_timeouts_by_host` entry for `host`: an exact match, else the closest
+    /// `*.parent` wildcard
+    pub fn for_host(host: &str) -> Option<u64> {
+        let settings = ClientConfig::settings();
+        let timeouts = &settings.connect_timeouts_by_host;
+        if timeouts.is_empty() {
+            return None;
+        }
+        let host = host.to_ascii_lowercase();
+        timeouts.get(&host).copied().or_else(|| {
+            host.match_indices('.').find_map(|(at, _)| timeouts.get(&format!("*{}", &host[at..])).copied())
+        })
+    }
+
+    /// Connector layer installed by `ClientConfig`, with `default` for unlisted hosts
+    pub fn layer(default: Duration) -> ConnectTimeoutLayer {
+        ConnectTimeoutLayer { default }
+    }
+
+    /// Timeout for a connection opened now; `None` means unbounded (explicit 0 in the map)
+    fn current(default: Duration) -> (String, Option<Duration>) {
+        let target = TARGET.try_with(|target| {
+            let target = target.lock();
+            (target.host.clone(), target.request_timeout_ms)
+        });
+        let Ok((host, request_timeout_ms)) = target else {
+            return (String::new(), Some(default));
+        };
+        let timeout = match request_timeout_ms {
+            0 => Self::for_host(&host).map_or(Some(default), |ms| (ms > 0).then(|| Duration::from_millis(ms))),
+            ms => Some(Duration::from_millis(ms)),
+        };
+        (host, timeout)
+    }
+}
+
+#[derive(Clone)]
+pub struct ConnectTimeoutLayer {
+    default: Duration,
+}
+
+impl<S> Layer<S> for ConnectTimeoutLayer {
+    type Service = ConnectTimeoutService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ConnectTimeoutService { inner, default: self.default }
+    }
+}
+
+#[derive(Clone)]
+pub struct ConnectTimeoutService<S> {
+    inner: S,
+    default: Duration,
+}
+
+impl<S, R> Service<R> for ConnectTimeoutService<S>
+where
+    S: Service<R, Error = BoxError>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = BoxError;
+    type Future = BoxFuture<'static, Result<S::Response, BoxError>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), BoxError>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: R) -> Self::Future {
+        // hyper-util calls the connector from the request's own task, so the target is read here
+        let (host, timeout) = ConnectTimeout::current(self.default);
+        let connecting = self.inner.call(request);
+        Box::pin(async move {
+            let Some(timeout) = timeout else { return connecting.await };
+            match tokio::time::timeout(timeout, connecting).await {
+                Ok(result) => result,
+                Err(_) => {
+                    let timeout_ms = timeout.as_millis() as u64;
+                    let error = HttpError::new("CONNECT_TIMEOUT", format!("Connecting to {} took longer than {} ms", host, timeout_ms))
+                        .with_details(json!({ "host": host, "connect_timeout_ms": timeout_ms }));
+                    Err(io::Error::new(io::ErrorKind::TimedOut, error).into())
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEFAULT: Duration = Duration::from_secs(10);
+
+    /// Timeout a connection opened by a request to `url` gets
+    async fn current(url: &str, request_timeout_ms: u64) -> Option<Duration> {
+        let url = Url::parse(url).unwrap();
+        ConnectTimeout::scope(&url, request_timeout_ms, async { ConnectTimeout::current(DEFAULT).1 }).await
+    }
+
+    #[tokio::test]
+    async fn host_entries_match_exactly_then_by_closest_wildcard() {
+        let mut settings = ClientConfig::settings().clone();
+        settings.connect_timeouts_by_host.extend([
+            ("api.timeouts.test".to_owned(), 100),
+            ("*.timeouts.test".to_owned(), 200),
+            ("*.eu.timeouts.test".to_owned(), 300),
+            ("unbounded.timeouts.test".to_owned(), 0),
+        ]);
+        ClientConfig::set_settings(settings);
+
+        assert_eq!(ConnectTimeout::for_host("API.timeouts.test"), Some(100));
+        assert_eq!(ConnectTimeout::for_host("cdn.timeouts.test"), Some(200));
+        assert_eq!(ConnectTimeout::for_host("a.b.eu.timeouts.test"), Some(300));
+        assert_eq!(ConnectTimeout::for_host("timeouts.test"), None);
+        assert_eq!(ConnectTimeout::for_host("other.test"), None);
+
+        assert_eq!(current("https://api.timeouts.test/", 0).await, Some(Duration::from_millis(100)));
+        assert_eq!(current("https://api.timeouts.test/", 50).await, Some(Duration::from_millis(50)));
+        assert_eq!(current("https://unbounded.timeouts.test/", 0).await, None);
+        assert_eq!(current("https://other.test/", 0).await, Some(DEFAULT));
+    }
+}
@@ -0,0 +1,162 @@
+use crate::client_config::{AddressFamily, ClientConfig};
+use crate::connect_timeout::ConnectTimeout;
+use crate::dart_dl_ffi::{self, PortEvent};
+use crate::dns::DnsResolver;
+use crate::json;
+use crate::metrics::{ConnectionStats, RequestMetrics};
+use crate::models::HttpError;
+use crate::trace::Trace;
+
+use futures_util::future::BoxFuture;
+use hyper::http::Extensions;
+use hyper_util::client::legacy::connect::{Connection, HttpInfo};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::task::{Context, Poll};
+use std::time::Instant;
+use tower::{Layer, Service};
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Last successful connect per host, for `since_last_open_ms`
+static LAST_OPENED: Lazy<Mutex<HashMap<String, Instant>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum ConnectionEvent {
+    Opened {
+        host: String,
+        remote_addr: Option<String>,
+        local_addr: Option<String>,
+        family: Option<&'static str>,
+        /// `h2` when negotiated via ALPN, else `http/1.1`
+        protocol: &'static str,
+        /// DNS + TCP + TLS
+        connect_ms: u128,
+        /// Since the previous connection to the same host was opened; short gaps mean churn
+        since_last_open_ms: Option<u128>,
+    },
+    Failed {
+        host: String,
+        connect_ms: u128,
+        /// `HttpError` code when there is one (e.g. `CONNECT_TIMEOUT`, `DNS_TIMEOUT`)
+        code: Option<String>,
+        error: String,
+    },
+}
+
+/// Real-time connection lifecycle events, opt-in via `ClientSettings::connection_events_id`:
+/// posted as `[id, PortEvent::Connection, json]` with `event` `opened` or `failed`.
+/// Reused pooled connections post nothing, so every `opened` is a fresh handshake.
+///
+/// There is no `closed` event: reqwest owns the connection object and gives no hook for
+/// its end, so a connection's lifetime shows up as `since_last_open_ms` on its successor.
+/// The negotiated TLS version is not exposed either.
+pub struct ConnectionEvents;
+
+impl ConnectionEvents {
+    /// Connector layer installed by `ClientConfig`; also times the `connect` trace phase and
+    /// counts and records the family of every new connection (see `ConnectionStats` and
+    /// `RequestMetrics::record_family_connect`)
+    pub fn layer() -> ConnectionEventsLayer {
+        ConnectionEventsLayer
+    }
+
+    fn post(id: i64, event: &ConnectionEvent) {
+        if let Ok(json) = json::to_vec(event) {
+            dart_dl_ffi::post_message(id, PortEvent::Connection, &json);
+        }
+    }
+
+    fn opened<C: Connection>(id: i64, host: String, conn: &C, started: Instant) {
+        let connected = conn.connected();
+        let mut extensions = Extensions::new();
+        connected.get_extras(&mut extensions);
+        let info = extensions.get::<HttpInfo>();
+        let now = Instant::now();
+        let since_last_open_ms = LAST_OPENED.lock().insert(host.clone(), now).map(|last| now.duration_since(last).as_millis());
+        Self::post(id, &ConnectionEvent::Opened {
+            host,
+            remote_addr: info.map(|info| info.remote_addr().to_string()),
+            local_addr: info.map(|info| info.local_addr().to_string()),
+            family: info.map(|info| AddressFamily::of(&info.remote_addr()).as_str()),
+            protocol: if connected.is_negotiated_h2() { "h2" } else { "http/1.1" },
+            connect_ms: started.elapsed().as_millis(),
+            since_last_open_ms,
+        });
+    }
+
+    /// Peer address of a new connection, when the connector reports one
+    fn remote_addr<C: Connection>(conn: &C) -> Option<SocketAddr> {
+        let mut extensions = Extensions::new();
+        conn.connected().get_extras(&mut extensions);
+        extensions.get::<HttpInfo>().map(HttpInfo::remote_addr)
+    }
+
+    fn failed(id: i64, host: String, error: &BoxError, started: Instant) {
+        let code = HttpError::from_source_chain(error.as_ref()).map(|error| error.code);
+        Self::post(id, &ConnectionEvent::Failed { host, connect_ms: started.elapsed().as_millis(), code, error: error.to_string() });
+    }
+}
+
+#[derive(Clone)]
+pub struct ConnectionEventsLayer;
+
+impl<S> Layer<S> for ConnectionEventsLayer {
+    type Service = ConnectionEventsService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ConnectionEventsService { inner }
+    }
+}
+
+#[derive(Clone)]
+pub struct ConnectionEventsService<S> {
+    inner: S,
+}
+
+impl<S, R> Service<R> for ConnectionEventsService<S>
+where
+    S: Service<R, Error = BoxError>,
+    S::Response: Connection + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = BoxError;
+    type Future = BoxFuture<'static, Result<S::Response, BoxError>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), BoxError>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: R) -> Self::Future {
+        let connecting = self.inner.call(request);
+        let id = ClientConfig::settings().connection_events_id;
+        let recorder = Trace::current();
+        let host = ConnectTimeout::current_host().unwrap_or_default();
+        let started = Instant::now();
+        Box::pin(async move {
+            let result = connecting.await;
+            if let Some(recorder) = recorder {
+                recorder.record("connect", started);
+            }
+            if result.is_ok() {
+                ConnectionStats::connection_opened(&host);
+            }
+            if let Some(addr) = result.as_ref().ok().and_then(ConnectionEvents::remote_addr) {
+                let family = AddressFamily::of(&addr);
+                RequestMetrics::record_family_connect(family, started.elapsed());
+                DnsResolver::connected(&host, family);
+            }
+            match (&result, id) {
+                (Ok(conn), Some(id)) => ConnectionEvents::opened(id, host, conn, started),
+                (Err(error), Some(id)) => ConnectionEvents::failed(id, host, error, started),
+                (_, None) => {}
+            }
+            result
+        })
+    }
+}
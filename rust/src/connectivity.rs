@@ -0,0 +1,70 @@
+use crate::client_config::ClientConfig;
+
+use once_cell::sync::Lazy;
+use reqwest::header::LOCATION;
+use reqwest::Client;
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+/// How long a probe may take before the network counts as offline
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+static PROBE_CLIENT: Lazy<Client> = Lazy::new(|| ClientConfig::build_probe_client(PROBE_TIMEOUT));
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConnectivityStatus {
+    /// The probe came back as `204 No Content`
+    Online,
+    /// Something answered in the server's place: a redirect, or any other status or body
+    CaptivePortal,
+    /// The probe failed (DNS, connect, TLS or timeout)
+    Offline,
+}
+
+/// Outcome of one probe of `ClientSettings::connectivity_check_url`
+#[derive(Debug, Serialize)]
+pub struct ConnectivityReport {
+    pub status: ConnectivityStatus,
+    pub probe_url: String,
+    /// Status of the probe response, `None` when offline
+    pub status_code: Option<u16>,
+    /// `Location` of a redirecting portal
+    pub portal_url: Option<String>,
+    pub elapsed_ms: u128,
+    pub error: Option<String>,
+}
+
+/// Captive-portal detection with a generate-204 style probe (see the `check_connectivity` FFI)
+pub struct Connectivity;
+
+impl Connectivity {
+    /// Sends one GET to the probe URL, bypassing offline mode, redirects and the pool
+    pub async fn check() -> ConnectivityReport {
+        let probe_url = ClientConfig::settings().connectivity_check_url.clone();
+        let start = Instant::now();
+        let result = PROBE_CLIENT.get(probe_url.as_str()).send().await;
+        let elapsed_ms = start.elapsed().as_millis();
+
+        match result {
+            Ok(response) => {
+                let status_code = response.status().as_u16();
+                let portal_url = response
+                    .headers()
+                    .get(LOCATION)
+                    .and_then(|value| value.to_str().ok())
+                    .map(str::to_owned);
+                let status = if status_code == 204 { ConnectivityStatus::Online } else { ConnectivityStatus::CaptivePortal };
+                ConnectivityReport { status, probe_url, status_code: Some(status_code), portal_url, elapsed_ms, error: None }
+            }
+            Err(e) => ConnectivityReport {
+                status: ConnectivityStatus::Offline,
+                probe_url,
+                status_code: None,
+                portal_url: None,
+                elapsed_ms,
+                error: Some(e.to_string()),
+            },
+        }
+    }
+}
@@ -0,0 +1,44 @@
+use crate::models::HttpError;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use md5::{Digest, Md5};
+use reqwest::header::{HeaderName, HeaderValue};
+use ring::digest;
+use serde::{Deserialize, Serialize};
+
+/// Body digest header to attach (see `HttpRequest::content_digest`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DigestAlgorithm {
+    /// `Content-MD5: <base64>` (RFC 1864, still required by some storage APIs)
+    Md5,
+    /// `Content-Digest: sha-256=:<base64>:` (RFC 9530)
+    Sha256,
+    /// `Content-Digest: sha-512=:<base64>:` (RFC 9530)
+    Sha512,
+}
+
+pub struct ContentDigest;
+
+impl ContentDigest {
+    /// Header carrying the digest of `body`, which must be the exact bytes sent
+    pub fn header(algorithm: DigestAlgorithm, body: &[u8]) -> (HeaderName, HeaderValue) {
+        let (name, value) = match algorithm {
+            DigestAlgorithm::Md5 => ("content-md5", STANDARD.encode(Md5::digest(body))),
+            DigestAlgorithm::Sha256 => ("content-digest", Self::structured("sha-256", &digest::SHA256, body)),
+            DigestAlgorithm::Sha512 => ("content-digest", Self::structured("sha-512", &digest::SHA512, body)),
+        };
+        // base64 and the fixed prefixes are always valid header bytes
+        (HeaderName::from_static(name), HeaderValue::from_str(&value).expect("digest header is ASCII"))
+    }
+
+    /// Streamed bodies (`body_file`, `body_files`, `multipart`) are not hashed up front
+    pub fn unavailable(url: &str) -> HttpError {
+        HttpError::new("DIGEST_UNAVAILABLE", format!("content_digest needs an in-memory body, {} streams its body", url))
+    }
+
+    fn structured(label: &str, algorithm: &'static digest::Algorithm, body: &[u8]) -> String {
+        format!("{}=:{}:", label, STANDARD.encode(digest::digest(algorithm, body)))
+    }
+}
@@ -0,0 +1,62 @@
+use crate::json;
+
+use anyhow::Result;
+use cookie_store::{Cookie, CookieStore as Store, RawCookie};
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use reqwest::cookie::CookieStore;
+use reqwest::header::HeaderValue;
+use reqwest::Url;
+use std::convert::Infallible;
+
+static STORE: Lazy<RwLock<Store>> = Lazy::new(|| RwLock::new(Store::default()));
+
+/// Cookies shared by the shared client and its per-request variants (passthrough,
+/// title-case, pinned, HTTP/3): `Set-Cookie` from one request is sent on the next
+/// matching one, unless the request sets `Cookie` itself
+pub struct CookieJar;
+
+impl CookieJar {
+    /// Unexpired cookies as a JSON array, session cookies included, for `import`
+    pub fn export() -> Result<Vec<u8>> {
+        let store = STORE.read();
+        let cookies: Vec<&Cookie<'static>> = store.iter_unexpired().collect();
+        json::to_vec(&cookies)
+    }
+
+    /// Replaces the jar with the cookies of an `export` (expired ones are dropped) and
+    /// returns how many were loaded
+    pub fn import(bytes: &mut [u8]) -> Result<usize> {
+        let cookies: Vec<Cookie<'static>> = json::from_slice(bytes)?;
+        let store = Store::from_cookies(cookies.into_iter().map(Ok::<_, Infallible>), false)?;
+        let loaded = store.iter_unexpired().count();
+        *STORE.write() = store;
+        Ok(loaded)
+    }
+
+    pub fn clear() {
+        STORE.write().clear();
+    }
+}
+
+impl CookieStore for CookieJar {
+    fn set_cookies(&self, cookie_headers: &mut dyn Iterator<Item = &HeaderValue>, url: &Url) {
+        let cookies = cookie_headers
+            .filter_map(|value| std::str::from_utf8(value.as_bytes()).ok())
+            .filter_map(|value| RawCookie::parse(value.to_owned()).ok());
+        STORE.write().store_response_cookies(cookies, url);
+    }
+
+    fn cookies(&self, url: &Url) -> Option<HeaderValue> {
+        let store = STORE.read();
+        let value = store
+            .get_request_values(url)
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect::<Vec<_>>()
+            .join("; ");
+        if value.is_empty() {
+            return None;
+        }
+        HeaderValue::from_str(&value).ok()
+    }
+}
@@ -0,0 +1,95 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use reqwest::header::{HeaderValue, InvalidHeaderValue};
+use reqwest::Url;
+
+/// Credentials registered for a host pattern: `api.example.com`, or `*.example.com`
+/// for any subdomain (the apex needs its own entry)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Credential {
+    pub host: String,
+    pub username: String,
+    #[serde(default, skip_serializing)]
+    pub password: String,
+}
+
+static STORE: Lazy<RwLock<Vec<Credential>>> = Lazy::new(|| RwLock::new(Vec::new()));
+
+/// Host → Basic credentials, applied to requests that carry no `Authorization` header
+pub struct CredentialStore;
+
+impl CredentialStore {
+    /// Adds or replaces the entry for `credential.host`
+    pub fn add(mut credential: Credential) {
+        credential.host.make_ascii_lowercase();
+        let mut store = STORE.write();
+        store.retain(|entry| entry.host != credential.host);
+        store.push(credential);
+    }
+
+    /// Removes the entry for `host`; false if there was none
+    pub fn remove(host: &str) -> bool {
+        let host = host.to_ascii_lowercase();
+        let mut store = STORE.write();
+        let before = store.len();
+        store.retain(|entry| entry.host != host);
+        store.len() != before
+    }
+
+    pub fn clear() {
+        STORE.write().clear();
+    }
+
+    /// Registered entries (passwords are never serialized)
+    pub fn list() -> Vec<Credential> {
+        STORE.read().clone()
+    }
+
+    /// `Basic` Authorization value for `url`, marked sensitive
+    pub fn authorization(url: &str) -> Option<HeaderValue> {
+        let (username, password) = Self::lookup(url)?;
+        Self::basic(&username, &password).ok()
+    }
+
+    /// `Basic` Authorization value (RFC 7617: base64 of `username:password`, UTF-8), marked sensitive
+    pub fn basic(username: &str, password: &str) -> Result<HeaderValue, InvalidHeaderValue> {
+        let encoded = STANDARD.encode(format!("{}:{}", username, password));
+        Self::sensitive(&format!("Basic {}", encoded))
+    }
+
+    /// `Bearer` Authorization value, marked sensitive; fails if `token` is not a valid header value
+    pub fn bearer(token: &str) -> Result<HeaderValue, InvalidHeaderValue> {
+        Self::sensitive(&format!("Bearer {}", token))
+    }
+
+    fn sensitive(value: &str) -> Result<HeaderValue, InvalidHeaderValue> {
+        let mut value = HeaderValue::from_str(value)?;
+        value.set_sensitive(true);
+        Ok(value)
+    }
+
+    /// `(username, password)` for `url`: an exact host match wins over the longest wildcard
+    pub fn lookup(url: &str) -> Option<(String, String)> {
+        let store = STORE.read();
+        if store.is_empty() {
+            return None;
+        }
+        let host = Url::parse(url).ok()?.host_str()?.to_ascii_lowercase();
+
+        store
+            .iter()
+            .filter_map(|entry| {
+                if entry.host == host {
+                    Some((usize::MAX, entry))
+                } else {
+                    let suffix = entry.host.strip_prefix('*')?;
+                    (suffix.starts_with('.') && host.ends_with(suffix)).then_some((suffix.len(), entry))
+                }
+            })
+            .max_by_key(|(specificity, _)| *specificity)
+            .map(|(_, entry)| (entry.username.clone(), entry.password.clone()))
+    }
+}
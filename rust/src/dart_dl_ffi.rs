@@ -0,0 +1,221 @@
+use once_cell::sync::OnceCell;
+use std::ffi::{c_char, c_int, c_void, CStr};
+use std::sync::atomic::{AtomicI64, Ordering};
+
+// ---------- dart_api_dl layout (dart-sdk/runtime/include/dart_api_dl.c) ----------
+
+const DART_API_DL_MAJOR_VERSION: c_int = 2;
+
+#[repr(C)]
+struct DartApiEntry {
+    name: *const c_char,
+    function: *const c_void,
+}
+
+#[repr(C)]
+struct DartApi {
+    major: c_int,
+    minor: c_int,
+    functions: *const DartApiEntry,
+}
+
+type DartPostCObjectFn = unsafe extern "C" fn(port: i64, message: *mut DartCObject) -> bool;
+
+// ---------- Dart_CObject layout (dart-sdk/runtime/include/dart_native_api.h) ----------
+
+#[repr(i32)]
+#[allow(dead_code)]
+#[derive(Clone, Copy)]
+enum DartCObjectType {
+    Null = 0,
+    Bool = 1,
+    Int32 = 2,
+    Int64 = 3,
+    Double = 4,
+    String = 5,
+    Array = 6,
+    TypedData = 7,
+}
+
+const DART_TYPED_DATA_UINT8: i32 = 2;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct DartArray {
+    length: isize,
+    values: *mut *mut DartCObject,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct DartTypedData {
+    type_: i32,
+    length: isize,
+    values: *const u8,
+}
+
+// Only here so the union has the same size as the C one.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct DartExternalTypedData {
+    type_: i32,
+    length: isize,
+    data: *mut u8,
+    peer: *mut c_void,
+    callback: *const c_void,
+}
+
+#[repr(C)]
+union DartCObjectValue {
+    as_int64: i64,
+    as_array: DartArray,
+    as_typed_data: DartTypedData,
+    _as_external_typed_data: DartExternalTypedData,
+}
+
+#[repr(C)]
+pub struct DartCObject {
+    type_: DartCObjectType,
+    value: DartCObjectValue,
+}
+
+impl DartCObject {
+    fn int64(value: i64) -> Self {
+        Self { type_: DartCObjectType::Int64, value: DartCObjectValue { as_int64: value } }
+    }
+
+    /// Borrows `bytes`; Dart copies typed data when the message is posted
+    fn uint8_list(bytes: &[u8]) -> Self {
+        Self {
+            type_: DartCObjectType::TypedData,
+            value: DartCObjectValue {
+                as_typed_data: DartTypedData {
+                    type_: DART_TYPED_DATA_UINT8,
+                    length: bytes.len() as isize,
+                    values: bytes.as_ptr(),
+                },
+            },
+        }
+    }
+}
+
+/// Kind of message posted to the registered port, sent as the second array element
+#[repr(i64)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PortEvent {
+    /// Serialized `HttpResponse`
+    Response = 0,
+    /// Serialized `HttpError`
+    Error = 1,
+    /// JSON upload progress (see `progress.rs`)
+    UploadProgress = 2,
+    /// Bytes read from a raw socket
+    RawData = 3,
+    /// Raw socket closed, JSON `{"error": ...}`
+    RawClosed = 4,
+    /// Request fully written, JSON `{"bytes_sent": n}` (see `sent_event_id`)
+    RequestSent = 5,
+    /// One finished request of a streaming batch (see `batch_stream.rs`)
+    BatchItem = 6,
+    /// Streaming batch done, JSON `{"count": n, "errors": n}`
+    BatchComplete = 7,
+    /// Batch progress, JSON `{"completed", "total", "succeeded", "failed"}`
+    BatchProgress = 8,
+    /// Connection `opened` / `failed`, JSON (see `connection_events.rs`)
+    Connection = 9,
+    /// Raw bytes of a streamed response body (see `response_stream.rs`)
+    BodyChunk = 10,
+}
+
+static POST_COBJECT: OnceCell<DartPostCObjectFn> = OnceCell::new();
+static DART_PORT: AtomicI64 = AtomicI64::new(0);
+
+/// Initializes the Dart DL API from `NativeApi.initializeApiDLData`.
+/// Returns 0 on success and -1 if the data is missing or from an incompatible Dart SDK.
+#[no_mangle]
+pub extern "C" fn init_dart_api_dl(data: *mut c_void) -> isize {
+    if data.is_null() { return -1; }
+
+    // SAFETY: `data` is the `DartApi` table handed out by the Dart VM.
+    unsafe {
+        let api = &*(data as *const DartApi);
+        if api.major != DART_API_DL_MAJOR_VERSION { return -1; }
+
+        let mut entry = api.functions;
+        while !(*entry).name.is_null() {
+            if CStr::from_ptr((*entry).name).to_bytes() == b"Dart_PostCObject" {
+                let post: DartPostCObjectFn = std::mem::transmute((*entry).function);
+                let _ = POST_COBJECT.set(post);
+                return 0;
+            }
+            entry = entry.add(1);
+        }
+    }
+    -1
+}
+
+/// Registers the `ReceivePort.sendPort.nativePort` that async results and events go to.
+#[no_mangle]
+pub extern "C" fn register_dart_port(port: i64) {
+    DART_PORT.store(port, Ordering::Release);
+}
+
+/// Whether a port is registered and the DL API is initialized
+pub fn is_port_ready() -> bool {
+    POST_COBJECT.get().is_some() && DART_PORT.load(Ordering::Acquire) != 0
+}
+
+/// Posts `[id, event, payload]` to the registered port as `[int, int, Uint8List]`.
+/// Returns false if no port is registered or Dart rejected the message.
+pub fn post_message(id: i64, event: PortEvent, payload: &[u8]) -> bool {
+    let Some(post) = POST_COBJECT.get() else { return false };
+    let port = DART_PORT.load(Ordering::Acquire);
+    if port == 0 { return false; }
+
+    let mut id_obj = DartCObject::int64(id);
+    let mut event_obj = DartCObject::int64(event as i64);
+    let mut payload_obj = DartCObject::uint8_list(payload);
+    let mut values = [&mut id_obj as *mut _, &mut event_obj as *mut _, &mut payload_obj as *mut _];
+    let mut message = DartCObject {
+        type_: DartCObjectType::Array,
+        value: DartCObjectValue {
+            as_array: DartArray { length: values.len() as isize, values: values.as_mut_ptr() },
+        },
+    };
+
+    // SAFETY: every object outlives the call; Dart copies the message before returning.
+    unsafe { post(port, &mut message) }
+}
+
+/// A stand-in for the Dart port, so tests can see what would have been posted
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use parking_lot::Mutex;
+
+    /// `(id, event, payload)` of every message posted since `capture` was first called
+    static POSTED: Mutex<Vec<(i64, i64, Vec<u8>)>> = Mutex::new(Vec::new());
+
+    unsafe extern "C" fn record(_port: i64, message: *mut DartCObject) -> bool {
+        // SAFETY: `post_message` always passes an `[int, int, Uint8List]` array
+        unsafe {
+            let values = (*message).value.as_array.values;
+            let (id, event) = ((**values).value.as_int64, (**values.add(1)).value.as_int64);
+            let data = (**values.add(2)).value.as_typed_data;
+            let payload = std::slice::from_raw_parts(data.values, data.length as usize).to_vec();
+            POSTED.lock().push((id, event, payload));
+        }
+        true
+    }
+
+    /// Makes posting succeed from now on
+    pub(crate) fn capture() {
+        let _ = POST_COBJECT.set(record);
+        DART_PORT.store(1, Ordering::Release);
+    }
+
+    /// Payloads of the `event` messages posted for `id` so far, oldest first
+    pub(crate) fn posted(id: i64, event: PortEvent) -> Vec<Vec<u8>> {
+        POSTED.lock().iter().filter(|(posted_id, posted_event, _)| (*posted_id, *posted_event) == (id, event as i64)).map(|(_, _, payload)| payload.clone()).collect()
+    }
+}
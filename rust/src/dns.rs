@@ -0,0 +1,195 @@
+use crate::client_config::{AddressFamily, ClientConfig};
+use crate::json::json;
+use crate::models::HttpError;
+use crate::trace::Trace;
+
+use once_cell::sync::Lazy;
+use parking_lot::{Mutex, RwLock};
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+/// Caller-pinned addresses per host, consulted before any lookup
+static OVERRIDES: Lazy<RwLock<HashMap<String, Vec<SocketAddr>>>> = Lazy::new(|| RwLock::new(HashMap::new()));
+
+struct CachedLookup {
+    addrs: Vec<SocketAddr>,
+    resolved_at: Instant,
+}
+
+/// Positive lookup cache, only used when `ClientSettings::dns_cache_ttl_ms` > 0
+static CACHE: Lazy<Mutex<HashMap<String, CachedLookup>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Family the last new connection per host was made over, and when
+static CONNECTED_FAMILIES: Lazy<Mutex<HashMap<String, (AddressFamily, Instant)>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// How long a family that connected is tried first
+const CONNECTED_FAMILY_TTL: Duration = Duration::from_secs(600);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LookupSource {
+    Override,
+    Cache,
+    System,
+}
+
+/// Result of `DnsResolver::lookup_detailed`
+#[derive(Debug, Clone)]
+pub struct Lookup {
+    pub addrs: Vec<SocketAddr>,
+    pub source: LookupSource,
+    /// How long the answer stays in our cache. getaddrinfo does not expose record TTLs,
+    /// so this is `None` unless `dns_cache_ttl_ms` is set.
+    pub ttl_remaining: Option<Duration>,
+}
+
+/// Resolver installed on every client built by `ClientConfig`.
+/// State lives in statics so `flush` affects all clients at once.
+pub struct DnsResolver;
+
+impl DnsResolver {
+    /// Overrides, then the cache, then the system resolver (getaddrinfo)
+    pub async fn lookup(host: &str) -> io::Result<Vec<SocketAddr>> {
+        Ok(Self::lookup_detailed(host).await?.addrs)
+    }
+
+    /// `lookup` that also reports where the answer came from
+    pub async fn lookup_detailed(host: &str) -> io::Result<Lookup> {
+        if let Some(addrs) = OVERRIDES.read().get(host) {
+            return Ok(Lookup { addrs: addrs.clone(), source: LookupSource::Override, ttl_remaining: None });
+        }
+
+        let ttl = Duration::from_millis(ClientConfig::settings().dns_cache_ttl_ms);
+        if !ttl.is_zero() {
+            if let Some(cached) = CACHE.lock().get(host) {
+                let age = cached.resolved_at.elapsed();
+                if age < ttl {
+                    return Ok(Lookup { addrs: cached.addrs.clone(), source: LookupSource::Cache, ttl_remaining: Some(ttl - age) });
+                }
+            }
+        }
+
+        let addrs = Self::system_lookup(host).await?;
+        if !ttl.is_zero() {
+            CACHE.lock().insert(host.to_owned(), CachedLookup { addrs: addrs.clone(), resolved_at: Instant::now() });
+        }
+        Ok(Lookup { addrs, source: LookupSource::System, ttl_remaining: (!ttl.is_zero()).then_some(ttl) })
+    }
+
+    /// getaddrinfo, bounded by `ClientSettings::dns_timeout_ms`; fails with `DNS` or `DNS_TIMEOUT`
+    async fn system_lookup(host: &str) -> io::Result<Vec<SocketAddr>> {
+        let timeout_ms = ClientConfig::settings().dns_timeout_ms;
+        Self::bounded(host, timeout_ms, tokio::net::lookup_host((host, 0))).await
+    }
+
+    /// `lookup` of `host`, bounded by `timeout_ms` unless 0
+    async fn bounded<I>(host: &str, timeout_ms: u64, lookup: impl Future<Output = io::Result<I>>) -> io::Result<Vec<SocketAddr>>
+    where
+        I: Iterator<Item = SocketAddr>,
+    {
+        let failed = |e: io::Error| {
+            let error = HttpError::new("DNS", format!("Could not resolve {}: {}", host, e))
+                .with_details(json!({ "host": host }));
+            io::Error::new(e.kind(), error)
+        };
+        if timeout_ms == 0 {
+            return Ok(lookup.await.map_err(failed)?.collect());
+        }
+        match tokio::time::timeout(Duration::from_millis(timeout_ms), lookup).await {
+            Ok(addrs) => Ok(addrs.map_err(failed)?.collect()),
+            Err(_) => Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                HttpError::new("DNS_TIMEOUT", format!("Resolving {} took longer than {} ms", host, timeout_ms))
+                    .with_details(json!({ "host": host, "dns_timeout_ms": timeout_ms })),
+            )),
+        }
+    }
+
+    /// Pins `host` to `addrs` (port 0 means "use the URL's port"); an empty list removes the pin
+    pub fn set_override(host: &str, addrs: Vec<SocketAddr>) {
+        let mut overrides = OVERRIDES.write();
+        if addrs.is_empty() {
+            overrides.remove(host);
+        } else {
+            overrides.insert(host.to_owned(), addrs);
+        }
+    }
+
+    /// Drops cached lookups, overrides and remembered families so the next request re-resolves.
+    /// Pooled connections to the old addresses stay open until they idle out.
+    pub fn flush() {
+        CACHE.lock().clear();
+        OVERRIDES.write().clear();
+        CONNECTED_FAMILIES.lock().clear();
+    }
+
+    /// Called by the connector layer once a new connection to `host` is up
+    pub fn connected(host: &str, family: AddressFamily) {
+        CONNECTED_FAMILIES.lock().insert(host.to_owned(), (family, Instant::now()));
+    }
+
+    /// Family the connector tries first for `host`: the one its last connection was made
+    /// over when `remember` is set and that was recent, else `preferred`
+    pub(crate) fn first_family(host: &str, preferred: Option<AddressFamily>, remember: bool) -> Option<AddressFamily> {
+        let connected = CONNECTED_FAMILIES.lock().get(host).copied();
+        match connected {
+            Some((family, at)) if remember && at.elapsed() < CONNECTED_FAMILY_TTL => Some(family),
+            _ => preferred,
+        }
+    }
+}
+
+impl Resolve for DnsResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let host = name.as_str().to_owned();
+        let recorder = Trace::current();
+        let preferred = {
+            let settings = ClientConfig::settings();
+            Self::first_family(&host, settings.preferred_family, settings.happy_eyeballs_delay_ms.is_some())
+        };
+        Box::pin(async move {
+            let started = Instant::now();
+            let addrs = Self::lookup(&host).await;
+            if let Some(recorder) = recorder {
+                recorder.record("dns", started);
+            }
+            let mut addrs = addrs?;
+            // The connector tries the first address's family first and races the other later
+            if let Some(preferred) = preferred {
+                addrs.sort_by_key(|addr| AddressFamily::of(addr) != preferred);
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[tokio::test]
+    async fn slow_lookup_fails_promptly_with_dns_timeout() {
+        let stalled = async {
+            tokio::time::sleep(Duration::from_secs(10)).await;
+            Ok(std::iter::empty())
+        };
+        let started = Instant::now();
+        let error = DnsResolver::bounded("slow.test", 100, stalled).await.unwrap_err();
+        assert!(started.elapsed() < Duration::from_secs(1));
+        let code = error.get_ref().and_then(|inner| inner.downcast_ref::<HttpError>()).map(|error| error.code.as_str());
+        assert_eq!(code, Some("DNS_TIMEOUT"));
+    }
+
+    #[test]
+    fn connected_family_goes_first_only_when_remembered() {
+        DnsResolver::connected("dual-stack.test", AddressFamily::Ipv6);
+        let first = |remember| DnsResolver::first_family("dual-stack.test", Some(AddressFamily::Ipv4), remember);
+        assert_eq!(first(true), Some(AddressFamily::Ipv6));
+        assert_eq!(first(false), Some(AddressFamily::Ipv4));
+        assert_eq!(DnsResolver::first_family("unseen.test", None, true), None);
+    }
+}
@@ -0,0 +1,194 @@
+use crate::file_utils::FileUtils;
+use crate::json::json;
+use crate::models::{DownloadInfo, HttpError};
+
+use anyhow::Result;
+use futures::channel::oneshot;
+use futures_util::StreamExt;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use reqwest::header::{HeaderMap, HeaderValue, CONTENT_RANGE, ETAG, IF_RANGE, LAST_MODIFIED, RANGE};
+use reqwest::Response;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::fs::OpenOptions;
+use tokio::io::AsyncWriteExt;
+
+/// `If-Range` validator (ETag, else Last-Modified) of the last response written per path
+static VALIDATORS: Lazy<Mutex<HashMap<String, String>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Pause signal of a running download, tagged with its registration id
+type PauseSender = (u64, oneshot::Sender<()>);
+
+/// Pause signal of the download currently writing each path
+static PAUSES: Lazy<Mutex<HashMap<String, PauseSender>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+static NEXT_PAUSE_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Streams response bodies to files, with safe `Range` + `If-Range` resumption
+pub struct Download;
+
+impl Download {
+    /// Adds `Range` / `If-Range` to resume into `path` and returns the offset to resume from.
+    /// Without a validator (`if_range`, or one remembered from an earlier download to the same
+    /// path) nothing is added and the download restarts, since a bare `Range` could splice two
+    /// versions of the resource together.
+    pub async fn apply_resume_headers(headers: &mut HeaderMap, path: &str, if_range: Option<&str>) -> Result<u64> {
+        let existing = match tokio::fs::metadata(path).await {
+            Ok(metadata) => metadata.len(),
+            Err(_) => return Ok(0),
+        };
+        let validator = match if_range {
+            Some(validator) => Some(validator.to_owned()),
+            None => VALIDATORS.lock().get(path).cloned(),
+        };
+        let (Some(validator), true) = (validator, existing > 0) else { return Ok(0) };
+
+        headers.insert(RANGE, HeaderValue::from_str(&format!("bytes={}-", existing))?);
+        headers.insert(IF_RANGE, HeaderValue::from_str(&validator)?);
+        Ok(existing)
+    }
+
+    /// Stops the download writing `path`, keeping what it wrote so far. The request then
+    /// succeeds with `download.paused`, and a later `resume_download` continues from the
+    /// file's size. Returns false if no download is writing `path`.
+    pub fn pause(path: &str) -> bool {
+        match PAUSES.lock().remove(path) {
+            Some((_, sender)) => sender.send(()).is_ok(),
+            None => false,
+        }
+    }
+
+    /// Writes the body of a 200 or 206 to `path`. A 206 is appended at `resume_from`; a 200
+    /// means the resource changed (or the server ignored `Range`), so the file is restarted.
+    /// A failed download deletes a fresh file, also when the request is dropped mid-body
+    /// (read timeout, cancel); a paused one (see `pause`) is flushed and kept.
+    pub async fn write_body(response: Response, path: &str, resume_from: u64) -> Result<DownloadInfo> {
+        let resumed = resume_from > 0 && response.status().as_u16() == 206;
+        if resumed {
+            Self::check_content_range(response.headers(), resume_from)?;
+        }
+        let validator = [ETAG, LAST_MODIFIED]
+            .iter()
+            .find_map(|name| response.headers().get(name))
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_owned);
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(resumed)
+            .truncate(!resumed)
+            .open(path)
+            .await
+            .map_err(|e| FileUtils::io_error(path, e))?;
+        // A fresh file only holds a broken prefix; a resumed one keeps its valid part
+        let partial = PartialFile { path, armed: !resumed };
+
+        let mut pause = PauseGuard::register(path);
+        let mut paused = false;
+        let mut bytes_written = 0u64;
+        let mut stream = response.bytes_stream();
+        let result: Result<()> = async {
+            loop {
+                tokio::select! {
+                    biased;
+                    Ok(()) = &mut pause.receiver => {
+                        paused = true;
+                        break;
+                    }
+                    chunk = stream.next() => {
+                        let Some(chunk) = chunk else { break };
+                        let chunk = chunk?;
+                        file.write_all(&chunk).await.map_err(|e| FileUtils::io_error(path, e))?;
+                        bytes_written += chunk.len() as u64;
+                    }
+                }
+            }
+            file.flush().await.map_err(|e| FileUtils::io_error(path, e))?;
+            Ok(())
+        }
+        .await;
+        drop(pause);
+        drop(file);
+        result?;
+        partial.keep();
+
+        match validator {
+            Some(validator) => VALIDATORS.lock().insert(path.to_owned(), validator),
+            None => VALIDATORS.lock().remove(path),
+        };
+
+        Ok(DownloadInfo {
+            path: path.to_owned(),
+            bytes_written,
+            file_size: if resumed { resume_from + bytes_written } else { bytes_written },
+            resumed,
+            paused,
+        })
+    }
+
+    /// The 206 must continue exactly where the file ends
+    fn check_content_range(headers: &HeaderMap, resume_from: u64) -> Result<()> {
+        let start = headers
+            .get(CONTENT_RANGE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("bytes "))
+            .and_then(|range| range.split('-').next())
+            .and_then(|start| start.trim().parse::<u64>().ok());
+
+        match start {
+            Some(start) if start == resume_from => Ok(()),
+            _ => Err(HttpError::new("RANGE_MISMATCH", format!("206 response does not continue at byte {}", resume_from))
+                .with_details(json!({ "resume_from": resume_from }))
+                .into()),
+        }
+    }
+}
+
+/// Deletes the file being written unless `keep` is called, so a download that fails or is
+/// dropped leaves nothing behind
+struct PartialFile<'a> {
+    path: &'a str,
+    armed: bool,
+}
+
+impl PartialFile<'_> {
+    fn keep(mut self) {
+        self.armed = false;
+    }
+}
+
+impl Drop for PartialFile<'_> {
+    fn drop(&mut self) {
+        if self.armed {
+            let _ = std::fs::remove_file(self.path);
+        }
+    }
+}
+
+/// Registers the pause signal for one download and unregisters it when the write ends
+struct PauseGuard<'a> {
+    path: &'a str,
+    id: u64,
+    receiver: oneshot::Receiver<()>,
+}
+
+impl<'a> PauseGuard<'a> {
+    fn register(path: &'a str) -> Self {
+        let id = NEXT_PAUSE_ID.fetch_add(1, Ordering::Relaxed);
+        let (sender, receiver) = oneshot::channel();
+        PAUSES.lock().insert(path.to_owned(), (id, sender));
+        Self { path, id, receiver }
+    }
+}
+
+impl Drop for PauseGuard<'_> {
+    fn drop(&mut self) {
+        let mut pauses = PAUSES.lock();
+        // A newer download to the same path may have replaced this entry
+        if pauses.get(self.path).is_some_and(|(id, _)| *id == self.id) {
+            pauses.remove(self.path);
+        }
+    }
+}
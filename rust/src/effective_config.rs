@@ -0,0 +1,110 @@
+use crate::client_config::{self, ClientConfig, ClientSettings};
+use crate::concurrency_limit::ConcurrencyLimit;
+use crate::network_simulation::NetworkSimulation;
+
+use serde::Serialize;
+
+/// What is actually in effect right now (see the `get_effective_config` FFI). Most settings
+/// are read per request, but client-level ones only when a client is built, so those may
+/// differ between `settings` and `shared_client_settings`; `pending_settings` lists them.
+#[derive(Debug, Serialize)]
+pub struct EffectiveConfig {
+    /// Current `ClientSettings`, as last passed to `configure_http_client`
+    pub settings: ClientSettings,
+    /// Settings the shared client was built with, `None` before `init_http_client`
+    pub shared_client_settings: Option<ClientSettings>,
+    /// Client-level settings changed since the shared client was built; they apply on the
+    /// next `pause_connections` / `resume_connections`, which rebuild it
+    pub pending_settings: Vec<&'static str>,
+    pub shared_client: SharedClientConfig,
+    /// `pause_connections` is in effect
+    pub connections_paused: bool,
+    pub offline: bool,
+    /// Set while `set_network_simulation` is on
+    pub network_simulation: Option<NetworkSimulation>,
+    /// Whether `init_http_client` (or the first request) has started the runtime
+    pub runtime_started: bool,
+    pub runtime_worker_threads: usize,
+    pub runtime_max_blocking_threads: usize,
+    /// See `set_max_concurrency`
+    pub max_concurrency: usize,
+    pub requests_in_flight: usize,
+}
+
+/// Fixed builder options of the shared client (`ClientConfig::shared_mobile_client_builder`)
+#[derive(Debug, Serialize)]
+pub struct SharedClientConfig {
+    pub pool_idle_timeout_ms: u64,
+    pub pool_max_idle_per_host: usize,
+    pub connect_timeout_ms: u64,
+    pub timeout_ms: u64,
+    /// `None` while paused
+    pub tcp_keepalive_ms: Option<u64>,
+    pub http2_keep_alive_interval_ms: Option<u64>,
+    pub http2_keep_alive_timeout_ms: u64,
+    pub min_tls_version: &'static str,
+    pub max_redirects: usize,
+    /// Requests are sent as HTTP/2 unless `alpn_protocols` leaves `h2` out
+    pub forced_http2: bool,
+    pub proxy: bool,
+    /// Cookies are kept in the shared `CookieJar`
+    pub cookie_store: bool,
+    /// Certificates trusted through `add_root_certificate`, on top of the webpki roots
+    pub custom_root_certificates: usize,
+    /// `set_accept_invalid_certs` is on
+    pub accept_invalid_certs: bool,
+}
+
+impl EffectiveConfig {
+    pub fn current(paused: bool, offline: bool, runtime_started: bool, runtime_worker_threads: usize, runtime_max_blocking_threads: usize) -> Self {
+        let settings = ClientConfig::settings().clone();
+        let shared_client_settings = ClientConfig::shared_client_settings();
+        let pending_settings = shared_client_settings
+            .as_ref()
+            .map_or_else(Vec::new, |built| Self::changed_client_settings(built, &settings));
+        let millis = |duration: std::time::Duration| duration.as_millis() as u64;
+
+        Self {
+            settings,
+            shared_client_settings,
+            pending_settings,
+            shared_client: SharedClientConfig {
+                pool_idle_timeout_ms: millis(client_config::SHARED_POOL_IDLE_TIMEOUT),
+                pool_max_idle_per_host: client_config::SHARED_POOL_MAX_IDLE_PER_HOST,
+                connect_timeout_ms: millis(client_config::SHARED_CONNECT_TIMEOUT),
+                timeout_ms: millis(client_config::SHARED_TIMEOUT),
+                tcp_keepalive_ms: (!paused).then(|| millis(client_config::TCP_KEEPALIVE)),
+                http2_keep_alive_interval_ms: (!paused).then(|| millis(client_config::HTTP2_KEEP_ALIVE_INTERVAL)),
+                http2_keep_alive_timeout_ms: millis(client_config::HTTP2_KEEP_ALIVE_TIMEOUT),
+                min_tls_version: "1.2",
+                max_redirects: client_config::SHARED_MAX_REDIRECTS,
+                forced_http2: ClientConfig::offers_h2(),
+                proxy: false,
+                cookie_store: true,
+                custom_root_certificates: ClientConfig::root_certificate_count(),
+                accept_invalid_certs: ClientConfig::accepts_invalid_certs(),
+            },
+            connections_paused: paused,
+            offline,
+            network_simulation: NetworkSimulation::current(),
+            runtime_started,
+            runtime_worker_threads,
+            runtime_max_blocking_threads,
+            max_concurrency: ConcurrencyLimit::limit(),
+            requests_in_flight: ConcurrencyLimit::in_flight(),
+        }
+    }
+
+    /// The `ClientSettings` fields read at client build time that differ
+    fn changed_client_settings(built: &ClientSettings, current: &ClientSettings) -> Vec<&'static str> {
+        [
+            ("tcp_user_timeout_ms", built.tcp_user_timeout_ms != current.tcp_user_timeout_ms),
+            ("max_response_header_bytes", built.max_response_header_bytes != current.max_response_header_bytes),
+            ("alpn_protocols", built.alpn_protocols != current.alpn_protocols),
+            ("http2", built.http2 != current.http2),
+        ]
+        .into_iter()
+        .filter_map(|(name, changed)| changed.then_some(name))
+        .collect()
+    }
+}
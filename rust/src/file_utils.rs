@@ -0,0 +1,98 @@
+use crate::json::json;
+use crate::models::HttpError;
+use crate::progress::UploadProgress;
+
+use bytes::Bytes;
+use futures_util::stream::{self, BoxStream, StreamExt};
+use std::io::{self, ErrorKind};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::fs::File;
+use tokio::io::AsyncReadExt;
+use tokio_util::io::ReaderStream;
+
+pub struct FileUtils;
+
+impl FileUtils {
+    /// Opens `path` for streaming and returns the stream with the file size
+    pub async fn open_stream(path: &str) -> Result<(ReaderStream<File>, u64), HttpError> {
+        let file = File::open(path).await.map_err(|e| Self::io_error(path, e))?;
+        let size = file.metadata().await.map_err(|e| Self::io_error(path, e))?.len();
+        Ok((ReaderStream::new(file), size))
+    }
+
+    /// Streams `paths` back to back as one body and returns it with the summed size.
+    /// Every file is sized up front, so a missing one fails before anything is sent; each is
+    /// opened only once the previous one is done. A file that goes missing or shrinks by
+    /// then aborts the body with its path (`FILE_NOT_FOUND`, `FILE_CHANGED`, ...); bytes a
+    /// file gained since it was sized are not sent. `upload_stream_id` reports progress per file.
+    pub async fn concat_stream(paths: &[&str], upload_stream_id: Option<i64>) -> Result<(BoxStream<'static, io::Result<Bytes>>, u64), HttpError> {
+        let mut files = Vec::with_capacity(paths.len());
+        for path in paths {
+            files.push((path.to_string(), Self::file_size(path).await?));
+        }
+        let total = files.iter().map(|(_, size)| size).sum();
+        let progress = upload_stream_id.map(|id| UploadProgress::new(id, total));
+
+        let chunks = stream::iter(files)
+            .flat_map(move |(path, size)| {
+                let chunks = Self::sized_stream(path.clone(), size);
+                match &progress {
+                    Some(progress) => progress.track(chunks, Some(path), size),
+                    None => chunks,
+                }
+            })
+            .boxed();
+        Ok((chunks, total))
+    }
+
+    /// Opens `path` when first polled and yields exactly `size` bytes, or an error naming it
+    fn sized_stream(path: String, size: u64) -> BoxStream<'static, io::Result<Bytes>> {
+        let read = Arc::new(AtomicU64::new(0));
+        let counter = Arc::clone(&read);
+        let open_path = path.clone();
+
+        let contents = stream::once(async move {
+            let file = File::open(&open_path).await.map_err(|e| io::Error::other(Self::io_error(&open_path, e)));
+            (file, open_path)
+        })
+        .flat_map(move |(file, path)| match file {
+            Ok(file) => ReaderStream::new(file.take(size))
+                .map(move |chunk| chunk.map_err(|e| io::Error::other(Self::io_error(&path, e))))
+                .boxed(),
+            Err(error) => stream::once(async move { Err(error) }).boxed(),
+        })
+        .inspect(move |chunk| {
+            if let Ok(chunk) = chunk {
+                counter.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+            }
+        });
+        let shortfall = stream::once(async move {
+            let read = read.load(Ordering::Relaxed);
+            (read < size).then(|| {
+                let error = HttpError::new("FILE_CHANGED", format!("{}: shrank from {} to {} bytes while uploading", path, size, read))
+                    .with_details(json!({ "path": path, "expected": size, "read": read }));
+                Err(io::Error::other(error))
+            })
+        })
+        .filter_map(std::future::ready);
+
+        contents.chain(shortfall).boxed()
+    }
+
+    /// Size of `path` without opening it
+    pub async fn file_size(path: &str) -> Result<u64, HttpError> {
+        Ok(tokio::fs::metadata(path).await.map_err(|e| Self::io_error(path, e))?.len())
+    }
+
+    /// Maps an I/O error on `path` to `FILE_NOT_FOUND`, `PERMISSION_DENIED` or `FILE_ERROR`
+    pub fn io_error(path: &str, error: io::Error) -> HttpError {
+        let code = match error.kind() {
+            ErrorKind::NotFound => "FILE_NOT_FOUND",
+            ErrorKind::PermissionDenied => "PERMISSION_DENIED",
+            _ => "FILE_ERROR",
+        };
+        HttpError::new(code, format!("{}: {}", path, error))
+            .with_details(json!({ "path": path }))
+    }
+}
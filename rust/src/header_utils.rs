@@ -1,8 +1,54 @@
-use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use crate::client_config::ClientConfig;
+use crate::models::{AcceptEntry, ServerTimingMetric, SkippedHeader};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, ACCEPT_ENCODING};
 use std::collections::HashMap;
 use std::str::FromStr;
 use anyhow::Result;
 
+/// Common media types for content negotiation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AcceptType {
+    Json,
+    JsonApi,
+    Xml,
+    Html,
+    Text,
+    Protobuf,
+    OctetStream,
+    Any,
+}
+
+impl AcceptType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AcceptType::Json => "application/json",
+            AcceptType::JsonApi => "application/vnd.api+json",
+            AcceptType::Xml => "application/xml",
+            AcceptType::Html => "text/html",
+            AcceptType::Text => "text/plain",
+            AcceptType::Protobuf => "application/x-protobuf",
+            AcceptType::OctetStream => "application/octet-stream",
+            AcceptType::Any => "*/*",
+        }
+    }
+
+    /// Resolves the short aliases accepted over FFI (`json`, `json_api`, `xml`, ...)
+    pub fn from_alias(alias: &str) -> Option<Self> {
+        let accept_type = match alias {
+            "json" => AcceptType::Json,
+            "json_api" => AcceptType::JsonApi,
+            "xml" => AcceptType::Xml,
+            "html" => AcceptType::Html,
+            "text" => AcceptType::Text,
+            "protobuf" => AcceptType::Protobuf,
+            "binary" => AcceptType::OctetStream,
+            "any" => AcceptType::Any,
+            _ => return None,
+        };
+        Some(accept_type)
+    }
+}
+
 pub struct HeaderUtils;
 
 impl HeaderUtils {
@@ -34,6 +80,76 @@ impl HeaderUtils {
         Ok(header_map)
     }
 
+    /// Builds a quality-weighted `Accept` value, e.g. `application/json;q=0.9, */*;q=0.1`
+    /// Entries may be aliases (see `AcceptType::from_alias`) or full `type/subtype` media types
+    pub fn build_accept_value(entries: &[AcceptEntry]) -> Result<HeaderValue> {
+        if entries.is_empty() {
+            return Err(anyhow::anyhow!("Accept list cannot be empty"));
+        }
+
+        let mut value = String::with_capacity(entries.len() * 24);
+        for (i, entry) in entries.iter().enumerate() {
+            let media_type = match AcceptType::from_alias(entry.media_type) {
+                Some(accept_type) => accept_type.as_str(),
+                None => Self::validate_media_type(entry.media_type)?,
+            };
+
+            if i > 0 {
+                value.push_str(", ");
+            }
+            value.push_str(media_type);
+
+            if let Some(q) = entry.q {
+                if !(0.0..=1.0).contains(&q) {
+                    return Err(anyhow::anyhow!("Accept quality for {} must be within 0..=1, got {}", media_type, q));
+                }
+                // RFC 9110 allows at most three decimals
+                let q = format!("{:.3}", q);
+                value.push_str(";q=");
+                value.push_str(q.trim_end_matches('0').trim_end_matches('.'));
+            }
+        }
+
+        Ok(HeaderValue::from_str(&value)?)
+    }
+
+    /// Validates an `Accept-Language` value such as `de-CH, de;q=0.9, *;q=0.5`
+    pub fn accept_language_value(value: &str) -> Result<HeaderValue> {
+        let is_range = |range: &str| {
+            range == "*"
+                || (!range.is_empty()
+                    && range.split('-').all(|tag| (1..=8).contains(&tag.len()) && tag.bytes().all(|b| b.is_ascii_alphanumeric())))
+        };
+
+        for entry in value.split(',') {
+            let (range, params) = match entry.split_once(';') {
+                Some((range, params)) => (range.trim(), Some(params.trim())),
+                None => (entry.trim(), None),
+            };
+            let valid_q = match params {
+                Some(params) => params.strip_prefix("q=").is_some_and(|q| q.parse::<f32>().is_ok_and(|q| (0.0..=1.0).contains(&q))),
+                None => true,
+            };
+            if !is_range(range) || !valid_q {
+                return Err(anyhow::anyhow!("Invalid Accept-Language entry: {:?}", entry.trim()));
+            }
+        }
+
+        Ok(HeaderValue::from_str(value)?)
+    }
+
+    fn validate_media_type(media_type: &str) -> Result<&str> {
+        let is_token = |part: &str| {
+            !part.is_empty()
+                && part.bytes().all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+        };
+
+        match media_type.split_once('/') {
+            Some((main, sub)) if is_token(main) && is_token(sub) => Ok(media_type),
+            _ => Err(anyhow::anyhow!("Invalid media type in Accept list: {}", media_type)),
+        }
+    }
+
     /// Returns optimized header names using static references for common headers
     /// This avoids string allocations for frequently used headers
     fn get_optimized_header_name(key: &str) -> Result<HeaderName> {
@@ -43,6 +159,7 @@ impl HeaderUtils {
             "user-agent" => reqwest::header::USER_AGENT,
             "accept" => reqwest::header::ACCEPT,
             "accept-encoding" => reqwest::header::ACCEPT_ENCODING,
+            "accept-language" => reqwest::header::ACCEPT_LANGUAGE,
             "cache-control" => reqwest::header::CACHE_CONTROL,
             "content-length" => reqwest::header::CONTENT_LENGTH,
             "host" => reqwest::header::HOST,
@@ -63,6 +180,7 @@ impl HeaderUtils {
             "x-device-id" => HeaderName::from_static("x-device-id"),
             "x-app-version" => HeaderName::from_static("x-app-version"),
             "x-platform" => HeaderName::from_static("x-platform"),
+            "idempotency-key" => HeaderName::from_static(crate::method_utils::IDEMPOTENCY_KEY),
 
             // Fallback to dynamic allocation for uncommon headers
             _ => HeaderName::from_str(key)?,
@@ -71,18 +189,142 @@ impl HeaderUtils {
         Ok(header_name)
     }
 
+    /// Drops codings this build cannot decode (and `*`) from a caller-set `Accept-Encoding`,
+    /// keeping their q-values, so the server never picks one that would come back as
+    /// garbage; `identity` is sent if nothing is left
+    pub fn restrict_accept_encoding(headers: &mut HeaderMap) {
+        let Some(value) = headers.get(ACCEPT_ENCODING).and_then(|value| value.to_str().ok()) else { return };
+        let supported = ClientConfig::supported_encodings();
+        let entries: Vec<&str> = value.split(',').map(str::trim).filter(|entry| !entry.is_empty()).collect();
+        let kept: Vec<&str> = entries
+            .iter()
+            .copied()
+            .filter(|entry| {
+                let coding = entry.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+                coding == "identity" || supported.contains(&coding.as_str())
+            })
+            .collect();
+        if kept.len() == entries.len() {
+            return;
+        }
+        let restricted = if kept.is_empty() { "identity".to_owned() } else { kept.join(", ") };
+        if let Ok(restricted) = HeaderValue::from_str(&restricted) {
+            headers.insert(ACCEPT_ENCODING, restricted);
+        }
+    }
+
     /// Converts response headers to a HashMap efficiently
     /// Skips invalid UTF-8 headers to avoid crashes on mobile
     pub fn extract_response_headers(response_headers: &HeaderMap) -> HashMap<String, String> {
+        Self::extract_response_headers_strict(response_headers).0
+    }
+
+    /// Same as `extract_response_headers`, but also reports every header it had to skip.
+    /// Header names are already validated by hyper, so in practice only values are
+    /// rejected (non-UTF-8 / opaque bytes from misbehaving upstreams or proxies).
+    pub fn extract_response_headers_strict(
+        response_headers: &HeaderMap,
+    ) -> (HashMap<String, String>, Vec<SkippedHeader>) {
         let mut headers = HashMap::with_capacity(response_headers.len());
+        let mut skipped = Vec::new();
 
         for (k, v) in response_headers.iter() {
-            if let Ok(value_str) = v.to_str() {
-                headers.insert(k.as_str().to_owned(), value_str.to_owned());
+            match v.to_str() {
+                Ok(value_str) => {
+                    headers.insert(k.as_str().to_owned(), value_str.to_owned());
+                }
+                Err(_) => skipped.push(SkippedHeader {
+                    name: k.as_str().to_owned(),
+                    reason: "value is not visible ASCII".to_owned(),
+                }),
             }
-            // Skip invalid headers instead of failing
         }
 
-        headers
+        (headers, skipped)
+    }
+
+    /// Every header with a visible-ASCII value, in `HeaderMap` iteration order (see
+    /// `HttpResponse::headers_ordered`); skipped values are the ones `extract_response_headers` drops
+    pub fn extract_ordered_headers(response_headers: &HeaderMap) -> Vec<(String, String)> {
+        response_headers
+            .iter()
+            .filter_map(|(k, v)| Some((k.as_str().to_owned(), v.to_str().ok()?.to_owned())))
+            .collect()
+    }
+
+    /// Every `Set-Cookie` value with a visible-ASCII value, in wire order
+    pub fn extract_set_cookies(response_headers: &HeaderMap) -> Vec<String> {
+        response_headers
+            .get_all(reqwest::header::SET_COOKIE)
+            .iter()
+            .filter_map(|value| Some(value.to_str().ok()?.to_owned()))
+            .collect()
+    }
+
+    /// Parses every `Server-Timing` header (RFC: `name;dur=?;desc=?`, comma separated).
+    /// Entries with an invalid name or a non-numeric `dur` are skipped; unknown params are ignored.
+    pub fn parse_server_timing(response_headers: &HeaderMap) -> Vec<ServerTimingMetric> {
+        response_headers
+            .get_all("server-timing")
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| Self::split_unquoted(value, ','))
+            .filter_map(Self::parse_server_timing_metric)
+            .collect()
+    }
+
+    fn parse_server_timing_metric(entry: &str) -> Option<ServerTimingMetric> {
+        let mut parts = Self::split_unquoted(entry, ';').into_iter();
+        let name = parts.next()?.trim();
+        if name.is_empty() || HeaderName::from_str(name).is_err() {
+            return None;
+        }
+        let mut metric = ServerTimingMetric { name: name.to_owned(), duration_ms: None, description: None };
+        for param in parts {
+            let (key, value) = param.split_once('=').unwrap_or((param, ""));
+            let value = value.trim();
+            match key.trim().to_ascii_lowercase().as_str() {
+                "dur" if metric.duration_ms.is_none() => {
+                    metric.duration_ms = Some(value.parse::<f64>().ok().filter(|dur| dur.is_finite())?)
+                }
+                "desc" if metric.description.is_none() => metric.description = Some(Self::unquote(value)),
+                _ => {}
+            }
+        }
+        Some(metric)
+    }
+
+    /// Splits on `separator` outside double-quoted strings
+    fn split_unquoted(value: &str, separator: char) -> Vec<&str> {
+        let mut parts = Vec::new();
+        let (mut start, mut quoted, mut escaped) = (0, false, false);
+        for (i, c) in value.char_indices() {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' if quoted => escaped = true,
+                '"' => quoted = !quoted,
+                c if c == separator && !quoted => {
+                    parts.push(&value[start..i]);
+                    start = i + 1;
+                }
+                _ => {}
+            }
+        }
+        parts.push(&value[start..]);
+        parts.into_iter().filter(|part| !part.trim().is_empty()).collect()
+    }
+
+    fn unquote(value: &str) -> String {
+        match value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+            Some(inner) => {
+                let mut out = String::with_capacity(inner.len());
+                let mut chars = inner.chars();
+                while let Some(c) = chars.next() {
+                    out.push(if c == '\\' { chars.next().unwrap_or(c) } else { c });
+                }
+                out
+            }
+            None => value.to_owned(),
+        }
     }
-}
\ No newline at end of file
+}
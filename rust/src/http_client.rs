@@ -1,65 +1,1009 @@
-use crate::models::{HttpRequest, HttpResponse};
-use crate::client_config::ClientConfig;
+use crate::models::{HedgeInfo, HttpError, HttpRequest, HttpResponse, UrlAttempt, DEFAULT_RETRY_BASE_DELAY_MS, DEFAULT_RETRY_STATUSES};
+#[cfg(feature = "debug-logging")]
+use crate::body_logging::BodyLogger;
+use crate::body_handles::BodyHandles;
+use crate::cache::{CacheMode, ResponseCache, ResponseSource};
+use crate::cancellation::Cancellation;
+use crate::client_config::{AddressFamily, ClientConfig};
+use crate::concurrency_limit::ConcurrencyLimit;
+use crate::connect_timeout::ConnectTimeout;
+use crate::content_digest::ContentDigest;
+use crate::credentials::CredentialStore;
+use crate::dns::DnsResolver;
+use crate::download::Download;
+use crate::file_utils::FileUtils;
 use crate::header_utils::HeaderUtils;
+use crate::json::{self, json};
+#[allow(unused_imports)] // empty with `serde-json`
+use crate::json::prelude::*;
+use crate::memory_budget::{MemoryBudget, Reservation};
 use crate::method_utils::MethodUtils;
+use crate::metrics::{ConnectionStats, RequestMetrics};
+use crate::multipart_utils::MultipartUtils;
+use crate::network_simulation::NetworkSimulation;
+use crate::progress::{RequestSent, UploadProgress};
+use crate::redirects::{RedirectLimit, RedirectLog, Redirects};
+use crate::replay::RequestReplay;
+use crate::response_stream::ResponseStream;
+use crate::sigv4::{self, SigV4};
+use crate::stream_lanes::StreamLanes;
+use crate::trace::Trace;
 use crate::shared_client::MOBILE_CLIENT;
+use crate::transport::{ReqwestTransport, Transport};
+use crate::upload_stream::UploadStream;
+use crate::write_timeout::WriteTimeout;
 
-use reqwest::{Client, Version};
+use reqwest::header::{HeaderMap, HeaderValue, ACCEPT, ACCEPT_ENCODING, ACCEPT_LANGUAGE, AUTHORIZATION, CONTENT_LENGTH, CONTENT_TYPE};
+use reqwest::{Body, Client, Method, RequestBuilder, Response, Url, Version};
+use std::collections::HashMap;
+use std::future::Future;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use anyhow::Result;
-use futures_util::stream::{self, StreamExt};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use bytes::Bytes;
+use futures_util::stream::{self, FuturesUnordered, StreamExt};
+use once_cell::sync::Lazy;
+use parking_lot::{Mutex, RwLock};
+
+/// How `collect_response` reads headers and body
+#[derive(Debug, Clone, Copy, Default)]
+struct CollectOptions<'a> {
+    strict_headers: bool,
+    ordered_headers: bool,
+    /// Chunked read that keeps the received bytes on timeout (see `partial_body_on_timeout`)
+    partial_timeout_ms: Option<u64>,
+    raw_body: bool,
+    /// Write 200/206 bodies to this path, resuming at the offset
+    download: Option<(&'a str, u64)>,
+    /// Post the body to the Dart port instead (see `response_stream.rs`)
+    stream_id: Option<i64>,
+    status_line: bool,
+    headers_only: bool,
+    base64_body: bool,
+}
+
+impl<'a> CollectOptions<'a> {
+    fn for_request(request: &HttpRequest<'a>, resume_from: u64) -> Self {
+        Self {
+            strict_headers: request.strict_headers,
+            ordered_headers: request.ordered_headers,
+            partial_timeout_ms: request.partial_body_on_timeout.then_some(request.timeout_ms),
+            raw_body: request.raw_body || request.passthrough,
+            download: request.download_path.filter(|_| !request.headers_only).map(|path| (path, resume_from)),
+            stream_id: request.response_stream_id.filter(|_| !request.headers_only),
+            status_line: request.raw_status_line,
+            headers_only: request.headers_only,
+            base64_body: request.base64_body,
+        }
+    }
+}
+
+/// Wait before connect attempt `n + 1` is `n` times this (see `connect_attempts`)
+const CONNECT_RETRY_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Longest wait between two `max_retries` attempts, `Retry-After` included
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// Shared by every `passthrough` request, built on first use; swapped by `on_network_change`
+static PASSTHROUGH_CLIENT: Lazy<RwLock<Arc<Client>>> = Lazy::new(|| RwLock::new(Arc::new(ClientConfig::build_passthrough_client())));
+
+/// Shared by every `preserve_header_case` request, built on first use; swapped by `on_network_change`
+static TITLE_CASE_CLIENT: Lazy<RwLock<Arc<Client>>> = Lazy::new(|| RwLock::new(Arc::new(ClientConfig::build_title_case_client())));
+
+/// Shared by every `http3_only` request, built on first use; swapped by `on_network_change`
+#[cfg(feature = "http3")]
+static HTTP3_CLIENT: Lazy<RwLock<Arc<Client>>> = Lazy::new(|| RwLock::new(Arc::new(ClientConfig::build_http3_client())));
+
+/// Clients for `proxy_url` requests, one pool per proxy, credentials and client flavor;
+/// emptied by `on_network_change`, and once `MAX_PROXY_CLIENTS` are cached
+static PROXY_CLIENTS: Lazy<Mutex<HashMap<ProxyKey, Arc<Client>>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+const MAX_PROXY_CLIENTS: usize = 16;
+
+/// (proxy URL, username, password, passthrough, title case)
+type ProxyKey = (String, Option<String>, Option<String>, bool, bool);
+
+/// Shared by every `background` request, built on first use; swapped by `rebuild_clients`
+static BACKGROUND_CLIENT: Lazy<RwLock<Arc<Client>>> = Lazy::new(|| RwLock::new(Arc::new(ClientConfig::build_background_client())));
+
+/// See `HttpClient::set_offline`
+static OFFLINE: AtomicBool = AtomicBool::new(false);
 
 pub struct HttpClient {
-    client: Arc<Client>,
+    /// Swapped as a whole by `pause` / `resume`; requests keep the client they started with
+    client: RwLock<Arc<Client>>,
+    /// Further connections per host once the client's are at their stream limit
+    lanes: StreamLanes,
+    paused: AtomicBool,
+    transport: Arc<dyn Transport>,
 }
 
 impl HttpClient {
     /// Creates a new isolated HTTP client
     pub fn new() -> Self {
         Self {
-            client: Arc::new(ClientConfig::build_mobile_client()),
+            client: RwLock::new(Arc::new(ClientConfig::build_mobile_client())),
+            lanes: StreamLanes::new(),
+            paused: AtomicBool::new(false),
+            transport: Arc::new(ReqwestTransport),
         }
     }
 
     /// Returns a shared global client for max connection reuse
     pub fn shared() -> Self {
         Self {
-            client: Arc::new(
-                MOBILE_CLIENT.get_or_init(|| ClientConfig::build_shared_mobile_client()).clone()
-            ),
+            client: RwLock::new(Arc::new(
+                MOBILE_CLIENT.get_or_init(ClientConfig::build_shared_mobile_client).clone()
+            )),
+            lanes: StreamLanes::new(),
+            paused: AtomicBool::new(false),
+            transport: Arc::new(ReqwestTransport),
+        }
+    }
+
+    /// An isolated client whose requests are sent through `transport`, e.g. a
+    /// `MockTransport` so tests get canned responses without network
+    pub fn with_transport(transport: Arc<dyn Transport>) -> Self {
+        Self { transport, ..Self::new() }
+    }
+
+    fn client(&self) -> Arc<Client> {
+        self.client.read().clone()
+    }
+
+    /// Switches to a client without keep-alive pings for when the app is backgrounded.
+    /// Pings are fixed per connection, so the old pool is dropped: its idle connections
+    /// close once the requests still using them finish. No-op if already paused.
+    pub fn pause(&self) {
+        if !self.paused.swap(true, Ordering::AcqRel) {
+            *self.client.write() = Arc::new(ClientConfig::build_paused_mobile_client());
+            self.lanes.clear();
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Acquire)
+    }
+
+    /// Goes back to the shared client's keep-alive settings with a fresh pool (an isolated
+    /// `HttpClient::new` client comes back with the shared settings too). No-op unless paused.
+    pub fn resume(&self) {
+        if self.paused.swap(false, Ordering::AcqRel) {
+            *self.client.write() = Arc::new(ClientConfig::build_shared_mobile_client());
+            self.lanes.clear();
         }
     }
 
+    /// Recovery after the OS reports a network change (Wi-Fi <-> cellular, VPN up/down).
+    /// Under the client write lock, so no request can pick up the old pool meanwhile:
+    /// 1. every in-flight request is cancelled and fails with `NETWORK_CHANGED` (including
+    ///    downloads and hedged attempts; nothing is retried automatically);
+    /// 2. the DNS cache and host overrides are flushed;
+    /// 3. the client (and the passthrough, title-case and HTTP/3 clients, if built) is replaced by one with a fresh
+    ///    pool, keeping the paused state. Old connections close as the cancelled requests drop.
+    ///
+    /// Requests that start while this runs wait for the lock and go out on the new client.
+    /// `proxy_url` clients are dropped and rebuilt on next use. Background and `force_addr`
+    /// clients have no shared pool and are left alone.
+    /// Returns how many requests were cancelled.
+    pub fn on_network_change(&self) -> usize {
+        self.swap_clients(|| {
+            let cancelled = Cancellation::cancel_all("NETWORK_CHANGED");
+            DnsResolver::flush();
+            cancelled
+        })
+    }
+
+    /// Rebuilds every client after a TLS trust change (see `ClientConfig::add_root_certificates`),
+    /// as `on_network_change` does but without cancelling anything: requests in flight finish
+    /// on their old connections, later ones handshake with the new trust. The background
+    /// client is replaced too; `force_addr` clients are built per request anyway.
+    pub fn rebuild_clients(&self) {
+        self.swap_clients(|| {
+            if let Some(background) = Lazy::get(&BACKGROUND_CLIENT) {
+                *background.write() = Arc::new(ClientConfig::build_background_client());
+            }
+        })
+    }
+
+    /// Runs `before` under the write locks of the shared client and its built variants,
+    /// then replaces them (keeping the paused state) and drops the `proxy_url` clients
+    fn swap_clients<T>(&self, before: impl FnOnce() -> T) -> T {
+        let mut client = self.client.write();
+        let mut passthrough = Lazy::get(&PASSTHROUGH_CLIENT).map(|passthrough| passthrough.write());
+        let mut title_case = Lazy::get(&TITLE_CASE_CLIENT).map(|title_case| title_case.write());
+        #[cfg(feature = "http3")]
+        let mut http3 = Lazy::get(&HTTP3_CLIENT).map(|http3| http3.write());
+        let result = before();
+        PROXY_CLIENTS.lock().clear();
+        self.lanes.clear();
+        *client = Arc::new(if self.is_paused() {
+            ClientConfig::build_paused_mobile_client()
+        } else {
+            ClientConfig::build_shared_mobile_client()
+        });
+        if let Some(passthrough) = passthrough.as_mut() {
+            **passthrough = Arc::new(ClientConfig::build_passthrough_client());
+        }
+        if let Some(title_case) = title_case.as_mut() {
+            **title_case = Arc::new(ClientConfig::build_title_case_client());
+        }
+        #[cfg(feature = "http3")]
+        if let Some(http3) = http3.as_mut() {
+            **http3 = Arc::new(ClientConfig::build_http3_client());
+        }
+        result
+    }
+
     /// Executes a single HTTP request with optimized latency
     pub async fn execute_request(&self, request: HttpRequest<'_>) -> Result<HttpResponse> {
         let start_time = Instant::now();
+        let (url, json_response, body_as_handle, metadata) = (request.url, request.json_response, request.body_as_handle, request.metadata);
+        if let Some(id) = request.replay_id {
+            RequestReplay::retain(id, &request);
+        }
+        let method = request.method;
+        let result = if Self::is_offline() {
+            Err(HttpError::new("OFFLINE", format!("Offline mode is on, not sending request to {}", request.url)).into())
+        } else {
+            Trace::scope(method, url, Cancellation::run(request.group, request.cancel_handle, self.execute_bounded(request))).await
+        };
+        let result = match result {
+            Ok(response) if json_response => Self::parse_json_body(response, url),
+            Ok(response) if body_as_handle => Ok(Self::register_body(response)),
+            result => result,
+        };
+        RequestMetrics::record(&result, start_time.elapsed());
+        match (result, metadata) {
+            (Ok(mut response), Some(metadata)) => {
+                response.metadata = Some(metadata.to_owned());
+                Ok(response)
+            }
+            (Err(e), Some(metadata)) => {
+                let mut error = HttpError::from_error(&e);
+                error.metadata = Some(metadata.to_owned());
+                Err(error.into())
+            }
+            (result, None) => result,
+        }
+    }
+
+    /// While set, every request fails immediately with `OFFLINE` without touching the network.
+    /// `cancel_in_flight` also aborts running requests, which then fail with `OFFLINE`.
+    pub fn set_offline(offline: bool, cancel_in_flight: bool) {
+        OFFLINE.store(offline, Ordering::Release);
+        if offline && cancel_in_flight {
+            Cancellation::cancel_all("OFFLINE");
+        }
+    }
+
+    pub fn is_offline() -> bool {
+        OFFLINE.load(Ordering::Acquire)
+    }
+
+    /// Applies `total_timeout_ms` (and the background deadline) around the attempt, including
+    /// the waits for `ConcurrencyLimit` slots
+    async fn execute_bounded(&self, mut request: HttpRequest<'_>) -> Result<HttpResponse> {
+        let total_timeout_ms = Self::total_timeout_ms(&request);
+        let url = request.url;
+        let mut last_failure = None;
+        if total_timeout_ms == 0 {
+            return self.execute_with_retries(&mut request, &mut last_failure).await;
+        }
+
+        let total_timeout = Duration::from_millis(total_timeout_ms);
+        match tokio::time::timeout(total_timeout, self.execute_with_retries(&mut request, &mut last_failure)).await {
+            Ok(result) => result,
+            Err(_) => {
+                let mut details = json!({ "total_timeout_ms": total_timeout.as_millis() as u64 });
+                // The attempt that was cut short has no outcome; the last one that finished does
+                if let (Some(map), Some(failure)) = (details.as_object_mut(), last_failure) {
+                    map.insert("last_error".into(), json!({ "code": failure.code, "message": failure.message }));
+                }
+                Err(HttpError::new("TOTAL_TIMEOUT", format!("Request to {} exceeded total timeout of {}ms", url, total_timeout.as_millis()))
+                    .with_details(details)
+                    .into())
+            }
+        }
+    }
+
+    /// Repeats the request after transient failures with jittered exponential backoff
+    /// (see `HttpRequest::max_retries`), keeping each retried failure in `last_failure`
+    async fn execute_with_retries(&self, request: &mut HttpRequest<'_>, last_failure: &mut Option<HttpError>) -> Result<HttpResponse> {
+        if request.max_retries == 0 {
+            return self.execute_with_fallbacks(request).await;
+        }
 
         let method = MethodUtils::parse_method(request.method)?;
-        let mut req_builder = self.client.request(method, request.url.to_string());
+        let replayable = request.retry_non_idempotent || MethodUtils::is_retry_eligible(&method, &HeaderUtils::build_header_map(&request.headers)?);
+        let statuses = request.retry_statuses.clone().unwrap_or_else(|| DEFAULT_RETRY_STATUSES.to_vec());
+        let base_delay = Duration::from_millis(request.retry_base_delay_ms.unwrap_or(DEFAULT_RETRY_BASE_DELAY_MS));
+        let mut retries = 0;
+        loop {
+            let result = self.execute_with_fallbacks(request).await;
+            if retries < request.max_retries && Self::should_retry(&result, replayable, &statuses) {
+                *last_failure = Some(match &result {
+                    Ok(response) => HttpError::new("HTTP_STATUS", format!("Request to {} returned status {}", request.url, response.status_code)),
+                    Err(e) => HttpError::from_error(e),
+                });
+                retries += 1;
+                tokio::time::sleep(Self::retry_delay(base_delay, retries, &result)).await;
+                continue;
+            }
+            return match result {
+                Ok(mut response) => {
+                    response.retries = retries;
+                    Ok(response)
+                }
+                Err(e) if retries > 0 => {
+                    let mut error = HttpError::from_error(&e);
+                    let details = error.details.get_or_insert_with(|| json!({}));
+                    if let Some(map) = details.as_object_mut() {
+                        map.insert("retries".into(), retries.into());
+                    }
+                    Err(error.into())
+                }
+                Err(e) => Err(e),
+            };
+        }
+    }
+
+    /// `should_fall_back` for retries, with `statuses` instead of any 5xx
+    fn should_retry(result: &Result<HttpResponse>, replayable: bool, statuses: &[u16]) -> bool {
+        if !replayable {
+            return false;
+        }
+        match result {
+            Ok(response) => statuses.contains(&response.status_code),
+            Err(e) => match HttpError::code_of(e) {
+                "HTTP_STATUS" => Self::error_status(e).is_some_and(|status_code| statuses.contains(&status_code)),
+                code => Self::is_transient(code),
+            },
+        }
+    }
+
+    /// `base * 2^(retry - 1)`, drawn from its upper half so concurrent clients spread out,
+    /// raised to a 429/503 `Retry-After` in seconds and capped at `MAX_RETRY_DELAY`
+    fn retry_delay(base: Duration, retry: u32, result: &Result<HttpResponse>) -> Duration {
+        let backoff = base.saturating_mul(1 << (retry - 1).min(16)).min(MAX_RETRY_DELAY);
+        let jittered = backoff / 2 + backoff.mul_f64(rand::random::<f64>() / 2.0);
+        let retry_after = match result {
+            Ok(response) if matches!(response.status_code, 429 | 503) => response.headers.get("retry-after").and_then(|value| value.trim().parse::<u64>().ok()),
+            _ => None,
+        };
+        match retry_after {
+            Some(seconds) => jittered.max(Duration::from_secs(seconds)).min(MAX_RETRY_DELAY),
+            None => jittered,
+        }
+    }
+
+    /// Tries `url`, then each of `fallback_urls` while the previous one failed in a way
+    /// `should_fall_back` accepts (see `HttpRequest::fallback_urls`)
+    async fn execute_with_fallbacks(&self, request: &mut HttpRequest<'_>) -> Result<HttpResponse> {
+        if request.fallback_urls.is_empty() {
+            return self.execute_hedged(request).await;
+        }
+
+        let method = MethodUtils::parse_method(request.method)?;
+        let replayable = MethodUtils::is_retry_eligible(&method, &HeaderUtils::build_header_map(&request.headers)?);
+        let primary = request.url;
+        let urls: Vec<&str> = std::iter::once(primary).chain(request.fallback_urls.iter().copied()).collect();
+        let mut attempts = Vec::with_capacity(urls.len());
+
+        let result = 'urls: {
+            for (index, url) in urls.iter().enumerate() {
+                request.url = url;
+                let started = Instant::now();
+                let result = self.execute_hedged(request).await;
+                attempts.push(UrlAttempt {
+                    url: url.to_string(),
+                    status_code: match &result {
+                        Ok(response) => Some(response.status_code),
+                        Err(e) => Self::error_status(e),
+                    },
+                    error: result.as_ref().err().map(|e| HttpError::code_of(e).to_owned()),
+                    elapsed_ms: started.elapsed().as_millis(),
+                });
+                if index + 1 < urls.len() && Self::should_fall_back(&result, replayable, request.fallback_on_status) {
+                    continue;
+                }
+                break 'urls result;
+            }
+            unreachable!("urls always holds request.url")
+        };
+        // A retry (`execute_with_retries`) starts over from the primary
+        request.url = primary;
+
+        match result {
+            Ok(mut response) => {
+                response.url_attempts = attempts;
+                Ok(response)
+            }
+            Err(e) => {
+                let mut error = HttpError::from_error(&e);
+                let details = error.details.get_or_insert_with(|| json!({}));
+                // Through bytes, since simd-json's value serializer rejects `u128`
+                let attempts = json::to_vec(&attempts).and_then(|mut bytes| json::to_owned_value(&mut bytes));
+                if let (Some(map), Ok(attempts)) = (details.as_object_mut(), attempts) {
+                    map.insert("url_attempts".into(), attempts);
+                }
+                Err(error.into())
+            }
+        }
+    }
+
+    /// Transient failures (and 5xx with `fallback_on_status`) move on, for replayable
+    /// requests only (`MethodUtils::is_retry_eligible`)
+    fn should_fall_back(result: &Result<HttpResponse>, replayable: bool, on_status: bool) -> bool {
+        if !replayable {
+            return false;
+        }
+        let server_error = |status_code: u16| on_status && status_code >= 500;
+        match result {
+            Ok(response) => server_error(response.status_code),
+            Err(e) => match HttpError::code_of(e) {
+                "HTTP_STATUS" => Self::error_status(e).is_some_and(server_error),
+                code => Self::is_transient(code),
+            },
+        }
+    }
+
+    /// Network failures worth another try: connect, DNS and TLS errors, timeouts and resets
+    fn is_transient(code: &str) -> bool {
+        matches!(
+            code,
+            "CONNECT" | "CONNECT_TIMEOUT" | "DNS" | "DNS_TIMEOUT" | "TLS" | "TIMEOUT" | "REQUEST" | "WRITE_TIMEOUT" | "READ_TIMEOUT"
+        )
+    }
+
+    /// Status carried by an `HTTP_STATUS` error (`error_on_status`)
+    fn error_status(error: &anyhow::Error) -> Option<u16> {
+        error.downcast_ref::<HttpError>()?.details.as_ref()?.get("status_code")?.as_u64()?.try_into().ok()
+    }
+
+    /// Runs the attempt, racing up to `max_hedges` delayed copies of it. Dropping the
+    /// remaining futures once one succeeds cancels the losing attempts.
+    async fn execute_hedged(&self, request: &HttpRequest<'_>) -> Result<HttpResponse> {
+        if request.max_hedges == 0 {
+            return self.execute_attempt(request).await;
+        }
+
+        let attempt = |index: u32| async move { (index, self.execute_attempt(request).await) };
+        let delay = Duration::from_millis(request.hedge_delay_ms);
+        let mut attempts = FuturesUnordered::new();
+        attempts.push(attempt(0));
+        let mut launched = 1;
+        let mut next_hedge = tokio::time::Instant::now() + delay;
+
+        loop {
+            let can_hedge = launched <= request.max_hedges;
+            tokio::select! {
+                Some((index, result)) = attempts.next() => match result {
+                    Ok(mut response) => {
+                        response.hedge = Some(HedgeInfo { launched, winner: index });
+                        return Ok(response);
+                    }
+                    Err(e) if attempts.is_empty() && !can_hedge => return Err(e),
+                    // Keep waiting for the others, or hedge right away if none is left
+                    Err(_) => {
+                        if attempts.is_empty() {
+                            next_hedge = tokio::time::Instant::now();
+                        }
+                    }
+                },
+                _ = tokio::time::sleep_until(next_hedge), if can_hedge => {
+                    attempts.push(attempt(launched));
+                    launched += 1;
+                    next_hedge += delay;
+                }
+            }
+        }
+    }
+
+    /// The tighter of `total_timeout_ms` and, for `background` requests, the background deadline
+    fn total_timeout_ms(request: &HttpRequest<'_>) -> u64 {
+        let deadline_ms = if request.background { ClientConfig::settings().background_deadline_ms } else { 0 };
+        match (request.total_timeout_ms, deadline_ms) {
+            (0, deadline_ms) => deadline_ms,
+            (total_ms, 0) => total_ms,
+            (total_ms, deadline_ms) => total_ms.min(deadline_ms),
+        }
+    }
+
+    /// One attempt of `execute_request`, without the total timeout
+    async fn execute_attempt(&self, request: &HttpRequest<'_>) -> Result<HttpResponse> {
+        let start_time = Instant::now();
+
+        let method = MethodUtils::parse_method(request.method)?;
+        request.validate(&method)?;
+        let cache_url = match request.cache_mode {
+            // The cache holds decoded text, so requests wanting exact bytes bypass it
+            Some(_) if method == Method::GET && request.download_path.is_none() && request.response_stream_id.is_none() && !request.headers_only && request.decodes_body() && !request.base64_body && !request.raw_body => {
+                Some(Self::cache_url(request)?)
+            }
+            _ => None,
+        };
+        let pinned_client = match request.force_addr {
+            Some(addr) => Some(Self::pinned_client(request.url, addr, !request.decodes_body(), request.preserve_header_case)?),
+            None => None,
+        };
+        let proxy_client = match request.proxy_url {
+            Some(proxy_url) => Some(Self::proxy_client(request, proxy_url)?),
+            None => None,
+        };
+        let mut own_client = false;
+        let client = match pinned_client.or(proxy_client) {
+            Some(client) => client,
+            None if !request.decodes_body() => PASSTHROUGH_CLIENT.read().clone(),
+            None if request.preserve_header_case => TITLE_CASE_CLIENT.read().clone(),
+            #[cfg(feature = "http3")]
+            None if request.http3_only => HTTP3_CLIENT.read().clone(),
+            None if request.background => BACKGROUND_CLIENT.read().clone(),
+            None => {
+                own_client = true;
+                self.client()
+            }
+        };
+        let signed_url = match &request.aws_sigv4 {
+            Some(_) => Some(SigV4::canonical_url(request.url, &request.query_params)?),
+            None => None,
+        };
+        let mut req_builder = match &signed_url {
+            Some(url) => client.request(method.clone(), url.clone()),
+            None => client.request(method.clone(), request.url.to_string()),
+        };
+
+        let mut headers = HeaderUtils::build_header_map(&request.headers)?;
+        if request.decodes_body() {
+            HeaderUtils::restrict_accept_encoding(&mut headers);
+        } else if !request.passthrough && !headers.contains_key(ACCEPT_ENCODING) {
+            // The undecoding client advertises nothing itself; still ask for compression
+            headers.insert(ACCEPT_ENCODING, HeaderValue::from_str(&ClientConfig::supported_encodings().join(", "))?);
+        }
+
+        if let Some(accept) = &request.accept {
+            if !headers.contains_key(ACCEPT) {
+                headers.insert(ACCEPT, HeaderUtils::build_accept_value(accept)?);
+            }
+        }
+
+        if !headers.contains_key(ACCEPT_LANGUAGE) {
+            let accept_language = match request.accept_language {
+                Some(language) => Some(HeaderUtils::accept_language_value(language)?),
+                None => match &ClientConfig::settings().accept_language {
+                    Some(language) => Some(HeaderUtils::accept_language_value(language)?),
+                    None => None,
+                },
+            };
+            if let Some(accept_language) = accept_language {
+                headers.insert(ACCEPT_LANGUAGE, accept_language);
+            }
+        }
 
-        if !request.headers.is_empty() {
-            let headers = HeaderUtils::build_header_map(&request.headers)?;
+        let raw_body = request.body.is_some() || request.body_bytes.is_some() || request.body_file.is_some() || request.body_files.is_some();
+        if raw_body && !headers.contains_key(CONTENT_TYPE) {
+            let content_type = match request.default_content_type {
+                Some(content_type) => Some(HeaderValue::from_str(content_type)?),
+                None => match &ClientConfig::settings().default_content_type {
+                    Some(content_type) => Some(HeaderValue::from_str(content_type)?),
+                    None => None,
+                },
+            };
+            if let Some(content_type) = content_type.filter(|value| !value.is_empty()) {
+                headers.insert(CONTENT_TYPE, content_type);
+            }
+        }
+
+        if let Some(algorithm) = request.content_digest {
+            let body = match (&request.body_bytes, request.body) {
+                _ if request.has_streamed_body() => return Err(ContentDigest::unavailable(request.url).into()),
+                (Some(body), _) => &body[..],
+                (None, body) => body.unwrap_or_default().as_bytes(),
+            };
+            let (name, value) = ContentDigest::header(algorithm, body);
+            headers.insert(name, value);
+        }
+
+        if let (Some(credentials), Some(url)) = (&request.aws_sigv4, &signed_url) {
+            let payload_hash = match (&request.body_bytes, request.body) {
+                _ if request.has_streamed_body() => sigv4::UNSIGNED_PAYLOAD.to_owned(),
+                (Some(body), _) => SigV4::payload_hash(Some(body)),
+                (None, body) => SigV4::payload_hash(body.map(str::as_bytes)),
+            };
+            SigV4::sign(credentials, &method, url, &mut headers, &payload_hash)?;
+        }
+
+        if !headers.contains_key(AUTHORIZATION) {
+            let authorization = match (request.bearer_token, request.basic_auth) {
+                (Some(token), _) => Some(CredentialStore::bearer(token).map_err(|_| Self::invalid_auth("bearer_token"))?),
+                (None, Some((username, password))) => Some(CredentialStore::basic(username, password).map_err(|_| Self::invalid_auth("basic_auth"))?),
+                (None, None) => CredentialStore::authorization(request.url),
+            };
+            if let Some(authorization) = authorization {
+                headers.insert(AUTHORIZATION, authorization);
+            }
+        }
+
+        let resume_from = match request.download_path {
+            Some(path) if request.resume_download => Download::apply_resume_headers(&mut headers, path, request.if_range).await?,
+            _ => 0,
+        };
+
+        // The key covers `Authorization` and the headers the URL's responses vary on, so the
+        // request headers are kept as they are now, without the validators added below
+        let cache = cache_url.map(|url| (ResponseCache::key(&url, &headers), url, headers.clone()));
+        let mut cached = None;
+        if let Some((key, url, request_headers)) = &cache {
+            if let Some((hit, fresh)) = ResponseCache::get(key) {
+                if fresh {
+                    let mut fresh_hit = hit;
+                    fresh_hit.set_source(ResponseSource::CacheFresh);
+                    fresh_hit.elapsed_ms = start_time.elapsed().as_millis();
+                    fresh_hit.network_ms = 0;
+                    return Ok(fresh_hit);
+                }
+                if request.cache_mode == Some(CacheMode::StaleWhileRevalidate) {
+                    self.spawn_revalidation(key.clone(), url.clone(), request_headers.clone(), &hit);
+                    let mut stale = hit;
+                    stale.served_stale = true;
+                    stale.set_source(ResponseSource::CacheStale);
+                    stale.elapsed_ms = start_time.elapsed().as_millis();
+                    stale.network_ms = 0;
+                    return Ok(stale);
+                }
+                ResponseCache::apply_validators(&mut headers, &hit);
+                cached = Some(hit);
+            }
+        }
+
+        if !headers.is_empty() {
             req_builder = req_builder.headers(headers);
         }
 
-        if !request.query_params.is_empty() {
+        // A signed URL already carries the query params
+        if !request.query_params.is_empty() && signed_url.is_none() {
             req_builder = req_builder.query(&request.query_params);
         }
 
-        if let Some(body) = request.body {
-            req_builder = req_builder.body(body.to_string());
+        let plain_body = match (&request.body_bytes, request.body) {
+            (Some(body), _) => Some(body.clone()),
+            (None, Some(body)) => Some(Bytes::copy_from_slice(body.as_bytes())),
+            (None, None) => None,
+        };
+
+        // Wrapping the body in a stream makes the request uncloneable, so only when an event
+        // needs it, or `write_timeout_ms`, which must see slices being written, not one buffer
+        if let Some(body) = plain_body {
+            req_builder = match (request.upload_stream_id, request.sent_event_id) {
+                (None, None) if request.write_timeout_ms == 0 => req_builder.body(body),
+                (progress_id, event_id) => {
+                    let size = body.len() as u64;
+                    let mut chunks = match progress_id {
+                        Some(progress_id) => UploadProgress::new(progress_id, size).track_bytes(body),
+                        None => UploadProgress::slices(body),
+                    };
+                    if let Some(event_id) = event_id {
+                        chunks = RequestSent::notify(chunks, event_id, size);
+                    }
+                    req_builder.header(CONTENT_LENGTH, size).body(Body::wrap_stream(chunks))
+                }
+            };
         }
 
-        // Force HTTP/2 only (no HTTP/3)
-        req_builder = req_builder.version(Version::HTTP_2);
+        if let Some(path) = request.body_file {
+            let (file_stream, size) = FileUtils::open_stream(path).await?;
+            let mut chunks = file_stream.boxed();
+            if let Some(progress_id) = request.upload_stream_id {
+                chunks = UploadProgress::new(progress_id, size).track(chunks, None, size);
+            }
+            if let Some(event_id) = request.sent_event_id {
+                chunks = RequestSent::notify(chunks, event_id, size);
+            }
+            req_builder = req_builder
+                .header(CONTENT_LENGTH, size)
+                .body(Body::wrap_stream(chunks));
+        }
 
-        let response = req_builder.send().await?;
+        if let Some(paths) = &request.body_files {
+            let (mut chunks, size) = FileUtils::concat_stream(paths, request.upload_stream_id).await?;
+            if let Some(event_id) = request.sent_event_id {
+                chunks = RequestSent::notify(chunks, event_id, size);
+            }
+            req_builder = req_builder
+                .header(CONTENT_LENGTH, size)
+                .body(Body::wrap_stream(chunks));
+        }
+
+        if let Some(stream_id) = request.body_stream_id {
+            let mut chunks = UploadStream::take(stream_id, request.body_stream_length)?;
+            if let Some(size) = request.body_stream_length {
+                if let Some(progress_id) = request.upload_stream_id {
+                    chunks = UploadProgress::new(progress_id, size).track(chunks, None, size);
+                }
+                if let Some(event_id) = request.sent_event_id {
+                    chunks = RequestSent::notify(chunks, event_id, size);
+                }
+                req_builder = req_builder.header(CONTENT_LENGTH, size);
+            }
+            req_builder = req_builder.body(Body::wrap_stream(chunks));
+        }
+
+        if let Some(parts) = &request.multipart {
+            let form = MultipartUtils::build_form(parts, request.upload_stream_id, request.sent_event_id).await?;
+            req_builder = req_builder.multipart(form);
+        }
+
+        if request.timeout_ms > 0 {
+            req_builder = req_builder.timeout(Duration::from_millis(request.timeout_ms));
+        }
+
+        // Force HTTP/2 (`http3_only` has a client of its own), unless the caller lets ALPN
+        // negotiate, the ALPN list leaves h2 out or the caller wants header case on the wire,
+        // which only HTTP/1.1 carries
+        if request.http3_only {
+            #[cfg(feature = "http3")]
+            {
+                req_builder = req_builder.version(Version::HTTP_3);
+            }
+        } else if ClientConfig::offers_h2() && !request.negotiate_version && !request.preserve_header_case {
+            req_builder = req_builder.version(Version::HTTP_2);
+        }
+
+        let redirect_limit = RedirectLimit::for_request(request.follow_redirects, request.max_redirects);
+
+        // Held until the response is read; every hedge, retry and fallback URL takes its own
+        let _permit = ConcurrencyLimit::acquire().await;
+        // Counted from the permit on, against this attempt's own URL, so queued, cached and
+        // offline requests hold no connection and fallback URLs book their own host
+        let mut in_use = ConnectionStats::begin(request.url);
+        // Taken with the permit, so requests queued behind the limit hold no stream
+        let mut _stream = None;
+        if own_client && ClientConfig::offers_h2() && !request.negotiate_version {
+            let (client, built) = req_builder.build_split();
+            let built = built?;
+            let host = built.url().host_str().unwrap_or_default().to_owned();
+            let (client, stream) = self.lanes.acquire(&host, client, self.is_paused());
+            req_builder = RequestBuilder::from_parts(client, built);
+            _stream = stream;
+        }
+
+        // Connect errors happen before anything reaches the server, so even non-idempotent
+        // requests are safe to retry without `MethodUtils::is_retry_eligible`. Streaming
+        // bodies cannot be cloned and get one attempt.
+        let mut attempt = 1;
+        let (sent, redirects) = loop {
+            let retry_builder = (attempt < request.connect_attempts).then(|| req_builder.try_clone()).flatten();
+            let Some((sent, redirects)) = self.send(req_builder, request.connect_timeout_ms, request.write_timeout_ms, redirect_limit).await else {
+                return Err(Self::phase_timeout("WRITE_TIMEOUT", "Sending request to", request.write_timeout_ms, request.url));
+            };
+            match (sent, retry_builder) {
+                (Err(e), Some(next)) if e.is_connect() => {
+                    tokio::time::sleep(CONNECT_RETRY_BACKOFF * attempt).await;
+                    req_builder = next;
+                    attempt += 1;
+                }
+                (sent, _) => break (sent, redirects),
+            }
+        };
+
+        let response = match sent {
+            Ok(response) => response,
+            Err(e) if e.is_redirect() => {
+                let details = json!({ "urls": redirects.urls, "last_status": redirects.last_status });
+                return Err(HttpError::new("TOO_MANY_REDIRECTS", format!("Too many redirects for {}", request.url))
+                    .with_details(details)
+                    .into());
+            }
+            Err(e) => {
+                if let Some(proxy_url) = request.proxy_url.filter(|_| e.is_connect()) {
+                    return Err(Self::proxy_error(proxy_url, request.url, e).into());
+                }
+                if let Some(error) = HttpError::from_source_chain(&e) {
+                    return Err(error.into());
+                }
+                if Self::is_response_head_too_large(&e) {
+                    return Err(HttpError::new("HEADERS_TOO_LARGE", format!("Response headers from {} exceed max_response_header_bytes or hyper's HTTP/1.1 limits", request.url)).into());
+                }
+                match request.force_addr {
+                    Some(addr) if e.is_connect() => {
+                        return Err(HttpError::new("FORCE_ADDR_UNREACHABLE", format!("Could not connect to {} for {}: {}", addr, request.url, e))
+                            .with_details(json!({ "force_addr": addr, "url": request.url }))
+                            .into());
+                    }
+                    _ => return Err(e.into()),
+                }
+            }
+        };
+        in_use.follow(response.url());
+        // A partial read has its own deadline and returns what arrived instead of failing
+        let read_timeout_ms = if request.partial_body_on_timeout { 0 } else { request.read_timeout_ms };
+        let body_started = Instant::now();
+        let collected = Self::collect_response(response, request.url, CollectOptions::for_request(request, resume_from), start_time);
+        let collected = Self::within(read_timeout_ms, collected).await;
+        Trace::phase("body", body_started);
+        let Some(response) = collected else {
+            return Err(Self::phase_timeout("READ_TIMEOUT", "Reading response from", read_timeout_ms, request.url));
+        };
+        let mut response = response?;
+        response.redirects = redirects.urls;
+
+        if let Some((key, url, request_headers)) = &cache {
+            match cached {
+                Some(mut hit) if response.status_code == 304 => {
+                    ResponseCache::touch(key, &response);
+                    hit.elapsed_ms = response.elapsed_ms;
+                    hit.network_ms = response.network_ms;
+                    hit.redirects = std::mem::take(&mut response.redirects);
+                    hit.set_source(ResponseSource::NetworkRevalidated);
+                    response = hit;
+                }
+                _ => ResponseCache::store(url, request_headers, &response),
+            }
+        }
+
+        #[cfg(feature = "debug-logging")]
+        BodyLogger::log_response(&response);
+
+        if request.error_on_status && !(200..300).contains(&response.status_code) {
+            let status_code = response.status_code;
+            let details = json!({ "status_code": status_code, "body": response.body });
+            return Err(HttpError::new("HTTP_STATUS", format!("Request to {} returned status {}", request.url, status_code))
+                .with_details(details)
+                .into());
+        }
+
+        Ok(response)
+    }
+
+    /// Runs one phase of an attempt under `timeout_ms` (0 = unbounded); `None` if it expired
+    async fn within<F: Future>(timeout_ms: u64, future: F) -> Option<F::Output> {
+        if timeout_ms == 0 {
+            return Some(future.await);
+        }
+        tokio::time::timeout(Duration::from_millis(timeout_ms), future).await.ok()
+    }
+
+    fn phase_timeout(code: &str, phase: &str, timeout_ms: u64, url: &str) -> anyhow::Error {
+        HttpError::new(code, format!("{} {} exceeded {}ms", phase, url, timeout_ms))
+            .with_details(json!({ "timeout_ms": timeout_ms }))
+            .into()
+    }
+
+    /// Sends the request, recording any redirects it follows. New connections are bounded by
+    /// `connect_timeout_ms` when non-zero, else by the host's entry (see `ConnectTimeout`).
+    /// `None` if uploading the body took longer than `write_timeout_ms` (see `WriteTimeout`).
+    async fn send(&self, req_builder: RequestBuilder, connect_timeout_ms: u64, write_timeout_ms: u64, redirect_limit: RedirectLimit) -> Option<(reqwest::Result<Response>, RedirectLog)> {
+        let (client, built) = req_builder.build_split();
+        let mut built = match built {
+            Ok(built) => built,
+            Err(e) => return Some((Err(e), RedirectLog::default())),
+        };
+        let write_timeout = (write_timeout_ms > 0).then(|| WriteTimeout::wrap(&mut built, Duration::from_millis(write_timeout_ms))).flatten();
+        #[cfg(feature = "debug-logging")]
+        BodyLogger::log_request(&built);
+        if let Some(simulation) = NetworkSimulation::current() {
+            simulation.delay().await;
+        }
+        let url = built.url().clone();
+        let started = Instant::now();
+        let sent = Redirects::track(ConnectTimeout::scope(&url, connect_timeout_ms, self.transport.execute(&client, built)), redirect_limit);
+        let sent = match write_timeout {
+            Some(write_timeout) => tokio::select! {
+                sent = sent => sent,
+                _ = write_timeout.expired() => return None,
+            },
+            None => sent.await,
+        };
+        Trace::phase("ttfb", started);
+        Some(sent)
+    }
+
+    /// Reads status, headers and body into an `HttpResponse`
+    async fn collect_response(response: Response, url: &str, options: CollectOptions<'_>, start_time: Instant) -> Result<HttpResponse> {
         let status_code = response.status().as_u16();
         let version = Self::version_to_string(response.version());
-        let headers = HeaderUtils::extract_response_headers(response.headers());
-        let body_bytes = response.bytes().await?;
-        let body = String::from_utf8_lossy(&body_bytes).into_owned();
+        let status_line = options.status_line.then(|| Self::status_line(&response)).flatten();
+        let remote_addr = response.remote_addr();
+        let connected_family = remote_addr.map(|addr| AddressFamily::of(&addr).as_str().to_owned());
+        let remote_addr = remote_addr.map(|addr| addr.to_string());
+        let alpn_protocol = Self::alpn_protocol(&response);
+        if let Some(max) = ClientConfig::settings().max_response_headers {
+            let count = response.headers().len();
+            if count > max {
+                return Err(HttpError::new("HEADERS_TOO_LARGE", format!("{} sent {} header fields, limit is {}", url, count, max))
+                    .with_details(json!({ "header_count": count, "max_response_headers": max }))
+                    .into());
+            }
+        }
+        let response = match NetworkSimulation::current() {
+            Some(simulation) => simulation.throttle(response),
+            None => response,
+        };
+        let (headers, skipped_headers) = if options.strict_headers {
+            HeaderUtils::extract_response_headers_strict(response.headers())
+        } else {
+            (HeaderUtils::extract_response_headers(response.headers()), Vec::new())
+        };
+        let headers_ordered = if options.ordered_headers {
+            HeaderUtils::extract_ordered_headers(response.headers())
+        } else {
+            Vec::new()
+        };
+        let set_cookies = HeaderUtils::extract_set_cookies(response.headers());
+        let server_timing = HeaderUtils::parse_server_timing(response.headers());
+        if let Some((path, resume_from)) = options.download.filter(|_| status_code == 200 || status_code == 206) {
+            let download = Download::write_body(response, path, resume_from).await?;
+            let elapsed_ms = start_time.elapsed().as_millis();
+            return Ok(HttpResponse {
+                status_code,
+                headers,
+                body: String::new(),
+                version: version.to_string(),
+                url: url.to_string(),
+                elapsed_ms,
+                queue_wait_ms: 0,
+                network_ms: elapsed_ms,
+                skipped_headers,
+                headers_ordered,
+                set_cookies,
+                server_timing,
+                served_stale: false,
+                source: ResponseSource::Network,
+                from_cache: false,
+                truncated: false,
+                bytes_received: download.bytes_written,
+                body_preview: None,
+                download: Some(download),
+                status_line,
+                redirects: Vec::new(),
+                remote_addr,
+                connected_family,
+                alpn_protocol,
+                hedge: None,
+                url_attempts: Vec::new(),
+                retries: 0,
+                body_handle: None,
+                body_len: None,
+                json: None,
+                metadata: None,
+                body_encoding: None,
+                raw_body: None,
+            });
+        }
+
+        let mut reservation = match options.headers_only || options.stream_id.is_some() {
+            true => None,
+            false => MemoryBudget::reserve(url, response.content_length().unwrap_or(0)).await?,
+        };
+        let (body_bytes, truncated, bytes_received) = match options.stream_id {
+            Some(stream_id) => (Bytes::new(), false, ResponseStream::forward(response, stream_id).await?),
+            None => {
+                let (body_bytes, truncated) = match options.partial_timeout_ms {
+                    _ if options.headers_only => (Bytes::new(), false),
+                    Some(timeout_ms) => {
+                        let (body, truncated) = Self::read_body_partial(response, start_time, timeout_ms, reservation.as_mut()).await?;
+                        (Bytes::from(body), truncated)
+                    }
+                    None => match reservation.as_mut() {
+                        Some(reservation) => (Self::read_body_budgeted(response, reservation).await?, false),
+                        None => (response.bytes().await?, false),
+                    },
+                };
+                let bytes_received = body_bytes.len() as u64;
+                (body_bytes, truncated, bytes_received)
+            }
+        };
+        let (body, raw_body, body_encoding) = if options.raw_body {
+            (String::new(), Some(body_bytes), None)
+        } else if options.base64_body {
+            (STANDARD.encode(&body_bytes), None, Some("base64".to_owned()))
+        } else {
+            (String::from_utf8_lossy(&body_bytes).into_owned(), None, None)
+        };
         let elapsed_ms = start_time.elapsed().as_millis();
 
         Ok(HttpResponse {
@@ -67,20 +1011,307 @@ impl HttpClient {
             headers,
             body,
             version: version.to_string(),
-            url: request.url.to_string(),
+            url: url.to_string(),
             elapsed_ms,
+            queue_wait_ms: 0,
+            network_ms: elapsed_ms,
+            skipped_headers,
+            headers_ordered,
+            set_cookies,
+            server_timing,
+            served_stale: false,
+            source: ResponseSource::Network,
+            from_cache: false,
+            truncated,
+            bytes_received,
+            body_preview: None,
+            download: None,
+            status_line,
+            redirects: Vec::new(),
+            remote_addr,
+            connected_family,
+            alpn_protocol,
+            hedge: None,
+            url_attempts: Vec::new(),
+            retries: 0,
+            body_handle: None,
+            body_len: None,
+            json: None,
+            metadata: None,
+            body_encoding,
+            raw_body,
         })
     }
 
-    /// Executes multiple requests concurrently with a limit
-    pub async fn execute_requests_batch(&self, requests: Vec<HttpRequest<'_>>, concurrency: usize) -> Vec<HttpResponse> {
-        let responses = stream::iter(requests.into_iter())
-            .map(|req| self.execute_request(req))
-            .buffer_unordered(concurrency)
-            .collect::<Vec<_>>()
-            .await;
+    /// h2 answers an over-size header block by resetting the stream itself with
+    /// `REFUSED_STREAM`, which it does for nothing else on the client side. hyper's HTTP/1.1
+    /// parser fails with its too-large parse error past 100 headers or its head size limit.
+    fn is_response_head_too_large(error: &reqwest::Error) -> bool {
+        let mut source: Option<&(dyn std::error::Error + 'static)> = Some(error);
+        while let Some(current) = source {
+            if let Some(h2_error) = current.downcast_ref::<h2::Error>() {
+                return h2_error.is_library() && h2_error.reason() == Some(h2::Reason::REFUSED_STREAM);
+            }
+            if let Some(hyper_error) = current.downcast_ref::<hyper::Error>() {
+                if hyper_error.is_parse_too_large() {
+                    return true;
+                }
+            }
+            source = current.source();
+        }
+        false
+    }
+
+    /// hyper keeps the reason phrase only when it differs from the canonical one, so the
+    /// line is rebuilt from that or the canonical phrase
+    fn status_line(response: &Response) -> Option<String> {
+        let version = match response.version() {
+            Version::HTTP_10 => "HTTP/1.0",
+            Version::HTTP_11 => "HTTP/1.1",
+            _ => return None,
+        };
+        let status = response.status();
+        let reason = match response.extensions().get::<hyper::ext::ReasonPhrase>() {
+            Some(reason) => String::from_utf8_lossy(reason.as_bytes()).into_owned(),
+            None => status.canonical_reason().unwrap_or_default().to_owned(),
+        };
+        Some(format!("{} {} {}", version, status.as_str(), reason))
+    }
 
-        responses.into_iter().filter_map(Result::ok).collect()
+    /// `response.bytes()`, growing `reservation` as chunks arrive
+    async fn read_body_budgeted(response: Response, reservation: &mut Reservation) -> Result<Bytes> {
+        let mut body = Vec::with_capacity(response.content_length().unwrap_or(0).min(1 << 24) as usize);
+        let mut stream = response.bytes_stream();
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk?;
+            reservation.grow((body.len() + chunk.len()) as u64).await?;
+            body.extend_from_slice(&chunk);
+        }
+        Ok(Bytes::from(body))
+    }
+
+    /// Streams the body until it ends or times out; returns the bytes and whether it was cut short
+    async fn read_body_partial(response: Response, start_time: Instant, timeout_ms: u64, mut reservation: Option<&mut Reservation>) -> Result<(Vec<u8>, bool)> {
+        let deadline = (timeout_ms > 0)
+            .then(|| tokio::time::Instant::from_std(start_time + Duration::from_millis(timeout_ms)));
+        let mut body = Vec::with_capacity(response.content_length().unwrap_or(0).min(1 << 24) as usize);
+        let mut stream = response.bytes_stream();
+
+        loop {
+            let next = match deadline {
+                Some(deadline) => match tokio::time::timeout_at(deadline, stream.next()).await {
+                    Ok(next) => next,
+                    Err(_) => return Ok((body, true)),
+                },
+                None => stream.next().await,
+            };
+            match next {
+                Some(Ok(chunk)) => {
+                    if let Some(reservation) = reservation.as_mut() {
+                        reservation.grow((body.len() + chunk.len()) as u64).await?;
+                    }
+                    body.extend_from_slice(&chunk);
+                }
+                Some(Err(e)) if e.is_timeout() => return Ok((body, true)),
+                Some(Err(e)) => return Err(e.into()),
+                None => return Ok((body, false)),
+            }
+        }
+    }
+
+    /// Client for `force_addr`; `addr` is `ip:port` or a bare IP (port taken from the URL)
+    fn pinned_client(url: &str, addr: &str, passthrough: bool, title_case: bool) -> Result<Arc<Client>> {
+        let invalid = |reason: &str| HttpError::new("INVALID_FORCE_ADDR", format!("force_addr {:?}: {}", addr, reason))
+            .with_details(json!({ "force_addr": addr }));
+
+        let socket_addr = match addr.parse::<SocketAddr>() {
+            Ok(socket_addr) => socket_addr,
+            Err(_) => match addr.parse::<IpAddr>() {
+                Ok(ip) => SocketAddr::new(ip, 0),
+                Err(_) => return Err(invalid("expected an IP address with an optional port").into()),
+            },
+        };
+        let parsed = Url::parse(url)?;
+        let Some(host) = parsed.host_str() else {
+            return Err(invalid("the URL has no host").into());
+        };
+        Ok(Arc::new(ClientConfig::build_pinned_client(host, socket_addr, passthrough, title_case)?))
+    }
+
+    /// Cached client for `proxy_url`; an unsupported scheme or a URL that does not parse
+    /// is `INVALID_PROXY`
+    fn proxy_client(request: &HttpRequest<'_>, proxy_url: &str) -> Result<Arc<Client>> {
+        let (passthrough, title_case) = (!request.decodes_body(), request.preserve_header_case);
+        let key = (
+            proxy_url.to_owned(),
+            request.proxy_username.map(str::to_owned),
+            request.proxy_password.map(str::to_owned),
+            passthrough,
+            title_case,
+        );
+        if let Some(client) = PROXY_CLIENTS.lock().get(&key) {
+            return Ok(Arc::clone(client));
+        }
+
+        let invalid = |reason: &str| HttpError::new("INVALID_PROXY", format!("proxy_url {}: {}", Self::redact_proxy(proxy_url), reason))
+            .with_details(json!({ "proxy": Self::redact_proxy(proxy_url) }));
+        let scheme = Url::parse(proxy_url).map_err(|e| invalid(&e.to_string()))?.scheme().to_owned();
+        if !matches!(scheme.as_str(), "http" | "https" | "socks5" | "socks5h") {
+            return Err(invalid("expected an http, https, socks5 or socks5h URL").into());
+        }
+        let mut proxy = reqwest::Proxy::all(proxy_url).map_err(|e| invalid(&e.to_string()))?;
+        if request.proxy_username.is_some() || request.proxy_password.is_some() {
+            proxy = proxy.basic_auth(request.proxy_username.unwrap_or(""), request.proxy_password.unwrap_or(""));
+        }
+        let client = Arc::new(ClientConfig::build_proxy_client(proxy, passthrough, title_case)?);
+
+        let mut clients = PROXY_CLIENTS.lock();
+        if clients.len() >= MAX_PROXY_CLIENTS {
+            clients.clear();
+        }
+        clients.insert(key, Arc::clone(&client));
+        Ok(client)
+    }
+
+    /// `PROXY` for a connection that failed at the proxy or in its tunnel; `cause` keeps the
+    /// code the error would have had without a proxy (`CONNECT`, `DNS`, `TLS`, ...)
+    fn proxy_error(proxy_url: &str, url: &str, error: reqwest::Error) -> HttpError {
+        let error = anyhow::Error::from(error);
+        let cause = HttpError::from_source_chain(error.as_ref()).map_or_else(|| HttpError::code_of(&error).to_owned(), |inner| inner.code);
+        let proxy = Self::redact_proxy(proxy_url);
+        HttpError::new("PROXY", format!("Could not reach {} through proxy {}: {:#}", url, proxy, error))
+            .with_details(json!({ "proxy": proxy, "url": url, "cause": cause }))
+    }
+
+    fn invalid_auth(field: &str) -> HttpError {
+        HttpError::new("INVALID_REQUEST", format!("{} does not form a valid Authorization header", field))
+    }
+
+    /// `proxy_url` without a password embedded in it
+    fn redact_proxy(proxy_url: &str) -> String {
+        match Url::parse(proxy_url) {
+            Ok(mut parsed) if parsed.password().is_some() => {
+                let _ = parsed.set_password(Some("***"));
+                parsed.to_string()
+            }
+            _ => proxy_url.to_owned(),
+        }
+    }
+
+    /// URL the response cache files the request under, with query params in a stable order
+    fn cache_url(request: &HttpRequest<'_>) -> Result<String> {
+        let mut params: Vec<_> = request.query_params.iter().collect();
+        params.sort_unstable();
+        Ok(Url::parse_with_params(request.url, params)?.into())
+    }
+
+    /// Refreshes a stale-while-revalidate entry in the background (one at a time per key),
+    /// resending the original request `headers` with the entry's validators
+    fn spawn_revalidation(&self, key: String, url: String, request_headers: HeaderMap, cached: &HttpResponse) {
+        if !ResponseCache::begin_revalidation(&key) {
+            return;
+        }
+        let mut headers = request_headers.clone();
+        ResponseCache::apply_validators(&mut headers, cached);
+
+        let client = self.client();
+        let transport = Arc::clone(&self.transport);
+        tokio::spawn(async move {
+            let _permit = ConcurrencyLimit::acquire().await;
+            let start_time = Instant::now();
+            let sent = match client.get(url.as_str()).headers(headers).build() {
+                Ok(built) => {
+                    let url = built.url().clone();
+                    ConnectTimeout::scope(&url, 0, transport.execute(&client, built)).await
+                }
+                Err(e) => Err(e),
+            };
+            let result = match sent {
+                Ok(response) => Self::collect_response(response, &url, CollectOptions::default(), start_time).await,
+                Err(e) => Err(e.into()),
+            };
+            match result {
+                Ok(response) if response.status_code == 304 => ResponseCache::touch(&key, &response),
+                Ok(response) if response.status_code == 200 => {
+                    ResponseCache::end_revalidation(&key);
+                    ResponseCache::store(&url, &request_headers, &response);
+                }
+                _ => ResponseCache::end_revalidation(&key),
+            }
+        });
+    }
+
+    /// Executes multiple requests concurrently with a limit. Entry `i` is request `i`'s
+    /// response or error, whatever order they complete in.
+    pub async fn execute_requests_batch(&self, requests: Vec<HttpRequest<'_>>, concurrency: usize) -> Vec<Result<HttpResponse, HttpError>> {
+        self.execute_requests_batch_with(requests, concurrency, |_| {}).await
+    }
+
+    /// `execute_requests_batch`, calling `on_complete` as each request finishes (in completion order)
+    pub(crate) async fn execute_requests_batch_with(
+        &self,
+        requests: Vec<HttpRequest<'_>>,
+        concurrency: usize,
+        mut on_complete: impl FnMut(&Result<HttpResponse, HttpError>),
+    ) -> Vec<Result<HttpResponse, HttpError>> {
+        let mut results: Vec<Option<Result<HttpResponse, HttpError>>> = (0..requests.len()).map(|_| None).collect();
+        let mut responses = stream::iter(requests.into_iter().enumerate())
+            .map(|(index, req)| async move { (index, self.execute_request(req).await.map_err(|e| HttpError::from_error(&e))) })
+            .buffer_unordered(concurrency);
+
+        while let Some((index, result)) = responses.next().await {
+            on_complete(&result);
+            results[index] = Some(result);
+        }
+        results.into_iter().flatten().collect()
+    }
+
+    /// Moves the body into the handle registry for `body_as_handle`
+    fn register_body(mut response: HttpResponse) -> HttpResponse {
+        let body = match response.raw_body.take() {
+            Some(raw) => raw,
+            None => Bytes::from(std::mem::take(&mut response.body)),
+        };
+        response.body_len = Some(body.len() as u64);
+        response.body_handle = Some(BodyHandles::register(body));
+        response
+    }
+
+    /// Moves a 2xx body into `json` for `json_response` (run after the cache, so hits are parsed too)
+    fn parse_json_body(mut response: HttpResponse, url: &str) -> Result<HttpResponse> {
+        if !(200..300).contains(&response.status_code) || response.body.is_empty() {
+            return Ok(response);
+        }
+        let mut body = std::mem::take(&mut response.body).into_bytes();
+        match json::to_owned_value(&mut body) {
+            Ok(json) => {
+                response.json = Some(json);
+                Ok(response)
+            }
+            Err(e) => {
+                let content_type = response.headers.get("content-type").cloned();
+                let details = json!({ "status_code": response.status_code, "content_type": content_type });
+                Err(HttpError::new("RESPONSE_NOT_JSON", format!("Response from {} is not valid JSON: {}", url, e))
+                    .with_details(details)
+                    .into())
+            }
+        }
+    }
+
+    /// Protocol agreed via ALPN for HTTPS responses. reqwest does not expose the TLS session,
+    /// so this is derived from the HTTP version hyper speaks on the connection, which hyper
+    /// picks from the ALPN result: `h2`, else `http/1.1` (also when no token was agreed);
+    /// `h3` over QUIC.
+    fn alpn_protocol(response: &Response) -> Option<String> {
+        if response.url().scheme() != "https" {
+            return None;
+        }
+        let protocol = match response.version() {
+            Version::HTTP_3 => "h3",
+            Version::HTTP_2 => "h2",
+            _ => "http/1.1",
+        };
+        Some(protocol.to_owned())
     }
 
     fn version_to_string(version: Version) -> &'static str {
@@ -89,15 +1320,18 @@ impl HttpClient {
             Version::HTTP_10 => "HTTP/1.0",
             Version::HTTP_11 => "HTTP/1.1",
             Version::HTTP_2 => "HTTP/2",
+            Version::HTTP_3 => "HTTP/3",
             _ => "Unknown",
         }
     }
 
-    /// Prewarm connections to a list of URLs
+    /// Prewarm connections to a list of URLs, each under a `ConcurrencyLimit` slot
     pub async fn prewarm(&self, urls: &[&str]) {
-        futures_util::future::join_all(
-            urls.iter().map(|&url| self.client.get(url).send())
-        ).await;
+        futures_util::future::join_all(urls.iter().map(|&url| async move {
+            let _permit = ConcurrencyLimit::acquire().await;
+            self.client().get(url).send().await
+        }))
+        .await;
     }
 }
 
@@ -106,3 +1340,1075 @@ impl Default for HttpClient {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::content_digest::DigestAlgorithm;
+    use crate::models::MultipartPart;
+    use crate::transport::tests::counting;
+    use crate::transport::MockTransport;
+    use futures_util::future;
+    use http_body::Body as _;
+    use std::pin::Pin;
+
+    fn mock(transport: MockTransport) -> (HttpClient, Arc<MockTransport>) {
+        let transport = Arc::new(transport);
+        (HttpClient::with_transport(transport.clone()), transport)
+    }
+
+    fn request<'a>(method: &'a str, url: &'a str) -> HttpRequest<'a> {
+        HttpRequest { method, url, decompress: true, follow_redirects: true, ..Default::default() }
+    }
+
+    async fn next_chunk(body: &mut Body) -> Option<Bytes> {
+        let frame = future::poll_fn(|cx| Pin::new(&mut *body).poll_frame(cx)).await?;
+        frame.ok()?.into_data().ok()
+    }
+
+    fn code(result: Result<HttpResponse>) -> String {
+        HttpError::code_of(&result.expect_err("request should fail")).to_owned()
+    }
+
+    #[tokio::test]
+    async fn stalled_upload_fails_with_write_timeout() {
+        let (client, _) = mock(MockTransport::with_handler(|mut request| async move {
+            let mut body = request.body_mut().take().unwrap();
+            next_chunk(&mut body).await;
+            future::pending().await
+        }));
+        let body = Bytes::from(vec![0u8; 1 << 20]);
+        let request = HttpRequest {
+            body_bytes: Some(body),
+            write_timeout_ms: 100,
+            ..request("POST", "http://mock.test/upload")
+        };
+        let started = Instant::now();
+        assert_eq!(code(client.execute_request(request).await), "WRITE_TIMEOUT");
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+
+    #[tokio::test]
+    async fn slow_response_after_upload_is_not_a_write_timeout() {
+        let (client, _) = mock(MockTransport::with_handler(|mut request| async move {
+            let mut body = request.body_mut().take().unwrap();
+            while next_chunk(&mut body).await.is_some() {}
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            hyper::Response::new(Body::from("done"))
+        }));
+        let request = HttpRequest {
+            body: Some("payload"),
+            write_timeout_ms: 100,
+            ..request("POST", "http://mock.test/slow-response")
+        };
+        let response = client.execute_request(request).await.unwrap();
+        assert_eq!((response.status_code, response.body.as_str()), (200, "done"));
+    }
+
+    #[tokio::test]
+    async fn stalled_download_fails_with_read_timeout() {
+        let (client, _) = mock(MockTransport::with_handler(|_| async {
+            let chunks = stream::once(async { Ok::<_, std::io::Error>(Bytes::from_static(b"first")) }).chain(stream::pending());
+            hyper::Response::new(Body::wrap_stream(chunks))
+        }));
+        let request = HttpRequest { read_timeout_ms: 100, write_timeout_ms: 100, ..request("GET", "http://mock.test/download") };
+        assert_eq!(code(client.execute_request(request).await), "READ_TIMEOUT");
+    }
+
+    /// The mock cannot raise reqwest's own timeout, so this goes to a port that accepts
+    /// connections (the listen backlog does) but never answers
+    #[tokio::test]
+    async fn silent_server_fails_with_request_timeout() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/silent", listener.local_addr().unwrap());
+        let started = Instant::now();
+        let request = HttpRequest { timeout_ms: 200, negotiate_version: true, ..request("GET", &url) };
+        assert_eq!(code(HttpClient::new().execute_request(request).await), "TIMEOUT");
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+
+    /// Mock answering with the request's `Content-Type` (empty if it had none)
+    fn echo_content_type() -> MockTransport {
+        MockTransport::new(|request| {
+            let content_type = request.headers().get(CONTENT_TYPE).map(|value| value.as_bytes().to_vec()).unwrap_or_default();
+            hyper::Response::new(Bytes::from(content_type))
+        })
+    }
+
+    #[tokio::test]
+    async fn default_content_type_applies_only_without_explicit_header() {
+        let (client, _) = mock(echo_content_type());
+        let path = std::env::temp_dir().join(format!("default-content-type-{}", std::process::id()));
+        std::fs::write(&path, b"{}").unwrap();
+        let path = path.to_str().unwrap();
+        let json = Some("application/json");
+
+        let plain = HttpRequest { body: Some("{}"), ..request("POST", "http://mock.test/") };
+        let defaulted = HttpRequest { body: Some("{}"), default_content_type: json, ..request("POST", "http://mock.test/") };
+        let file = HttpRequest { body_file: Some(path), default_content_type: json, ..request("PUT", "http://mock.test/") };
+        let explicit = HttpRequest {
+            body: Some("a=1"),
+            headers: [("Content-Type", "application/x-www-form-urlencoded")].into_iter().collect(),
+            default_content_type: json,
+            ..request("POST", "http://mock.test/")
+        };
+        let bodiless = HttpRequest { default_content_type: json, ..request("GET", "http://mock.test/") };
+
+        let mut seen = Vec::new();
+        for request in [plain, defaulted, file, explicit, bodiless] {
+            seen.push(client.execute_request(request).await.unwrap().body);
+        }
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(seen, ["", "application/json", "application/json", "application/x-www-form-urlencoded", ""]);
+    }
+
+    #[tokio::test]
+    async fn cancelled_slow_request_returns_promptly() {
+        let (client, _) = mock(MockTransport::with_handler(|_| future::pending()));
+        let ticket = Cancellation::submit();
+        let handle = ticket.handle();
+        let slow = tokio::spawn(async move {
+            let request = HttpRequest { cancel_handle: Some(handle), ..request("GET", "http://mock.test/slow") };
+            client.execute_request(request).await
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let cancelled_at = Instant::now();
+        assert!(Cancellation::cancel(handle));
+        let result = tokio::time::timeout(Duration::from_secs(1), slow).await.expect("cancel should end the request").unwrap();
+        assert_eq!(code(result), "CANCELLED");
+        assert!(cancelled_at.elapsed() < Duration::from_millis(500));
+    }
+
+    /// Local keep-alive HTTP/1.1 server answering each (bodiless) request with `respond(head)`
+    struct LocalServer {
+        addr: std::net::SocketAddr,
+        /// Connections accepted
+        connections: Arc<std::sync::atomic::AtomicUsize>,
+        /// Request heads as received, header case included
+        heads: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl LocalServer {
+        async fn start(respond: fn(&str) -> Vec<u8>) -> Self {
+            Self::start_on(tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap(), respond)
+        }
+
+        fn start_on(listener: tokio::net::TcpListener, respond: fn(&str) -> Vec<u8>) -> Self {
+            use tokio::io::{AsyncReadExt, AsyncWriteExt};
+            let server = Self { addr: listener.local_addr().unwrap(), connections: Default::default(), heads: Default::default() };
+            let (connections, heads) = (Arc::clone(&server.connections), Arc::clone(&server.heads));
+            tokio::spawn(async move {
+                while let Ok((mut socket, _)) = listener.accept().await {
+                    connections.fetch_add(1, Ordering::SeqCst);
+                    let heads = Arc::clone(&heads);
+                    tokio::spawn(async move {
+                        let (mut buf, mut received) = ([0u8; 4096], Vec::new());
+                        while let Ok(read @ 1..) = socket.read(&mut buf).await {
+                            received.extend_from_slice(&buf[..read]);
+                            while let Some(end) = received.windows(4).position(|window| window == b"\r\n\r\n") {
+                                let head = String::from_utf8_lossy(&received[..end]).into_owned();
+                                received.drain(..end + 4);
+                                let response = respond(&head);
+                                heads.lock().push(head);
+                                if socket.write_all(&response).await.is_err() {
+                                    return;
+                                }
+                            }
+                        }
+                    });
+                }
+            });
+            server
+        }
+    }
+
+    /// An HTTP/1.1 response with `headers` (CRLF-terminated lines) and `body`
+    fn http1_response(status: &str, headers: &str, body: &[u8]) -> Vec<u8> {
+        let mut response = format!("HTTP/1.1 {}\r\ncontent-length: {}\r\n{}\r\n", status, body.len(), headers).into_bytes();
+        response.extend_from_slice(body);
+        response
+    }
+
+    /// Removes the DNS overrides of its hosts when dropped, also when the test fails
+    struct DnsOverrides(&'static [&'static str]);
+
+    impl DnsOverrides {
+        fn pin(hosts: &'static [&'static str], addr: std::net::SocketAddr) -> Self {
+            for host in hosts {
+                crate::dns::DnsResolver::set_override(host, vec![addr]);
+            }
+            Self(hosts)
+        }
+    }
+
+    impl Drop for DnsOverrides {
+        fn drop(&mut self) {
+            for host in self.0 {
+                crate::dns::DnsResolver::set_override(host, Vec::new());
+            }
+        }
+    }
+
+    /// The pool is keyed by origin, so HTTP/1.1 connections are not shared across hostnames
+    /// that resolve to the same address. See `h2_connections_are_not_coalesced_across_hosts`
+    /// for HTTP/2.
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn hosts_sharing_an_address_get_separate_connections() {
+        let server = LocalServer::start(|_| http1_response("200 OK", "", b"")).await;
+        let _overrides = DnsOverrides::pin(&["alpha.coalesce.test", "beta.coalesce.test"], "127.0.0.1:0".parse().unwrap());
+        let client = HttpClient::new();
+        let urls = [
+            format!("http://alpha.coalesce.test:{}/", server.addr.port()),
+            format!("http://beta.coalesce.test:{}/", server.addr.port()),
+            format!("http://alpha.coalesce.test:{}/again", server.addr.port()),
+            format!("http://beta.coalesce.test:{}/again", server.addr.port()),
+        ];
+        for url in &urls {
+            let response = client.execute_request(HttpRequest { negotiate_version: true, ..request("GET", url) }).await.unwrap();
+            assert_eq!(response.status_code, 200);
+        }
+        // The repeat requests reuse their origin's connection; the two origins never share one
+        assert_eq!(server.connections.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn connect_layer_remembers_the_family_that_connected() {
+        let server = LocalServer::start(|_| http1_response("200 OK", "", b"")).await;
+        let _overrides = DnsOverrides::pin(&["family.connect.test"], "127.0.0.1:0".parse().unwrap());
+        let url = format!("http://family.connect.test:{}/", server.addr.port());
+        HttpClient::new().execute_request(HttpRequest { negotiate_version: true, ..request("GET", &url) }).await.unwrap();
+        let first = DnsResolver::first_family("family.connect.test", Some(AddressFamily::Ipv6), true);
+        assert_eq!(first, Some(AddressFamily::Ipv4));
+        assert!(RequestMetrics::prometheus().lines().any(|line| line.starts_with("http_client_connect_seconds_count{family=\"ipv4\"} ") && !line.ends_with(" 0")));
+    }
+
+    #[tokio::test]
+    async fn only_connections_that_succeed_count_as_opened() {
+        let server = LocalServer::start(|_| http1_response("200 OK", "", b"")).await;
+        let refused: std::net::SocketAddr = refused_url().trim_start_matches("http://").trim_end_matches('/').parse().unwrap();
+        let _up = DnsOverrides::pin(&["up.opened.test"], "127.0.0.1:0".parse().unwrap());
+        let _down = DnsOverrides::pin(&["down.opened.test"], refused);
+        let client = HttpClient::new();
+        for path in ["first", "pooled"] {
+            let url = format!("http://up.opened.test:{}/{}", server.addr.port(), path);
+            client.execute_request(HttpRequest { negotiate_version: true, ..request("GET", &url) }).await.unwrap();
+        }
+        let url = format!("http://down.opened.test:{}/", refused.port());
+        assert!(client.execute_request(HttpRequest { negotiate_version: true, ..request("GET", &url) }).await.is_err());
+
+        let stats = ConnectionStats::snapshot();
+        assert_eq!(stats["up.opened.test"].connections_opened, 1);
+        assert_eq!(stats["down.opened.test"].connections_opened, 0);
+    }
+
+    #[tokio::test]
+    async fn preserve_header_case_title_cases_names_on_the_wire() {
+        let server = LocalServer::start(|_| http1_response("200 OK", "", b"")).await;
+        let url = format!("http://{}/case", server.addr);
+        let client = HttpClient::new();
+        let headers = HashMap::from([("x-api-key", "k")]);
+        for preserve_header_case in [true, false] {
+            let request = HttpRequest { headers: headers.clone(), preserve_header_case, negotiate_version: true, ..request("GET", &url) };
+            client.execute_request(request).await.unwrap();
+        }
+        let heads = server.heads.lock();
+        assert!(heads[0].contains("\r\nX-Api-Key: k"), "{}", heads[0]);
+        assert!(heads[1].contains("\r\nx-api-key: k"), "{}", heads[1]);
+    }
+
+    /// `/hop/n` redirects to `/hop/n+1` up to `/hop/3`, which answers "end"
+    fn hops(head: &str) -> Vec<u8> {
+        let hop: u32 = head.split(' ').nth(1).and_then(|path| path.strip_prefix("/hop/")).and_then(|n| n.parse().ok()).unwrap_or(3);
+        match hop {
+            3.. => http1_response("200 OK", "", b"end"),
+            hop => http1_response("302 Found", &format!("location: /hop/{}\r\n", hop + 1), b""),
+        }
+    }
+
+    #[tokio::test]
+    async fn redirects_follow_the_request_settings() {
+        let server = LocalServer::start(hops).await;
+        let url = format!("http://{}/hop/0", server.addr);
+        let client = HttpClient::new();
+        let hop = |follow_redirects, max_redirects| HttpRequest { follow_redirects, max_redirects, negotiate_version: true, ..request("GET", &url) };
+
+        let unfollowed = client.execute_request(hop(false, 0)).await.unwrap();
+        assert_eq!((unfollowed.status_code, unfollowed.headers.get("location").map(String::as_str)), (302, Some("/hop/1")));
+        let followed = client.execute_request(hop(true, 3)).await.unwrap();
+        assert_eq!((followed.status_code, followed.body.as_str(), followed.redirects.len()), (200, "end", 4));
+        assert_eq!(code(client.execute_request(hop(true, 2)).await), "TOO_MANY_REDIRECTS");
+    }
+
+    /// Serializes the tests that change the app-wide concurrency limit
+    static LIMIT_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn concurrency_limit_caps_requests_in_flight() {
+        let _serial = LIMIT_LOCK.lock().await;
+        let (transport, peak) = counting(Duration::from_millis(5));
+        let (client, transport) = mock(transport);
+        assert!(ConcurrencyLimit::set(4));
+        let requests = (0..200).map(|_| request("GET", "http://mock.test/limited")).collect();
+        let responses = client.execute_requests_batch(requests, 64).await;
+        ConcurrencyLimit::set(crate::concurrency_limit::DEFAULT_MAX_CONCURRENCY);
+        assert_eq!(responses.len(), 200);
+        assert!(responses.iter().all(Result::is_ok));
+        assert_eq!(transport.requests().len(), 200);
+        assert!(peak.load(Ordering::SeqCst) <= 4, "peak {}", peak.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn concurrency_limit_counts_every_hedge() {
+        let _serial = LIMIT_LOCK.lock().await;
+        let (transport, peak) = counting(Duration::from_millis(100));
+        let (client, transport) = mock(transport);
+        assert!(ConcurrencyLimit::set(2));
+        let hedged = HttpRequest { max_hedges: 3, hedge_delay_ms: 10, ..request("GET", "http://mock.test/hedged") };
+        let response = client.execute_request(hedged).await;
+        ConcurrencyLimit::set(crate::concurrency_limit::DEFAULT_MAX_CONCURRENCY);
+        assert_eq!(response.unwrap().hedge.unwrap().launched, 4);
+        assert!(transport.requests().len() <= 2, "hedges beyond the limit must wait for a slot");
+        assert!(peak.load(Ordering::SeqCst) <= 2, "peak {}", peak.load(Ordering::SeqCst));
+    }
+
+    /// Mock answering 503 to the first `failures` requests, then 200
+    fn flaky(failures: usize) -> MockTransport {
+        let seen = std::sync::atomic::AtomicUsize::new(0);
+        MockTransport::new(move |_| {
+            let status = if seen.fetch_add(1, Ordering::SeqCst) < failures { 503 } else { 200 };
+            hyper::Response::builder().status(status).body(Bytes::new()).unwrap()
+        })
+    }
+
+    fn retried<'a>(method: &'a str, url: &'a str) -> HttpRequest<'a> {
+        HttpRequest { max_retries: 3, retry_base_delay_ms: Some(1), ..request(method, url) }
+    }
+
+    fn with_key(request: HttpRequest<'_>) -> HttpRequest<'_> {
+        HttpRequest { headers: [("Idempotency-Key", "order-42")].into_iter().collect(), ..request }
+    }
+
+    /// A local port nothing listens on, so connecting is refused
+    fn refused_url() -> String {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        format!("http://{}/", listener.local_addr().unwrap())
+    }
+
+    fn retries_of(error: &anyhow::Error) -> Option<u64> {
+        error.downcast_ref::<HttpError>()?.details.as_ref()?.get("retries")?.as_u64()
+    }
+
+    #[tokio::test]
+    async fn post_without_idempotency_key_is_not_retried() {
+        let (client, transport) = mock(flaky(2));
+        let response = client.execute_request(retried("POST", "http://mock.test/orders")).await.unwrap();
+        assert_eq!((response.status_code, response.retries), (503, 0));
+        assert_eq!(transport.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn post_with_idempotency_key_is_retried() {
+        let (client, transport) = mock(flaky(2));
+        let response = client.execute_request(with_key(retried("POST", "http://mock.test/orders"))).await.unwrap();
+        assert_eq!((response.status_code, response.retries), (200, 2));
+        assert_eq!(transport.requests().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn get_is_retried() {
+        let (client, transport) = mock(flaky(2));
+        let response = client.execute_request(retried("GET", "http://mock.test/orders")).await.unwrap();
+        assert_eq!((response.status_code, response.retries), (200, 2));
+        assert_eq!(transport.requests().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn total_timeout_cuts_retries_short() {
+        let (client, transport) = mock(flaky(usize::MAX));
+        let request = HttpRequest {
+            max_retries: 10,
+            retry_base_delay_ms: Some(50),
+            timeout_ms: 1_000,
+            total_timeout_ms: 300,
+            ..request("GET", "http://mock.test/unavailable")
+        };
+        let started = Instant::now();
+        let error = client.execute_request(request).await.unwrap_err();
+        assert!(started.elapsed() < Duration::from_secs(1));
+        let error = error.downcast_ref::<HttpError>().unwrap();
+        assert_eq!(error.code, "TOTAL_TIMEOUT");
+        let last_error = &error.details.as_ref().unwrap()["last_error"];
+        assert_eq!(last_error["code"].as_str(), Some("HTTP_STATUS"));
+        assert_eq!(last_error["message"].as_str(), Some("Request to http://mock.test/unavailable returned status 503"));
+        let attempts = transport.requests().len();
+        assert!((2..11).contains(&attempts), "{} attempts", attempts);
+    }
+
+    #[tokio::test]
+    async fn connect_attempts_retry_until_the_port_accepts() {
+        let addr = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        // Refused on the first attempt; listening by the second, after the 100 ms backoff
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(30)).await;
+            LocalServer::start_on(tokio::net::TcpListener::bind(addr).await.unwrap(), |_| http1_response("200 OK", "", b"up"));
+        });
+        let url = format!("http://{}/", addr);
+        let client = HttpClient::new();
+        let once = HttpRequest { negotiate_version: true, ..request("POST", &url) };
+        assert_eq!(code(client.execute_request(once).await), "CONNECT");
+
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        let retried = HttpRequest { negotiate_version: true, connect_attempts: 3, body: Some("order"), ..request("POST", &url) };
+        let response = client.execute_request(retried).await.unwrap();
+        assert_eq!((response.status_code, response.body.as_str()), (200, "up"));
+    }
+
+    /// Mock reading each request body to the end and keeping the last one
+    fn body_recorder() -> (MockTransport, Arc<Mutex<Vec<u8>>>) {
+        let recorded = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&recorded);
+        let transport = MockTransport::with_handler(move |mut request| {
+            let sink = Arc::clone(&sink);
+            async move {
+                let mut body = request.body_mut().take().unwrap();
+                let mut received = Vec::new();
+                while let Some(chunk) = next_chunk(&mut body).await {
+                    received.extend_from_slice(&chunk);
+                }
+                *sink.lock() = received;
+                hyper::Response::new(Body::from("ok"))
+            }
+        });
+        (transport, recorded)
+    }
+
+    #[tokio::test]
+    async fn only_flagged_multipart_parts_are_gzipped() {
+        const PHOTO: &[u8] = &[0xff, 0xd8, 0xff, 0xe0, 0x00, 0x10, b'J', b'F', b'I', b'F'];
+        let path = std::env::temp_dir().join(format!("multipart-gzip-{}.jpg", std::process::id()));
+        std::fs::write(&path, PHOTO).unwrap();
+        let notes = "compressible ".repeat(64);
+        let (transport, recorded) = body_recorder();
+        let (client, _) = mock(transport);
+        let part = |name, data, file_path, gzip| MultipartPart { name, data, file_path, filename: None, content_type: None, gzip };
+        let request = HttpRequest {
+            multipart: Some(vec![part("notes", Some(notes.as_str()), None, true), part("photo", None, path.to_str(), false)]),
+            ..request("POST", "http://mock.test/upload")
+        };
+        client.execute_request(request).await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let body = recorded.lock().clone();
+        let find = |needle: &[u8]| body.windows(needle.len()).position(|window| window == needle);
+        let (notes_at, photo_at) = (find(b"name=\"notes\"").unwrap(), find(b"name=\"photo\"").unwrap());
+        let (notes_part, photo_part) = (&body[notes_at..photo_at], &body[photo_at..]);
+        let contains = |part: &[u8], needle: &[u8]| part.windows(needle.len()).any(|window| window.eq_ignore_ascii_case(needle));
+        assert!(contains(notes_part, b"content-encoding: gzip"));
+        assert!(contains(notes_part, &[0x1f, 0x8b]), "notes part is not gzip data");
+        assert!(notes_part.len() < notes.len(), "notes part was not compressed");
+        assert!(!contains(photo_part, b"content-encoding"));
+        assert!(contains(photo_part, PHOTO), "photo bytes were altered");
+    }
+
+    #[tokio::test]
+    async fn content_digest_hashes_the_body_sent() {
+        let (client, _) = mock(MockTransport::new(|request| {
+            let digest = ["content-digest", "content-md5"].iter()
+                .find_map(|name| request.headers().get(*name))
+                .map(|value| value.as_bytes().to_vec())
+                .unwrap_or_default();
+            hyper::Response::new(Bytes::from(digest))
+        }));
+        for (algorithm, expected) in [
+            (DigestAlgorithm::Sha256, "sha-256=:LPJNul+wow4m6DsqxbninhsWHlwfp0JecwQzYpOLmCQ=:"),
+            (DigestAlgorithm::Md5, "XUFAKrxLKna5cZ2REBfFkg=="),
+        ] {
+            let text = HttpRequest { body: Some("hello"), content_digest: Some(algorithm), ..request("PUT", "http://mock.test/digest") };
+            assert_eq!(client.execute_request(text).await.unwrap().body, expected);
+            let bytes = HttpRequest {
+                body_bytes: Some(Bytes::from_static(b"hello")),
+                content_digest: Some(algorithm),
+                ..request("PUT", "http://mock.test/digest")
+            };
+            assert_eq!(client.execute_request(bytes).await.unwrap().body, expected);
+        }
+        let streamed = HttpRequest {
+            body_file: Some("/nonexistent"),
+            content_digest: Some(DigestAlgorithm::Sha256),
+            ..request("PUT", "http://mock.test/digest")
+        };
+        assert_eq!(code(client.execute_request(streamed).await), "DIGEST_UNAVAILABLE");
+    }
+
+    #[tokio::test]
+    async fn json_response_parses_only_2xx_bodies() {
+        let (client, _) = mock(MockTransport::new(|request| {
+            let (status, body) = match request.url().path() {
+                "/json/ok" => (200, r#"{"id":7,"tags":["a"]}"#),
+                "/json/html" => (200, "<html>"),
+                _ => (404, "<html>not found</html>"),
+            };
+            hyper::Response::builder().status(status).body(Bytes::from_static(body.as_bytes())).unwrap()
+        }));
+        let json = |url| HttpRequest { json_response: true, ..request("GET", url) };
+        let ok = client.execute_request(json("http://mock.test/json/ok")).await.unwrap();
+        assert_eq!(ok.json, Some(json!({ "id": 7, "tags": ["a"] })));
+        assert!(ok.body.is_empty());
+        assert_eq!(code(client.execute_request(json("http://mock.test/json/html")).await), "RESPONSE_NOT_JSON");
+        let missing = client.execute_request(json("http://mock.test/json/missing")).await.unwrap();
+        assert_eq!((missing.status_code, missing.json.is_none()), (404, true));
+        assert_eq!(missing.body, "<html>not found</html>");
+    }
+
+    #[tokio::test]
+    async fn metadata_is_echoed_on_success_and_failure() {
+        let (client, _) = mock(MockTransport::new(|_| hyper::Response::new(Bytes::from_static(b"<html>"))));
+        const TOKEN: &str = r#"{"screen":"feed","attempt":2}"#;
+        let plain = HttpRequest { metadata: Some(TOKEN), ..request("GET", "http://mock.test/metadata") };
+        assert_eq!(client.execute_request(plain).await.unwrap().metadata.as_deref(), Some(TOKEN));
+        let failing = HttpRequest { metadata: Some(TOKEN), json_response: true, ..request("GET", "http://mock.test/metadata") };
+        let error = client.execute_request(failing).await.expect_err("body is not JSON");
+        let error = error.downcast_ref::<HttpError>().unwrap();
+        assert_eq!((error.code.as_str(), error.metadata.as_deref()), ("RESPONSE_NOT_JSON", Some(TOKEN)));
+        let untagged = client.execute_request(request("GET", "http://mock.test/metadata")).await.unwrap();
+        assert_eq!(untagged.metadata, None);
+    }
+
+    #[tokio::test]
+    async fn connect_failures_follow_the_same_gate() {
+        let client = HttpClient::new();
+        let url = refused_url();
+        let post = HttpRequest { negotiate_version: true, ..retried("POST", &url) };
+        let error = client.execute_request(post).await.unwrap_err();
+        assert_eq!((HttpError::code_of(&error), retries_of(&error)), ("CONNECT", None));
+
+        let keyed = with_key(HttpRequest { negotiate_version: true, ..retried("POST", &url) });
+        let error = client.execute_request(keyed).await.unwrap_err();
+        assert_eq!((HttpError::code_of(&error), retries_of(&error)), ("CONNECT", Some(3)));
+    }
+
+    #[tokio::test]
+    async fn every_retry_starts_over_from_the_primary_url() {
+        let retried_fallback = HttpRequest {
+            fallback_urls: vec!["http://mock.test/mirror"],
+            fallback_on_status: true,
+            ..retried("GET", "http://mock.test/primary")
+        };
+        let (client, transport) = mock(flaky(3));
+        let response = client.execute_request(retried_fallback).await.unwrap();
+        assert_eq!((response.status_code, response.retries), (200, 1));
+        assert_eq!(
+            transport.requests(),
+            ["GET http://mock.test/primary", "GET http://mock.test/mirror", "GET http://mock.test/primary", "GET http://mock.test/mirror"]
+        );
+    }
+
+    #[tokio::test]
+    async fn fallback_urls_follow_the_same_gate() {
+        let fallback = |method| HttpRequest { fallback_urls: vec!["http://mock.test/mirror"], fallback_on_status: true, ..request(method, "http://mock.test/primary") };
+
+        let (client, transport) = mock(flaky(1));
+        let response = client.execute_request(fallback("POST")).await.unwrap();
+        assert_eq!((response.status_code, response.url_attempts.len()), (503, 1));
+        assert_eq!(transport.requests(), ["POST http://mock.test/primary"]);
+
+        let (client, transport) = mock(flaky(1));
+        let response = client.execute_request(with_key(fallback("POST"))).await.unwrap();
+        assert_eq!((response.status_code, response.url_attempts.len()), (200, 2));
+        assert_eq!(transport.requests(), ["POST http://mock.test/primary", "POST http://mock.test/mirror"]);
+
+        let (client, _) = mock(flaky(1));
+        assert_eq!(client.execute_request(fallback("GET")).await.unwrap().status_code, 200);
+    }
+
+    #[tokio::test]
+    async fn connection_stats_book_each_url_attempt_against_its_host() {
+        let request = HttpRequest {
+            fallback_urls: vec!["http://mirror.stats.test/"],
+            fallback_on_status: true,
+            ..request("GET", "http://primary.stats.test/")
+        };
+        let (client, _) = mock(flaky(1));
+        assert_eq!(client.execute_request(request).await.unwrap().status_code, 200);
+
+        let stats = ConnectionStats::snapshot();
+        let counts = |host: &str| stats.get(host).map(|stats| (stats.requests, stats.in_use, stats.peak_in_use));
+        assert_eq!(counts("primary.stats.test"), Some((1, 0, 1)));
+        assert_eq!(counts("mirror.stats.test"), Some((1, 0, 1)));
+
+        // A fresh cache hit never reaches the network
+        let (client, _) = mock(cacheable(&[("cache-control", "max-age=60")]));
+        client.execute_request(cached("http://cached.stats.test/", &[])).await.unwrap();
+        let hit = client.execute_request(cached("http://cached.stats.test/", &[])).await.unwrap();
+        assert_eq!(hit.source, ResponseSource::CacheFresh);
+        assert_eq!(ConnectionStats::snapshot()["cached.stats.test"].requests, 1);
+    }
+
+    #[tokio::test]
+    async fn unreachable_primary_falls_back_to_mirror() {
+        let server = LocalServer::start(|_| http1_response("200 OK", "", b"mirror")).await;
+        let (primary, mirror) = (refused_url(), format!("http://{}/mirror", server.addr));
+        let request = HttpRequest { fallback_urls: vec![&mirror], negotiate_version: true, ..request("GET", &primary) };
+        let response = HttpClient::new().execute_request(request).await.unwrap();
+        assert_eq!((response.status_code, response.body.as_str()), (200, "mirror"));
+        let attempts: Vec<_> = response.url_attempts.iter().map(|attempt| (attempt.url.as_str(), attempt.status_code, attempt.error.as_deref())).collect();
+        assert_eq!(attempts, [(primary.as_str(), None, Some("CONNECT")), (mirror.as_str(), Some(200), None)]);
+    }
+
+    /// Mock answering 200 with `headers` and a body counting the requests it has seen
+    fn cacheable(headers: &'static [(&'static str, &'static str)]) -> MockTransport {
+        let seen = std::sync::atomic::AtomicUsize::new(0);
+        MockTransport::new(move |_| {
+            let mut response = hyper::Response::builder();
+            for (name, value) in headers {
+                response = response.header(*name, *value);
+            }
+            response.body(Bytes::from((seen.fetch_add(1, Ordering::SeqCst) + 1).to_string())).unwrap()
+        })
+    }
+
+    fn cached<'a>(url: &'a str, headers: &[(&'a str, &'a str)]) -> HttpRequest<'a> {
+        HttpRequest { cache_mode: Some(CacheMode::Etag), headers: headers.iter().copied().collect(), ..request("GET", url) }
+    }
+
+    #[tokio::test]
+    async fn cache_is_not_shared_across_authorization() {
+        let (client, _) = mock(cacheable(&[("cache-control", "max-age=60")]));
+        let url = "http://mock.test/cache/authorization";
+        let alice = client.execute_request(cached(url, &[("Authorization", "Bearer alice")])).await.unwrap();
+        let bob = client.execute_request(cached(url, &[("Authorization", "Bearer bob")])).await.unwrap();
+        let alice_again = client.execute_request(cached(url, &[("Authorization", "Bearer alice")])).await.unwrap();
+        assert_eq!((alice.body.as_str(), bob.body.as_str(), alice_again.body.as_str()), ("1", "2", "1"));
+        assert_eq!((bob.source, alice_again.source), (ResponseSource::Network, ResponseSource::CacheFresh));
+    }
+
+    #[tokio::test]
+    async fn cache_keys_on_vary_headers() {
+        let (client, _) = mock(cacheable(&[("cache-control", "max-age=60"), ("vary", "Accept")]));
+        let url = "http://mock.test/cache/vary";
+        let json = client.execute_request(cached(url, &[("Accept", "application/json")])).await.unwrap();
+        let xml = client.execute_request(cached(url, &[("Accept", "application/xml")])).await.unwrap();
+        let json_again = client.execute_request(cached(url, &[("Accept", "application/json")])).await.unwrap();
+        assert_eq!((json.body.as_str(), xml.body.as_str(), json_again.body.as_str()), ("1", "2", "1"));
+    }
+
+    #[tokio::test]
+    async fn private_no_store_and_vary_star_are_not_cached() {
+        for (path, headers) in [
+            ("private", &[("cache-control", "private, max-age=60")][..]),
+            ("no-store", &[("cache-control", "no-store, max-age=60")][..]),
+            ("vary-star", &[("cache-control", "max-age=60"), ("vary", "*")][..]),
+        ] {
+            let (client, transport) = mock(cacheable(headers));
+            let url = format!("http://mock.test/cache/{}", path);
+            for _ in 0..2 {
+                let response = client.execute_request(cached(&url, &[])).await.unwrap();
+                assert_eq!(response.source, ResponseSource::Network, "{}", path);
+            }
+            assert_eq!(transport.requests().len(), 2, "{}", path);
+        }
+    }
+
+    #[tokio::test]
+    async fn raw_body_requests_bypass_the_cache() {
+        const BINARY: &[u8] = &[0x89, b'P', b'N', b'G', 0xff, 0x00, 0xfe];
+        let (client, transport) = mock(MockTransport::new(|_| {
+            hyper::Response::builder().header("cache-control", "max-age=60").body(Bytes::from_static(BINARY)).unwrap()
+        }));
+        let url = "http://mock.test/cache/binary";
+        let text = client.execute_request(cached(url, &[])).await.unwrap();
+        assert_eq!(text.source, ResponseSource::Network);
+        let raw = client.execute_request(HttpRequest { raw_body: true, ..cached(url, &[]) }).await.unwrap();
+        assert_eq!(raw.source, ResponseSource::Network);
+        assert_eq!(raw.raw_body.as_deref(), Some(BINARY));
+        assert_eq!(transport.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn every_byte_value_survives_raw_and_base64_bodies() {
+        let all: Bytes = (0..=255u8).collect();
+        let body = all.clone();
+        let (client, _) = mock(MockTransport::new(move |_| hyper::Response::new(body.clone())));
+        let raw = client.execute_request(HttpRequest { raw_body: true, ..request("GET", "http://mock.test/bytes") }).await.unwrap();
+        assert_eq!(raw.raw_body.as_ref(), Some(&all));
+        let encoded = client.execute_request(HttpRequest { base64_body: true, ..request("GET", "http://mock.test/bytes") }).await.unwrap();
+        assert_eq!(encoded.body_encoding.as_deref(), Some("base64"));
+        assert_eq!(base64::engine::general_purpose::STANDARD.decode(&encoded.body).unwrap(), all);
+    }
+
+    #[tokio::test]
+    async fn http2_is_forced_unless_the_request_opts_out() {
+        let (client, _) = mock(MockTransport::new(|request| hyper::Response::new(Bytes::from(format!("{:?}", request.version())))));
+        let version = |request| async { client.execute_request(request).await.unwrap().body };
+        assert_eq!(version(request("GET", "http://mock.test/version")).await, "HTTP/2.0");
+        assert_eq!(version(HttpRequest { negotiate_version: true, ..request("GET", "http://mock.test/version") }).await, "HTTP/1.1");
+        assert_eq!(version(HttpRequest { preserve_header_case: true, ..request("GET", "http://mock.test/version") }).await, "HTTP/1.1");
+        #[cfg(feature = "http3")]
+        assert_eq!(version(HttpRequest { http3_only: true, ..request("GET", "http://mock.test/version") }).await, "HTTP/3.0");
+    }
+
+    fn gzipped(text: &str) -> Vec<u8> {
+        use std::io::Write;
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(text.as_bytes()).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[tokio::test]
+    async fn decompress_false_keeps_the_encoded_body() {
+        let server = LocalServer::start(|_| http1_response("200 OK", "content-encoding: gzip\r\n", &gzipped("hello"))).await;
+        let url = format!("http://{}/gzip", server.addr);
+        let client = HttpClient::new();
+        let fetch = |decompress| HttpRequest { decompress, raw_body: true, negotiate_version: true, ..request("GET", &url) };
+
+        let encoded = client.execute_request(fetch(false)).await.unwrap();
+        assert_eq!(encoded.raw_body.as_deref(), Some(&gzipped("hello")[..]));
+        assert_eq!(encoded.headers.get("content-encoding").map(String::as_str), Some("gzip"));
+        let decoded = client.execute_request(fetch(true)).await.unwrap();
+        assert_eq!(decoded.raw_body.as_deref(), Some(&b"hello"[..]));
+    }
+
+    #[tokio::test]
+    async fn every_set_cookie_header_is_kept() {
+        let (client, _) = mock(MockTransport::new(|_| {
+            hyper::Response::builder()
+                .header("set-cookie", "session=abc; Path=/; HttpOnly")
+                .header("set-cookie", "theme=dark; Expires=Wed, 21 Oct 2026 07:28:00 GMT")
+                .body(Bytes::new())
+                .unwrap()
+        }));
+        let response = client.execute_request(request("GET", "http://mock.test/login")).await.unwrap();
+        assert_eq!(response.set_cookies, ["session=abc; Path=/; HttpOnly", "theme=dark; Expires=Wed, 21 Oct 2026 07:28:00 GMT"]);
+    }
+
+    #[tokio::test]
+    async fn streamed_body_is_posted_in_full() {
+        use crate::dart_dl_ffi::{tests as port, PortEvent};
+        port::capture();
+        let (client, _) = mock(MockTransport::with_handler(|_| async {
+            let chunks = stream::iter((0..10).map(|_| Ok::<_, std::io::Error>(Bytes::from(vec![7u8; 10_000]))));
+            hyper::Response::new(Body::wrap_stream(chunks))
+        }));
+        let request = HttpRequest { response_stream_id: Some(511_000), ..request("GET", "http://mock.test/stream") };
+        let response = client.execute_request(request).await.unwrap();
+        assert_eq!((response.bytes_received, response.body.len()), (100_000, 0));
+        let chunks = port::posted(511_000, PortEvent::BodyChunk);
+        assert_eq!(chunks.iter().map(Vec::len).sum::<usize>(), 100_000);
+        assert!(chunks.iter().flatten().all(|&byte| byte == 7));
+    }
+
+    #[tokio::test]
+    async fn upload_progress_rises_to_the_body_size() {
+        use crate::dart_dl_ffi::{tests as port, PortEvent};
+        #[derive(serde::Deserialize)]
+        struct Progress {
+            sent: u64,
+            total: u64,
+        }
+        port::capture();
+        let (transport, recorded) = body_recorder();
+        let (client, _) = mock(transport);
+        let body = Bytes::from(vec![1u8; 300_000]);
+        let request = HttpRequest { body_bytes: Some(body), upload_stream_id: Some(512_000), ..request("PUT", "http://mock.test/upload") };
+        client.execute_request(request).await.unwrap();
+        assert_eq!(recorded.lock().len(), 300_000);
+
+        let events: Vec<Progress> = port::posted(512_000, PortEvent::UploadProgress)
+            .into_iter()
+            .map(|mut event| json::from_slice(&mut event).unwrap())
+            .collect();
+        assert!(events.windows(2).all(|pair| pair[0].sent <= pair[1].sent));
+        assert!(events.iter().all(|event| event.total == 300_000));
+        assert_eq!(events.last().map(|event| event.sent), Some(300_000));
+    }
+
+    #[tokio::test]
+    async fn download_writes_the_whole_body_to_the_file() {
+        let (client, _) = mock(MockTransport::new(|_| {
+            hyper::Response::builder().header("content-length", "250000").body(Bytes::from(vec![9u8; 250_000])).unwrap()
+        }));
+        let path = std::env::temp_dir().join(format!("download-to-file-{}", std::process::id()));
+        let request = HttpRequest { download_path: path.to_str(), ..request("GET", "http://mock.test/file") };
+        let response = client.execute_request(request).await.unwrap();
+        let download = response.download.expect("download info");
+        let written = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!((download.bytes_written, download.file_size, written.len()), (250_000, 250_000, 250_000));
+        assert!(response.body.is_empty() && written.iter().all(|&byte| byte == 9));
+    }
+
+    /// Answers `status` with `headers`, sends `b"-more"` and then stalls mid-body
+    fn stalling_download(status: u16, headers: &'static [(&'static str, &'static str)]) -> MockTransport {
+        MockTransport::with_handler(move |_| async move {
+            let chunks = stream::once(async { Ok::<_, std::io::Error>(Bytes::from_static(b"-more")) }).chain(stream::pending());
+            let mut response = hyper::Response::builder().status(status);
+            for (name, value) in headers {
+                response = response.header(*name, *value);
+            }
+            response.body(Body::wrap_stream(chunks)).unwrap()
+        })
+    }
+
+    #[tokio::test]
+    async fn failed_download_deletes_a_fresh_file_and_keeps_a_resumed_one() {
+        let path = std::env::temp_dir().join(format!("download-partial-{}", std::process::id()));
+        let download = |resume_download| HttpRequest {
+            download_path: path.to_str(),
+            resume_download,
+            if_range: Some("\"v1\""),
+            read_timeout_ms: 100,
+            ..request("GET", "http://mock.test/file")
+        };
+
+        let (client, _) = mock(stalling_download(200, &[("content-length", "100")]));
+        assert_eq!(code(client.execute_request(download(false)).await), "READ_TIMEOUT");
+        assert!(!path.exists(), "a fresh partial file must be deleted");
+
+        std::fs::write(&path, b"first").unwrap();
+        let (client, _) = mock(stalling_download(206, &[("content-range", "bytes 5-104/105")]));
+        let result = client.execute_request(download(true)).await;
+        let written = std::fs::read(&path);
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(code(result), "READ_TIMEOUT");
+        assert!(written.unwrap().starts_with(b"first"), "a resumed file keeps its valid part");
+    }
+
+    /// 200 with `x-filler-0` .. `x-filler-119`, more than hyper's 100 HTTP/1.1 headers
+    fn many_headers(_: &str) -> Vec<u8> {
+        let headers: String = (0..120).map(|i| format!("x-filler-{}: {}\r\n", i, i)).collect();
+        http1_response("200 OK", &headers, b"")
+    }
+
+    #[tokio::test]
+    async fn oversized_response_heads_fail_with_headers_too_large() {
+        let server = LocalServer::start(many_headers).await;
+        let url = format!("http://{}/headers", server.addr);
+        let response = HttpClient::new().execute_request(HttpRequest { negotiate_version: true, ..request("GET", &url) }).await;
+        assert_eq!(code(response), "HEADERS_TOO_LARGE");
+
+        let (client, _) = mock(MockTransport::new(|_| {
+            let mut response = hyper::Response::builder();
+            for i in 0..30 {
+                response = response.header(format!("x-filler-{}", i), "1");
+            }
+            response.body(Bytes::new()).unwrap()
+        }));
+        let set_max = |max| {
+            let mut settings = ClientConfig::settings().clone();
+            settings.max_response_headers = max;
+            ClientConfig::set_settings(settings);
+        };
+        set_max(Some(25));
+        let response = client.execute_request(request("GET", "http://mock.test/headers")).await;
+        set_max(None);
+        let error = response.unwrap_err();
+        let details = error.downcast_ref::<HttpError>().and_then(|error| error.details.clone());
+        assert_eq!(HttpError::code_of(&error), "HEADERS_TOO_LARGE");
+        assert_eq!(details, Some(json!({ "header_count": 30, "max_response_headers": 25 })));
+    }
+
+    /// `/login` sets a session cookie; everything else answers with the `Cookie` it was sent
+    fn session(head: &str) -> Vec<u8> {
+        if head.starts_with("GET /login ") {
+            return http1_response("200 OK", "set-cookie: session=s515; Path=/\r\n", b"");
+        }
+        let cookie = head.lines().find_map(|line| line.strip_prefix("cookie: ")).unwrap_or_default();
+        http1_response("200 OK", "", cookie.as_bytes())
+    }
+
+    #[tokio::test]
+    async fn shared_client_sends_cookies_back() {
+        let server = LocalServer::start(session).await;
+        let (login, profile) = (format!("http://{}/login", server.addr), format!("http://{}/profile", server.addr));
+        let client = HttpClient::shared();
+        client.execute_request(HttpRequest { negotiate_version: true, ..request("GET", &login) }).await.unwrap();
+        let response = client.execute_request(HttpRequest { negotiate_version: true, ..request("GET", &profile) }).await.unwrap();
+        assert_eq!(response.body, "session=s515");
+        // A request setting `Cookie` itself is sent as is
+        let headers = HashMap::from([("cookie", "session=mine")]);
+        let own = client.execute_request(HttpRequest { headers, negotiate_version: true, ..request("GET", &profile) }).await.unwrap();
+        assert_eq!(own.body, "session=mine");
+    }
+
+    #[tokio::test]
+    async fn requests_go_through_the_request_proxy() {
+        let proxy = LocalServer::start(|_| http1_response("200 OK", "", b"via proxy")).await;
+        let proxy_url = format!("http://{}", proxy.addr);
+        let client = HttpClient::new();
+        let proxied = HttpRequest {
+            proxy_url: Some(&proxy_url),
+            proxy_username: Some("user"),
+            proxy_password: Some("pass"),
+            negotiate_version: true,
+            ..request("GET", "http://origin.proxy.test/page")
+        };
+        assert_eq!(client.execute_request(proxied).await.unwrap().body, "via proxy");
+        let head = proxy.heads.lock()[0].clone();
+        assert!(head.starts_with("GET http://origin.proxy.test/page HTTP/1.1"), "{}", head);
+        assert!(head.contains("\r\nproxy-authorization: Basic dXNlcjpwYXNz"), "{}", head);
+
+        let unreachable = refused_url();
+        let refused = HttpRequest { proxy_url: Some(&unreachable), negotiate_version: true, ..request("GET", "http://origin.proxy.test/") };
+        assert_eq!(code(client.execute_request(refused).await), "PROXY");
+        let ftp = HttpRequest { proxy_url: Some("ftp://proxy.test:21"), ..request("GET", "http://origin.proxy.test/") };
+        assert_eq!(code(client.execute_request(ftp).await), "INVALID_PROXY");
+    }
+
+    #[tokio::test]
+    async fn auth_helpers_set_the_authorization_header() {
+        let (client, _) = mock(MockTransport::new(|request| {
+            hyper::Response::new(request.headers().get(AUTHORIZATION).map(|value| Bytes::copy_from_slice(value.as_bytes())).unwrap_or_default())
+        }));
+        let url = "http://mock.test/auth";
+        let sent = |request| async { client.execute_request(request).await.unwrap().body };
+        assert_eq!(sent(HttpRequest { bearer_token: Some("t0k3n"), ..request("GET", url) }).await, "Bearer t0k3n");
+        // Only the username is split on ':', so a password keeps its colons
+        assert_eq!(sent(HttpRequest { basic_auth: Some(("user", "p:a:ss")), ..request("GET", url) }).await, "Basic dXNlcjpwOmE6c3M=");
+        let explicit = HttpRequest { headers: HashMap::from([("authorization", "Custom x")]), bearer_token: Some("t0k3n"), ..request("GET", url) };
+        assert_eq!(sent(explicit).await, "Custom x");
+        assert_eq!(code(client.execute_request(HttpRequest { bearer_token: Some("bad\ntoken"), ..request("GET", url) }).await), "INVALID_REQUEST");
+    }
+
+    /// TLS acceptor offering `alpn`, with a certificate for `localhost` / 127.0.0.1 issued by
+    /// `testdata/ca.pem`
+    fn tls_acceptor(alpn: &[u8]) -> tokio_rustls::TlsAcceptor {
+        use rustls::pki_types::pem::PemObject;
+        use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+        let certs = CertificateDer::pem_slice_iter(include_bytes!("../testdata/server.pem")).collect::<Result<Vec<_>, _>>().unwrap();
+        let key = PrivateKeyDer::from_pem_slice(include_bytes!("../testdata/server.key")).unwrap();
+        let mut config = rustls::ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(certs, key)
+            .unwrap();
+        config.alpn_protocols = vec![alpn.to_vec()];
+        tokio_rustls::TlsAcceptor::from(Arc::new(config))
+    }
+
+    /// HTTPS server for `localhost` / 127.0.0.1 speaking HTTP/1.1
+    async fn tls_server() -> std::net::SocketAddr {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        let acceptor = tls_acceptor(b"http/1.1");
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                let acceptor = acceptor.clone();
+                tokio::spawn(async move {
+                    let Ok(mut tls) = acceptor.accept(socket).await else { return };
+                    let (mut buf, mut received) = ([0u8; 4096], Vec::new());
+                    while !received.windows(4).any(|window| window == b"\r\n\r\n") {
+                        match tls.read(&mut buf).await {
+                            Ok(read @ 1..) => received.extend_from_slice(&buf[..read]),
+                            _ => return,
+                        }
+                    }
+                    let _ = tls.write_all(&http1_response("200 OK", "connection: close\r\n", b"trusted")).await;
+                    let _ = tls.shutdown().await;
+                });
+            }
+        });
+        addr
+    }
+
+    /// HTTPS server for `localhost` / 127.0.0.1 speaking only h2, answering every stream
+    /// with an empty 200; counts the connections it accepted
+    async fn h2_tls_server() -> (std::net::SocketAddr, Arc<std::sync::atomic::AtomicUsize>) {
+        let acceptor = tls_acceptor(b"h2");
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let connections = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let accepted = Arc::clone(&connections);
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                accepted.fetch_add(1, Ordering::SeqCst);
+                let acceptor = acceptor.clone();
+                tokio::spawn(async move {
+                    let Ok(tls) = acceptor.accept(socket).await else { return };
+                    let Ok(mut connection) = h2::server::handshake(tls).await else { return };
+                    while let Some(Ok((_, mut respond))) = connection.accept().await {
+                        let _ = respond.send_response(hyper::Response::new(()), true);
+                    }
+                });
+            }
+        });
+        (addr, connections)
+    }
+
+    /// Serializes the tests that trust `testdata/ca.pem`. Dropping it forgets the added roots
+    /// and rebuilds the global clients, also when the test fails
+    struct TrustedRoots(#[allow(dead_code)] tokio::sync::MutexGuard<'static, ()>);
+
+    static TRUST_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+    impl TrustedRoots {
+        async fn lock() -> Self {
+            Self(TRUST_LOCK.lock().await)
+        }
+
+        fn add_test_ca(&self) {
+            assert_eq!(ClientConfig::add_root_certificates(include_bytes!("../testdata/ca.pem")).unwrap(), 1);
+        }
+    }
+
+    impl Drop for TrustedRoots {
+        fn drop(&mut self) {
+            ClientConfig::clear_root_certificates();
+            crate::CLIENT.rebuild_clients();
+        }
+    }
+
+    #[tokio::test]
+    async fn added_root_certificate_is_trusted_by_clients_built_afterwards() {
+        let trust = TrustedRoots::lock().await;
+        let addr = tls_server().await;
+        let url = format!("https://localhost:{}/", addr.port());
+        let fetch = |client: HttpClient| {
+            let url = url.clone();
+            async move { client.execute_request(HttpRequest { negotiate_version: true, ..request("GET", &url) }).await }
+        };
+        assert_eq!(code(fetch(HttpClient::new()).await), "TLS");
+
+        trust.add_test_ca();
+        assert_eq!(fetch(HttpClient::new()).await.unwrap().body, "trusted");
+
+        let error = ClientConfig::add_root_certificates(b"-----BEGIN GARBAGE-----").unwrap_err();
+        assert_eq!(HttpError::code_of(&error), "INVALID_CERTIFICATE");
+        assert_eq!(ClientConfig::root_certificate_count(), 1);
+    }
+
+    #[tokio::test]
+    async fn add_root_certificate_rebuilds_the_shared_client() {
+        let _trust = TrustedRoots::lock().await;
+        let addr = tls_server().await;
+        let url = format!("https://localhost:{}/", addr.port());
+        let fetch = || crate::CLIENT.execute_request(HttpRequest { negotiate_version: true, ..request("GET", &url) });
+        assert_eq!(code(fetch().await), "TLS");
+
+        let pem = include_bytes!("../testdata/ca.pem");
+        assert_eq!(crate::add_root_certificate(pem.as_ptr(), pem.len()), 1);
+        assert_eq!(fetch().await.unwrap().body, "trusted");
+
+        crate::clear_root_certificates();
+        assert_eq!(code(fetch().await), "TLS");
+    }
+
+    /// `localhost` and 127.0.0.1 share an address and one certificate valid for both, which
+    /// is when a browser would coalesce them onto one HTTP/2 connection; the pool keeps them apart
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn h2_connections_are_not_coalesced_across_hosts() {
+        let trust = TrustedRoots::lock().await;
+        trust.add_test_ca();
+        let (addr, connections) = h2_tls_server().await;
+        // Not `::1`, which the server does not listen on
+        let _overrides = DnsOverrides::pin(&["localhost"], std::net::SocketAddr::new(addr.ip(), 0));
+        let client = HttpClient::new();
+        for host in ["localhost", "127.0.0.1", "localhost", "127.0.0.1"] {
+            let url = format!("https://{}:{}/", host, addr.port());
+            let response = client.execute_request(HttpRequest { negotiate_version: true, ..request("GET", &url) }).await.unwrap();
+            assert_eq!((response.status_code, response.version.as_str()), (200, "HTTP/2"));
+        }
+        assert_eq!(connections.load(Ordering::SeqCst), 2);
+    }
+}
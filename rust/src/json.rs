@@ -0,0 +1,90 @@
+//! JSON backend for everything the crate parses or serializes: simd-json by default, or
+//! serde_json with the `serde-json` feature, for targets where simd-json's SIMD paths are
+//! missing or slower (older ARM cores) and to compare the two on a device. `OwnedValue`
+//! and `json!` come from the same backend, so a build without the default `simd-json`
+//! feature does not compile simd-json at all.
+
+use anyhow::Result;
+use serde::de::{Deserialize, DeserializeOwned};
+use serde::Serialize;
+use std::io::Write;
+
+#[cfg(not(any(feature = "simd-json", feature = "serde-json")))]
+compile_error!("enable a JSON backend: the `simd-json` (default) or `serde-json` feature");
+
+#[cfg(feature = "serde-json")]
+use serde_json as backend;
+#[cfg(not(feature = "serde-json"))]
+use simd_json as backend;
+
+#[cfg(feature = "serde-json")]
+pub use serde_json::{json, Value as OwnedValue};
+#[cfg(not(feature = "serde-json"))]
+pub use simd_json::{json, OwnedValue};
+
+/// Value accessors (`get`, `as_u64`, `as_object_mut`): traits for simd-json, inherent
+/// methods for serde_json
+pub mod prelude {
+    #[cfg(not(feature = "serde-json"))]
+    pub use simd_json::prelude::{ValueAsMutObject, ValueAsScalar, ValueObjectAccess};
+}
+
+/// A JSON buffer that borrowing types (`HttpRequest<'_>`) are deserialized from.
+/// simd-json unescapes strings in place, so borrowed `&str` fields can point into the
+/// buffer; serde_json cannot borrow an escaped string from raw input, so it parses into
+/// a `Value` first and the fields borrow from that.
+pub struct Document {
+    bytes: Vec<u8>,
+    #[cfg(feature = "serde-json")]
+    value: Option<serde_json::Value>,
+}
+
+impl Document {
+    pub fn new(bytes: Vec<u8>) -> Self {
+        Self {
+            bytes,
+            #[cfg(feature = "serde-json")]
+            value: None,
+        }
+    }
+
+    #[cfg(not(feature = "serde-json"))]
+    pub fn deserialize<'a, T: Deserialize<'a>>(&'a mut self) -> Result<T> {
+        Ok(backend::from_slice(&mut self.bytes)?)
+    }
+
+    #[cfg(feature = "serde-json")]
+    pub fn deserialize<'a, T: Deserialize<'a>>(&'a mut self) -> Result<T> {
+        let value: &'a serde_json::Value = self.value.insert(backend::from_slice(&self.bytes)?);
+        Ok(T::deserialize(value)?)
+    }
+}
+
+/// Owned types only; see `Document` for borrowing ones. `bytes` may be clobbered.
+pub fn from_slice<T: DeserializeOwned>(bytes: &mut [u8]) -> Result<T> {
+    Ok(backend::from_slice(bytes)?)
+}
+
+/// Any JSON document as an `OwnedValue`. `bytes` may be clobbered.
+#[cfg(not(feature = "serde-json"))]
+pub fn to_owned_value(bytes: &mut [u8]) -> Result<OwnedValue> {
+    Ok(simd_json::to_owned_value(bytes)?)
+}
+
+/// Any JSON document as an `OwnedValue`
+#[cfg(feature = "serde-json")]
+pub fn to_owned_value(bytes: &mut [u8]) -> Result<OwnedValue> {
+    Ok(serde_json::from_slice(bytes)?)
+}
+
+pub fn to_vec<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>> {
+    Ok(backend::to_vec(value)?)
+}
+
+pub fn to_writer<W: Write, T: Serialize + ?Sized>(writer: W, value: &T) -> Result<()> {
+    Ok(backend::to_writer(writer, value)?)
+}
+
+pub fn to_string<T: Serialize + ?Sized>(value: &T) -> Result<String> {
+    Ok(backend::to_string(value)?)
+}
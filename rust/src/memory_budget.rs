@@ -0,0 +1,146 @@
+use crate::client_config::ClientConfig;
+use crate::json::json;
+use crate::models::HttpError;
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::time::Instant;
+
+/// Bytes currently reserved by all in-flight response bodies
+static IN_USE: AtomicU64 = AtomicU64::new(0);
+/// Woken whenever a reservation is released, so waiters can retry
+static RELEASED: Notify = Notify::const_new();
+
+/// Global cap on response bytes buffered in memory at once (`ClientSettings::memory_budget_bytes`).
+/// A body reserves its `Content-Length` before the first byte is read and grows the
+/// reservation as chunks beyond that arrive; bodies of unknown length (chunked, or
+/// decompressed) therefore reserve from their first chunk on. The reservation is held
+/// until the `HttpResponse` is built; bodies going to a `download_path` and `headers_only`
+/// responses never reserve.
+pub struct MemoryBudget;
+
+impl MemoryBudget {
+    /// Reserves `bytes` for one body of `url`; `None` when no budget is configured
+    pub async fn reserve(url: &str, bytes: u64) -> Result<Option<Reservation>, HttpError> {
+        let (budget, wait_ms) = {
+            let settings = ClientConfig::settings();
+            (settings.memory_budget_bytes, settings.memory_budget_wait_ms)
+        };
+        if budget == 0 {
+            return Ok(None);
+        }
+        let mut reservation = Reservation {
+            url: url.to_owned(),
+            budget,
+            deadline: Instant::now() + Duration::from_millis(wait_ms),
+            bytes: 0,
+        };
+        reservation.grow(bytes).await?;
+        Ok(Some(reservation))
+    }
+
+    pub fn in_use() -> u64 {
+        IN_USE.load(Ordering::Acquire)
+    }
+}
+
+/// Share of the budget held by one body, returned on drop
+pub struct Reservation {
+    url: String,
+    budget: u64,
+    deadline: Instant,
+    bytes: u64,
+}
+
+impl Reservation {
+    /// Extends the reservation to `total` bytes. While nothing is reserved yet, a full budget
+    /// is waited on until `memory_budget_wait_ms` after the reservation was created; once a
+    /// body holds part of the budget it never waits for more (bodies stuck holding budget
+    /// while waiting on each other would never finish), so that fails with
+    /// `MEMORY_BUDGET_EXCEEDED` at once, as does a body larger than the whole budget.
+    pub async fn grow(&mut self, total: u64) -> Result<(), HttpError> {
+        if total <= self.bytes {
+            return Ok(());
+        }
+        if total > self.budget {
+            return Err(self.exceeded(total));
+        }
+        let extra = total - self.bytes;
+        loop {
+            // Registered before the check, so a release in between still wakes us
+            let released = RELEASED.notified();
+            tokio::pin!(released);
+            released.as_mut().enable();
+
+            let budget = self.budget;
+            if IN_USE.fetch_update(Ordering::AcqRel, Ordering::Acquire, |used| (used + extra <= budget).then_some(used + extra)).is_ok() {
+                self.bytes = total;
+                return Ok(());
+            }
+            if self.bytes > 0 || tokio::time::timeout_at(self.deadline, released).await.is_err() {
+                return Err(self.exceeded(total));
+            }
+        }
+    }
+
+    fn exceeded(&self, total: u64) -> HttpError {
+        let in_use = MemoryBudget::in_use();
+        HttpError::new(
+            "MEMORY_BUDGET_EXCEEDED",
+            format!("{}: buffering {} bytes would exceed memory_budget_bytes ({}, {} in use)", self.url, total, self.budget, in_use),
+        )
+        .with_details(json!({ "requested": total, "in_use": in_use, "memory_budget_bytes": self.budget }))
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        if self.bytes > 0 {
+            IN_USE.fetch_sub(self.bytes, Ordering::AcqRel);
+            RELEASED.notify_waiters();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reservation(budget: u64, wait_ms: u64) -> Reservation {
+        Reservation { url: "http://budget.test/".to_owned(), budget, deadline: Instant::now() + Duration::from_millis(wait_ms), bytes: 0 }
+    }
+
+    #[tokio::test]
+    async fn full_budget_waits_then_fails_without_deadlocking_holders() {
+        let mut first = reservation(100, 0);
+        first.grow(60).await.unwrap();
+
+        // Nothing held yet: waits for a release within the deadline
+        let mut second = reservation(100, 5_000);
+        let released = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            drop(first);
+        });
+        second.grow(50).await.unwrap();
+        released.await.unwrap();
+        assert_eq!(MemoryBudget::in_use(), 50);
+
+        // Nothing held, no release before the deadline
+        let error = reservation(100, 30).grow(60).await.unwrap_err();
+        assert_eq!(error.code, "MEMORY_BUDGET_EXCEEDED");
+
+        // Already holding part of the budget: fails at once instead of waiting
+        let mut third = reservation(100, 5_000);
+        third.grow(40).await.unwrap();
+        let started = Instant::now();
+        assert_eq!(second.grow(70).await.unwrap_err().code, "MEMORY_BUDGET_EXCEEDED");
+        assert!(started.elapsed() < Duration::from_secs(1));
+
+        // Larger than the whole budget
+        assert_eq!(reservation(100, 5_000).grow(101).await.unwrap_err().code, "MEMORY_BUDGET_EXCEEDED");
+
+        drop((second, third));
+        assert_eq!(MemoryBudget::in_use(), 0);
+    }
+}
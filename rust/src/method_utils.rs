@@ -1,6 +1,10 @@
+use reqwest::header::HeaderMap;
 use reqwest::Method;
 use anyhow::Result;
 
+/// Header whose presence lets a non-idempotent request be retried (IETF httpapi draft)
+pub const IDEMPOTENCY_KEY: &str = "idempotency-key";
+
 pub struct MethodUtils;
 
 impl MethodUtils {
@@ -51,4 +55,12 @@ impl MethodUtils {
             &Method::GET | &Method::HEAD | &Method::PUT | &Method::DELETE | &Method::OPTIONS | &Method::TRACE
         )
     }
+
+    /// Whether a request can be sent again automatically (`max_retries`, `fallback_urls`):
+    /// only idempotent methods, or any method carrying a non-empty `Idempotency-Key`.
+    /// Only `connect_attempts`, which repeats nothing but the TCP/TLS handshake, skips this gate.
+    pub fn is_retry_eligible(method: &Method, headers: &HeaderMap) -> bool {
+        Self::is_idempotent_method(method)
+            || headers.get(IDEMPOTENCY_KEY).is_some_and(|key| !key.as_bytes().is_empty())
+    }
 }
\ No newline at end of file
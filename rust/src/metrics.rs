@@ -0,0 +1,187 @@
+use crate::client_config::AddressFamily;
+use crate::models::{HttpError, HttpResponse};
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Status classes used as labels, so cardinality stays fixed
+const STATUS_CLASSES: [&str; 5] = ["1xx", "2xx", "3xx", "4xx", "5xx"];
+
+/// Latency histogram upper bounds in seconds (`+Inf` is implied)
+const LATENCY_BUCKETS: [f64; 11] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+struct Metrics {
+    responses: [AtomicU64; STATUS_CLASSES.len()],
+    /// Failed requests by error code; the code set is small and fixed by the crate
+    errors: Mutex<BTreeMap<String, u64>>,
+    latency_buckets: [AtomicU64; LATENCY_BUCKETS.len()],
+    latency_sum_us: AtomicU64,
+    latency_count: AtomicU64,
+    /// New connections and their connect time, indexed by `family_slot`
+    family_connects: [AtomicU64; 2],
+    family_connect_us: [AtomicU64; 2],
+}
+
+static METRICS: Lazy<Metrics> = Lazy::new(|| Metrics {
+    responses: Default::default(),
+    errors: Mutex::new(BTreeMap::new()),
+    latency_buckets: Default::default(),
+    latency_sum_us: AtomicU64::new(0),
+    latency_count: AtomicU64::new(0),
+    family_connects: Default::default(),
+    family_connect_us: Default::default(),
+});
+
+const FAMILIES: [AddressFamily; 2] = [AddressFamily::Ipv4, AddressFamily::Ipv6];
+
+fn family_slot(family: AddressFamily) -> usize {
+    FAMILIES.iter().position(|&slot| slot == family).unwrap_or_default()
+}
+
+/// Process-wide request counters and latency histogram, fed by `HttpClient::execute_request`,
+/// plus the connect time of new connections per address family
+pub struct RequestMetrics;
+
+impl RequestMetrics {
+    /// Records the outcome and total latency of one `execute_request` call
+    pub fn record(result: &anyhow::Result<HttpResponse>, elapsed: Duration) {
+        let metrics = &*METRICS;
+        match result {
+            Ok(response) => {
+                let class = (response.status_code / 100).clamp(1, 5) as usize - 1;
+                metrics.responses[class].fetch_add(1, Ordering::Relaxed);
+            }
+            Err(error) => {
+                *metrics.errors.lock().entry(HttpError::code_of(error).to_owned()).or_insert(0) += 1;
+            }
+        }
+
+        let seconds = elapsed.as_secs_f64();
+        if let Some(bucket) = LATENCY_BUCKETS.iter().position(|&bound| seconds <= bound) {
+            metrics.latency_buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        }
+        metrics.latency_sum_us.fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+        metrics.latency_count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records a new connection over `family` and how long connecting (DNS + TCP + TLS) took
+    pub fn record_family_connect(family: AddressFamily, connect: Duration) {
+        let metrics = &*METRICS;
+        metrics.family_connects[family_slot(family)].fetch_add(1, Ordering::Relaxed);
+        metrics.family_connect_us[family_slot(family)].fetch_add(connect.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    /// Prometheus text exposition format (version 0.0.4)
+    pub fn prometheus() -> String {
+        let metrics = &*METRICS;
+        let mut out = String::with_capacity(2048);
+
+        out.push_str("# HELP http_client_responses_total Responses received, by status class.\n");
+        out.push_str("# TYPE http_client_responses_total counter\n");
+        for (class, count) in STATUS_CLASSES.iter().zip(&metrics.responses) {
+            let _ = writeln!(out, "http_client_responses_total{{class=\"{}\"}} {}", class, count.load(Ordering::Relaxed));
+        }
+
+        out.push_str("# HELP http_client_errors_total Requests that returned an error, by error code (error_on_status failures count as HTTP_STATUS).\n");
+        out.push_str("# TYPE http_client_errors_total counter\n");
+        for (code, count) in metrics.errors.lock().iter() {
+            let _ = writeln!(out, "http_client_errors_total{{code=\"{}\"}} {}", code, count);
+        }
+
+        out.push_str("# HELP http_client_request_duration_seconds Total time per request.\n");
+        out.push_str("# TYPE http_client_request_duration_seconds histogram\n");
+        let mut cumulative = 0;
+        for (bound, count) in LATENCY_BUCKETS.iter().zip(&metrics.latency_buckets) {
+            cumulative += count.load(Ordering::Relaxed);
+            let _ = writeln!(out, "http_client_request_duration_seconds_bucket{{le=\"{}\"}} {}", bound, cumulative);
+        }
+        let count = metrics.latency_count.load(Ordering::Relaxed);
+        let sum = metrics.latency_sum_us.load(Ordering::Relaxed) as f64 / 1_000_000.0;
+        let _ = writeln!(out, "http_client_request_duration_seconds_bucket{{le=\"+Inf\"}} {}", count);
+        let _ = writeln!(out, "http_client_request_duration_seconds_sum {}", sum);
+        let _ = writeln!(out, "http_client_request_duration_seconds_count {}", count);
+
+        out.push_str("# HELP http_client_connect_seconds Connect time (DNS + TCP + TLS) of new connections, by the address family that connected.\n");
+        out.push_str("# TYPE http_client_connect_seconds summary\n");
+        for (slot, family) in FAMILIES.iter().enumerate() {
+            let sum = metrics.family_connect_us[slot].load(Ordering::Relaxed) as f64 / 1_000_000.0;
+            let _ = writeln!(out, "http_client_connect_seconds_sum{{family=\"{}\"}} {}", family.as_str(), sum);
+            let _ = writeln!(out, "http_client_connect_seconds_count{{family=\"{}\"}} {}", family.as_str(), metrics.family_connects[slot].load(Ordering::Relaxed));
+        }
+        out
+    }
+}
+
+/// Per-host connection usage, see `ConnectionStats`
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct HostConnections {
+    /// Attempts currently on the wire, i.e. connections (or h2 streams) in use
+    pub in_use: u64,
+    /// Highest `in_use` seen since start-up
+    pub peak_in_use: u64,
+    /// Attempts sent (every hedge, retry and fallback URL), including ones that reused a
+    /// pooled connection
+    pub requests: u64,
+    /// New connections that completed their handshake (TCP, plus TLS for `https`)
+    pub connections_opened: u64,
+}
+
+static CONNECTIONS: Lazy<Mutex<BTreeMap<String, HostConnections>>> = Lazy::new(|| Mutex::new(BTreeMap::new()));
+
+/// Per-host connection reuse counters.
+/// reqwest keeps its pool private, so open and idle counts are not observable; `requests`
+/// against `connections_opened` shows how often the pool was hit, and `peak_in_use`
+/// bounds how many idle connections per host are worth keeping.
+pub struct ConnectionStats;
+
+/// Decrements `in_use` for its host when the request finishes or is dropped
+pub struct InUseGuard(Option<String>);
+
+impl Drop for InUseGuard {
+    fn drop(&mut self) {
+        if let Some(host) = self.0.take() {
+            if let Some(stats) = CONNECTIONS.lock().get_mut(&host) {
+                stats.in_use = stats.in_use.saturating_sub(1);
+            }
+        }
+    }
+}
+
+impl InUseGuard {
+    /// Rebooks the request against the host of `url` when a redirect ended up elsewhere
+    pub fn follow(&mut self, url: &reqwest::Url) {
+        if url.host_str().is_some_and(|host| self.0.as_deref() != Some(host)) {
+            *self = ConnectionStats::begin(url.as_str());
+        }
+    }
+}
+
+impl ConnectionStats {
+    /// Marks one attempt on `url` as in flight until the guard is dropped
+    pub fn begin(url: &str) -> InUseGuard {
+        let Some(host) = reqwest::Url::parse(url).ok().and_then(|url| url.host_str().map(str::to_owned)) else {
+            return InUseGuard(None);
+        };
+        let mut connections = CONNECTIONS.lock();
+        let stats = connections.entry(host.clone()).or_default();
+        stats.in_use += 1;
+        stats.requests += 1;
+        stats.peak_in_use = stats.peak_in_use.max(stats.in_use);
+        InUseGuard(Some(host))
+    }
+
+    /// Called by the connector layer for every new connection to `host` that succeeded
+    pub fn connection_opened(host: &str) {
+        CONNECTIONS.lock().entry(host.to_owned()).or_default().connections_opened += 1;
+    }
+
+    /// Snapshot keyed by host
+    pub fn snapshot() -> BTreeMap<String, HostConnections> {
+        CONNECTIONS.lock().clone()
+    }
+}
@@ -1,26 +1,462 @@
+use crate::cache::{CacheMode, ResponseSource};
+use crate::content_digest::DigestAlgorithm;
+use crate::json::OwnedValue;
+use crate::method_utils::MethodUtils;
+use crate::sigv4::AwsSigV4;
+use bytes::Bytes;
+use reqwest::Method;
 use serde::{Serialize, Deserialize};
-use simd_json::OwnedValue;
 use std::collections::HashMap;
+use std::fmt;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct HttpRequest<'a> {
     pub url: &'a str,
     pub method: &'a str,
     pub headers: HashMap<&'a str, &'a str>,
     pub body: Option<&'a str>,
     pub query_params: HashMap<&'a str, &'a str>,
+    /// Bounds each attempt from sending until the body is read, failing with `TIMEOUT`;
+    /// 0 keeps the client default (15 s shared, 20 s isolated)
     pub timeout_ms: u64,
+    /// `false` returns a 3xx response as is, `Location` header included
     pub follow_redirects: bool,
+    /// Most redirects followed before failing with `TOO_MANY_REDIRECTS`; 0 keeps the
+    /// client's limit (5 shared, 3 isolated)
     pub max_redirects: usize,
+    /// Bounds opening each new connection (DNS + TCP + TLS), failing with `CONNECT_TIMEOUT`.
+    /// 0 uses `connect_timeouts_by_host`, then the client default.
     pub connect_timeout_ms: u64,
+    /// Bounds reading the response body; exceeding it fails with `READ_TIMEOUT` (0 = no bound).
+    /// Not applied with `partial_body_on_timeout`, which has its own deadline.
     pub read_timeout_ms: u64,
+    /// Bounds uploading the request body, from its first chunk to its last; exceeding it fails
+    /// with `WRITE_TIMEOUT` (0 = no bound). The wait for the response once the body is sent
+    /// is not counted, and requests without a body are unaffected. Applies to each connect attempt.
     pub write_timeout_ms: u64,
     pub auto_referer: bool,
+    /// `false` returns the body as the server sent it, `Content-Encoding` and `Content-Length`
+    /// intact, while still advertising the compiled-in codings unless `Accept-Encoding` is set.
+    /// Compressed bytes are not text: read them with `base64_body`, `execute_request_raw_body`
+    /// or `execute_request_binary_format_raw`. Bypasses the response cache.
     pub decompress: bool,
+    /// Send over HTTP/3 (QUIC) only, failing if the server cannot be reached that way.
+    /// Needs the `http3` cargo feature; without it the request fails validation.
     pub http3_only: bool,
+    /// Let ALPN pick HTTP/2 or HTTP/1.1 instead of forcing HTTP/2. Plain `http://` URLs then
+    /// use HTTP/1.1, where forcing HTTP/2 would need a server speaking h2c.
+    #[serde(default)]
+    pub negotiate_version: bool,
+    /// Report response headers that had to be dropped in `HttpResponse::skipped_headers`
+    #[serde(default)]
+    pub strict_headers: bool,
+    /// Also return every response header in `HttpResponse::headers_ordered`, duplicates included
+    #[serde(default)]
+    pub ordered_headers: bool,
+    /// Typed content negotiation; ignored when `headers` already carries an `Accept`
+    #[serde(default, borrow)]
+    pub accept: Option<Vec<AcceptEntry<'a>>>,
+    /// Overrides the configured default Content-Type for this body (`""` sends none)
+    #[serde(default)]
+    pub default_content_type: Option<&'a str>,
+    /// Overrides `ClientSettings::accept_language`; an `Accept-Language` in `headers` wins over both
+    #[serde(default)]
+    pub accept_language: Option<&'a str>,
+    /// Sends a `multipart/form-data` body built from these parts
+    #[serde(default, borrow)]
+    pub multipart: Option<Vec<MultipartPart<'a>>>,
+    /// Tag for upload progress events posted to the registered Dart port, for every body
+    /// kind (`body_stream_id` only with `body_stream_length`)
+    #[serde(default)]
+    pub upload_stream_id: Option<i64>,
+    /// Body pushed from Dart through `upload_stream_write` (see `upload_stream.rs`)
+    #[serde(default)]
+    pub body_stream_id: Option<i64>,
+    /// Declared size of the `body_stream_id` body: sent as `Content-Length`, enables upload
+    /// progress percentages, and fails the request with `UPLOAD_LENGTH_MISMATCH` if the
+    /// stream delivers a different amount. Without it the body goes out chunked.
+    #[serde(default)]
+    pub body_stream_length: Option<u64>,
+    /// Tag for a `RequestSent` event posted to the Dart port once the body is written and
+    /// only the response is pending. Requests without a body post no event: hyper never
+    /// reports when it has written the headers.
+    #[serde(default)]
+    pub sent_event_id: Option<i64>,
+    /// Turn non-2xx responses into an `HTTP_STATUS` error carrying status and body
+    #[serde(default)]
+    pub error_on_status: bool,
+    /// Opt-in response caching for GET requests. Entries are per URL, `Authorization` and
+    /// the request headers named in `Vary`; `private`, `no-store` and `Vary: *` responses are not kept.
+    /// Entries hold decoded text, so raw-body requests (`execute_request_raw_body`,
+    /// `execute_request_binary_format_raw`) bypass the cache.
+    #[serde(default)]
+    pub cache_mode: Option<CacheMode>,
+    /// Streams this file as the raw body (Content-Length = file size) instead of `body`
+    #[serde(default)]
+    pub body_file: Option<&'a str>,
+    /// Streams these files back to back as one raw body (Content-Length = summed size),
+    /// opening each only when the previous one is done. With `upload_stream_id`, progress
+    /// is posted per file (as `part`, the path) and in aggregate.
+    #[serde(default, borrow)]
+    pub body_files: Option<Vec<&'a str>>,
+    /// On a timeout while reading the body, return what arrived with `truncated: true`
+    /// instead of failing. The deadline is `timeout_ms` from the start (client timeout if 0).
+    #[serde(default)]
+    pub partial_body_on_timeout: bool,
+    /// Length of `HttpResponse::body_preview` in chars for `execute_request_raw_body`
+    /// (default `DEFAULT_PREVIEW_CHARS`)
+    #[serde(default)]
+    pub preview_chars: Option<usize>,
+    /// Connect to this address (`"10.0.0.7:443"`, or a bare IP to keep the URL's port)
+    /// instead of resolving the host; TLS SNI and `Host` still use the URL's host
+    #[serde(default)]
+    pub force_addr: Option<&'a str>,
+    /// Send this request through a proxy: `http://`, `https://`, `socks5://` or `socks5h://`
+    /// (the proxy resolves the host). Failing to reach the target through it is `PROXY`.
+    #[serde(default)]
+    pub proxy_url: Option<&'a str>,
+    /// Basic credentials for `proxy_url` (`Proxy-Authorization`, or SOCKS5 username/password)
+    #[serde(default)]
+    pub proxy_username: Option<&'a str>,
+    #[serde(default)]
+    pub proxy_password: Option<&'a str>,
+    /// Keep an owned copy of this request under this id so `resend_request(id)` can send it
+    /// again without the JSON envelope; see `replay::RequestReplay` for expiry
+    #[serde(default)]
+    pub replay_id: Option<i64>,
+    /// Tag for `cancel_group`: cancels this request together with the rest of its group
+    #[serde(default)]
+    pub group: Option<&'a str>,
+    /// Handle `execute_request_cancellable` returned for this request, set by that call
+    #[serde(skip)]
+    pub cancel_handle: Option<u64>,
+    /// Opaque caller token, ignored by the client and echoed verbatim as `metadata` on the
+    /// response, or on the error when the request fails
+    #[serde(default)]
+    pub metadata: Option<&'a str>,
+    /// Fresh TCP/TLS connection attempts before giving up (0 or 1 = no retry). Only connect
+    /// failures are retried, with a short linear backoff, never a request that was sent.
+    #[serde(default)]
+    pub connect_attempts: u32,
+    /// Sign the request with AWS Signature Version 4 (sets `Authorization` and `x-amz-*`).
+    /// Streamed bodies (`body_file`, `body_files`, `multipart`) are signed as `UNSIGNED-PAYLOAD`.
+    #[serde(default, borrow)]
+    pub aws_sigv4: Option<AwsSigV4<'a>>,
+    /// Sends `Authorization: Bearer <token>`; an `Authorization` in `headers` wins
+    #[serde(default)]
+    pub bearer_token: Option<&'a str>,
+    /// `[username, password]` sent as `Authorization: Basic` (RFC 7617); an `Authorization`
+    /// in `headers` wins, and this wins over credentials from `add_credential`
+    #[serde(default, borrow)]
+    pub basic_auth: Option<(&'a str, &'a str)>,
+    /// Fill `HttpResponse::status_line` with the HTTP/1.1 status line as received
+    #[serde(default)]
+    pub raw_status_line: bool,
+    /// Return once the response head arrives and drop the body unread (`body` stays empty).
+    /// Dropping an unfinished body resets the h2 stream or closes the HTTP/1.1 connection
+    /// instead of returning it to the pool. Bypasses the response cache and `download_path`.
+    #[serde(default)]
+    pub headers_only: bool,
+    /// Proxy passthrough: the body comes back exactly as sent on the wire (no decompression,
+    /// no `Accept-Encoding` added) and `Content-Encoding`, `Content-Length` and
+    /// `Transfer-Encoding` are reported verbatim. hyper always removes chunked framing, so
+    /// chunk boundaries are not preserved; re-chunk when replaying `Transfer-Encoding: chunked`.
+    /// Body bytes are only returned by `execute_request_raw_body`. Bypasses the response cache.
+    #[serde(default)]
+    pub passthrough: bool,
+    /// Send header names Title-Cased (`x-api-key` goes out as `X-Api-Key`) instead of the
+    /// default lowercase. Only HTTP/1.1 carries case: HTTP/2 always lowercases on the wire,
+    /// so h2 is not forced for these requests, but a server that negotiates it still gets
+    /// lowercase names. hyper's exact-case preservation is not reachable through reqwest,
+    /// so mixed spellings like `X-API-Key` come out Title-Cased too. Uses its own pool.
+    #[serde(default)]
+    pub preserve_header_case: bool,
+    /// Return the body base64-encoded in `body` with `body_encoding: "base64"`, so binary
+    /// content survives the JSON envelope. Costs 4/3 the body size plus the encoding pass.
+    /// Bypasses the response cache.
+    #[serde(default)]
+    pub base64_body: bool,
+    /// Keep the body on the Rust side and return `HttpResponse::body_handle` instead, for
+    /// Dart to read in slices with `read_body_slice`; `body` is left empty. The handle must be
+    /// released with `free_body_handle` (see `body_handles::BodyHandles`).
+    #[serde(default)]
+    pub body_as_handle: bool,
+    /// JSON round trip: a 2xx body is parsed in Rust and returned in `HttpResponse::json`
+    /// (with `body` left empty), failing with `RESPONSE_NOT_JSON` if it is not JSON; an empty
+    /// 2xx body leaves `json` unset. Non-2xx bodies come back raw in `body`. The structured
+    /// value is only carried by the JSON response envelope, not by the binary format.
+    #[serde(default)]
+    pub json_response: bool,
+    /// Attach `Content-MD5` or `Content-Digest` computed over `body` / `body_bytes`.
+    /// Streamed bodies (`body_file`, `body_files`, `multipart`, `body_stream_id`) fail with `DIGEST_UNAVAILABLE`.
+    #[serde(default)]
+    pub content_digest: Option<DigestAlgorithm>,
+    /// Upper bound for the whole operation, including any retry attempts and backoff waits;
+    /// exceeding it fails with `TOTAL_TIMEOUT` (0 = no bound), whose `details.last_error`
+    /// holds the `code` and `message` of the last retried attempt
+    #[serde(default)]
+    pub total_timeout_ms: u64,
+    /// Hedging for tail latency: while no attempt has succeeded, start another identical one
+    /// every `hedge_delay_ms`, up to `max_hedges` extra attempts; the first success wins and
+    /// the others are cancelled (`HttpResponse::hedge` tells which). Idempotent methods only,
+    /// and not with streamed bodies or downloads, which cannot be sent twice at once.
+    #[serde(default)]
+    pub max_hedges: u32,
+    #[serde(default)]
+    pub hedge_delay_ms: u64,
+    /// Mirrors tried in order after `url` fails with a connect, DNS, TLS or transport error
+    /// (timeouts, resets), for idempotent methods or an `Idempotency-Key` only: other requests
+    /// return their first error, as with `max_retries`. The first success or the last error
+    /// is returned, with every URL tried listed in `HttpResponse::url_attempts` or the
+    /// error's `details.url_attempts`. `total_timeout_ms` covers all of them. Not with
+    /// `body_stream_id`, which can only be sent once.
+    #[serde(default, borrow)]
+    pub fallback_urls: Vec<&'a str>,
+    /// Also move on to the next URL on a 5xx status (same method rules as timeouts)
+    #[serde(default)]
+    pub fallback_on_status: bool,
+    /// Send the request again up to this many times after a transient failure, waiting
+    /// `retry_base_delay_ms * 2^n` with jitter (and at least a 429/503 `Retry-After`) between
+    /// tries. Connect, DNS and TLS failures, timeouts, resets and `retry_statuses` are retried
+    /// only for idempotent methods, an `Idempotency-Key`, or `retry_non_idempotent`; any other
+    /// request returns its first error (see `MethodUtils::is_retry_eligible`). The wait
+    /// counts towards `total_timeout_ms`; `HttpResponse::retries` or `details.retries` tell
+    /// how many retries were made. Not with `body_stream_id` or a streamed response.
+    #[serde(default)]
+    pub max_retries: u32,
+    /// First backoff delay (default `DEFAULT_RETRY_BASE_DELAY_MS`)
+    #[serde(default)]
+    pub retry_base_delay_ms: Option<u64>,
+    /// Statuses worth another try (default `DEFAULT_RETRY_STATUSES`); with `error_on_status`
+    /// the `HTTP_STATUS` error is retried the same way
+    #[serde(default)]
+    pub retry_statuses: Option<Vec<u16>>,
+    /// Also retry methods that are not idempotent (e.g. POST) after the request may have
+    /// reached the server
+    #[serde(default)]
+    pub retry_non_idempotent: bool,
+    /// Sent while the app is in the background: capped by `ClientSettings::background_deadline_ms`
+    /// and sent on a client without keep-alive or idle pooling, so nothing lingers after the
+    /// request. Connections to the same host are not reused between background requests.
+    #[serde(default)]
+    pub background: bool,
+    /// Zero-copy body that replaces `body` (set by `execute_request_with_borrowed_body`)
+    #[serde(skip)]
+    pub body_bytes: Option<Bytes>,
+    /// With `execute_download_to_file`: continue an existing partial file with `Range`, guarded
+    /// by `If-Range` so a changed resource restarts the file instead of corrupting it
+    #[serde(default)]
+    pub resume_download: bool,
+    /// Validator for `If-Range`; defaults to the ETag/Last-Modified remembered from the last
+    /// download to the same path (in memory only, so pass it after an app restart)
+    #[serde(default)]
+    pub if_range: Option<&'a str>,
+    /// Write the body to this file (set by `execute_download_to_file`)
+    #[serde(skip)]
+    pub download_path: Option<&'a str>,
+    /// Post the body to the Dart port in chunks under this id (set by `execute_streaming_request`)
+    #[serde(skip)]
+    pub response_stream_id: Option<i64>,
+    /// Keep the body as raw bytes instead of decoding it (set by `execute_request_raw_body`)
+    #[serde(skip)]
+    pub raw_body: bool,
+}
+
+/// Preview length used when `preview_chars` is not set
+pub const DEFAULT_PREVIEW_CHARS: usize = 256;
+
+/// First retry delay used when `retry_base_delay_ms` is not set
+pub const DEFAULT_RETRY_BASE_DELAY_MS: u64 = 200;
+
+/// Retried statuses when `retry_statuses` is not set: timeouts, rate limits and the
+/// gateway / overload errors a proxy or load balancer returns while a backend restarts
+pub const DEFAULT_RETRY_STATUSES: [u16; 5] = [408, 429, 502, 503, 504];
+
+fn is_zero(value: &u32) -> bool {
+    *value == 0
+}
+
+impl HttpRequest<'_> {
+    /// Rejects contradictory field combinations with `INVALID_REQUEST` before any I/O.
+    /// `method` is the parsed `self.method`.
+    pub fn validate(&self, method: &Method) -> Result<(), HttpError> {
+        let invalid = |conflict: String| Err(HttpError::new("INVALID_REQUEST", format!("{} ({})", conflict, self.url)));
+
+        let sources: Vec<&str> = [
+            ("body", self.body.is_some()),
+            ("body_bytes", self.body_bytes.is_some()),
+            ("body_file", self.body_file.is_some()),
+            ("body_files", self.body_files.is_some()),
+            ("multipart", self.multipart.is_some()),
+            ("body_stream_id", self.body_stream_id.is_some()),
+        ]
+        .into_iter()
+        .filter_map(|(name, set)| set.then_some(name))
+        .collect();
+        if sources.len() > 1 {
+            return invalid(format!("Only one body source may be set, got {}", sources.join(", ")));
+        }
+        if matches!(*method, Method::GET | Method::HEAD) && self.multipart.is_some() {
+            return invalid(format!("multipart cannot be sent with {}", method));
+        }
+        if self.body_stream_length.is_some() && self.body_stream_id.is_none() {
+            return invalid("body_stream_length needs body_stream_id".to_owned());
+        }
+        if self.resume_download && self.download_path.is_none() {
+            return invalid("resume_download needs a download path".to_owned());
+        }
+        if self.headers_only && self.download_path.is_some() {
+            return invalid("headers_only cannot be combined with a download path".to_owned());
+        }
+        if self.passthrough && self.base64_body {
+            return invalid("passthrough returns raw bytes and cannot be combined with base64_body".to_owned());
+        }
+        if self.max_hedges > 0 {
+            if !MethodUtils::is_idempotent_method(method) {
+                return invalid(format!("max_hedges needs an idempotent method, got {}", method));
+            }
+            if self.has_streamed_body() || self.download_path.is_some() {
+                return invalid("max_hedges cannot be combined with a streamed body or a download path".to_owned());
+            }
+        }
+        if self.max_retries > 0 && (self.body_stream_id.is_some() || self.response_stream_id.is_some()) {
+            return invalid("max_retries cannot be combined with body_stream_id or a streamed response".to_owned());
+        }
+        if !self.fallback_urls.is_empty() && self.body_stream_id.is_some() {
+            return invalid("fallback_urls cannot be combined with body_stream_id".to_owned());
+        }
+        if self.background && self.passthrough {
+            return invalid("background cannot be combined with passthrough".to_owned());
+        }
+        if self.http3_only {
+            if !cfg!(feature = "http3") {
+                return invalid("http3_only needs a build with the http3 feature".to_owned());
+            }
+            let conflicts: Vec<&str> = [
+                ("negotiate_version", self.negotiate_version),
+                ("preserve_header_case", self.preserve_header_case),
+                ("passthrough", self.passthrough),
+                ("decompress: false", !self.decompress),
+                ("background", self.background),
+                ("force_addr", self.force_addr.is_some()),
+                ("proxy_url", self.proxy_url.is_some()),
+            ]
+            .into_iter()
+            .filter_map(|(name, set)| set.then_some(name))
+            .collect();
+            if !conflicts.is_empty() {
+                return invalid(format!("http3_only cannot be combined with {}", conflicts.join(", ")));
+            }
+        }
+        if self.bearer_token.is_some() && self.basic_auth.is_some() {
+            return invalid("bearer_token cannot be combined with basic_auth".to_owned());
+        }
+        if self.aws_sigv4.is_some() && (self.bearer_token.is_some() || self.basic_auth.is_some()) {
+            return invalid("aws_sigv4 cannot be combined with bearer_token or basic_auth".to_owned());
+        }
+        if self.basic_auth.is_some_and(|(username, _)| username.contains(':')) {
+            return invalid("basic_auth username cannot contain ':' (RFC 7617)".to_owned());
+        }
+        if self.proxy_url.is_none() && (self.proxy_username.is_some() || self.proxy_password.is_some()) {
+            return invalid("proxy_username and proxy_password need proxy_url".to_owned());
+        }
+        if self.proxy_url.is_some() && (self.force_addr.is_some() || self.background) {
+            return invalid("proxy_url cannot be combined with force_addr or background".to_owned());
+        }
+        if !self.decompress && (self.background || self.preserve_header_case) {
+            return invalid("decompress: false cannot be combined with background or preserve_header_case".to_owned());
+        }
+        if self.preserve_header_case && (self.background || self.passthrough) {
+            return invalid("preserve_header_case cannot be combined with background or passthrough".to_owned());
+        }
+        if self.body_as_handle {
+            let conflicts: Vec<&str> = [
+                ("base64_body", self.base64_body),
+                ("json_response", self.json_response),
+                ("headers_only", self.headers_only),
+                ("a download path", self.download_path.is_some()),
+            ]
+            .into_iter()
+            .filter_map(|(name, set)| set.then_some(name))
+            .collect();
+            if !conflicts.is_empty() {
+                return invalid(format!("body_as_handle cannot be combined with {}", conflicts.join(", ")));
+            }
+        }
+        if self.response_stream_id.is_some() {
+            let conflicts: Vec<&str> = [
+                ("base64_body", self.base64_body),
+                ("json_response", self.json_response),
+                ("body_as_handle", self.body_as_handle),
+                ("partial_body_on_timeout", self.partial_body_on_timeout),
+                ("max_hedges", self.max_hedges > 0),
+                ("fallback_urls", !self.fallback_urls.is_empty()),
+                ("a download path", self.download_path.is_some()),
+            ]
+            .into_iter()
+            .filter_map(|(name, set)| set.then_some(name))
+            .collect();
+            if !conflicts.is_empty() {
+                return invalid(format!("A streamed response cannot be combined with {}", conflicts.join(", ")));
+            }
+        }
+        if self.json_response {
+            let conflicts: Vec<&str> = [
+                ("base64_body", self.base64_body),
+                ("passthrough", self.passthrough),
+                ("headers_only", self.headers_only),
+                ("a download path", self.download_path.is_some()),
+            ]
+            .into_iter()
+            .filter_map(|(name, set)| set.then_some(name))
+            .collect();
+            if !conflicts.is_empty() {
+                return invalid(format!("json_response cannot be combined with {}", conflicts.join(", ")));
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether `Content-Encoding` is decoded, i.e. neither `passthrough` nor `decompress: false`
+    pub fn decodes_body(&self) -> bool {
+        self.decompress && !self.passthrough
+    }
+
+    /// Whether the body is streamed rather than held in memory
+    pub fn has_streamed_body(&self) -> bool {
+        self.body_file.is_some() || self.body_files.is_some() || self.multipart.is_some() || self.body_stream_id.is_some()
+    }
+}
+
+/// One `multipart/form-data` part; exactly one of `data` and `file_path` must be set
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MultipartPart<'a> {
+    pub name: &'a str,
+    #[serde(default)]
+    pub data: Option<&'a str>,
+    /// Streamed from disk while sending
+    #[serde(default)]
+    pub file_path: Option<&'a str>,
+    /// Defaults to the file name of `file_path`
+    #[serde(default)]
+    pub filename: Option<&'a str>,
+    #[serde(default)]
+    pub content_type: Option<&'a str>,
+    /// Gzip this part and send it with `Content-Encoding: gzip`. The compressed part is
+    /// built in memory (files included), so keep it to compressible, modest-sized data.
+    #[serde(default)]
+    pub gzip: bool,
 }
 
+/// One entry of an `Accept` list: a media type (or alias such as `json`) plus optional quality
 #[derive(Debug, Serialize, Deserialize)]
+pub struct AcceptEntry<'a> {
+    pub media_type: &'a str,
+    #[serde(default)]
+    pub q: Option<f32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HttpResponse {
     pub status_code: u16,
     pub headers: HashMap<String, String>,
@@ -28,11 +464,368 @@ pub struct HttpResponse {
     pub version: String,
     pub url: String,
     pub elapsed_ms: u128,
+    /// Time the job spent queued for the FFI worker before it was picked up
+    #[serde(default)]
+    pub queue_wait_ms: u128,
+    /// Time spent inside `execute_request` (network + body read)
+    #[serde(default)]
+    pub network_ms: u128,
+    /// Headers dropped while extracting the response (only populated for `strict_headers`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub skipped_headers: Vec<SkippedHeader>,
+    /// Every header as `(name, value)`, duplicates kept (only populated for `ordered_headers`).
+    /// Names appear in the order first received with each name's values together in wire
+    /// order: hyper's header map does not keep how different names were interleaved.
+    /// `headers` keeps the last value of a repeated name. Not carried by the binary format.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub headers_ordered: Vec<(String, String)>,
+    /// Every `Set-Cookie` value in wire order. Cookies cannot be comma-joined like other
+    /// repeated headers, and `headers["set-cookie"]` only holds the last one.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub set_cookies: Vec<String>,
+    /// Metrics from `Server-Timing` headers, in header order; malformed entries are skipped
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub server_timing: Vec<ServerTimingMetric>,
+    /// Served from the cache under `stale_while_revalidate` while a refresh runs
+    #[serde(default)]
+    pub served_stale: bool,
+    /// Where the body came from; `from_cache` is true for every source but `network`
+    #[serde(default)]
+    pub source: ResponseSource,
+    #[serde(default)]
+    pub from_cache: bool,
+    /// Body was cut short by a timeout (only with `partial_body_on_timeout`)
+    #[serde(default)]
+    pub truncated: bool,
+    /// Body bytes received, before lossy UTF-8 conversion
+    #[serde(default)]
+    pub bytes_received: u64,
+    /// First `preview_chars` chars of the body, only for `execute_request_raw_body`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body_preview: Option<String>,
+    /// Set instead of `body` when the body was written to a file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub download: Option<DownloadInfo>,
+    /// HTTP/1.1 status line with the server's own reason phrase (`raw_status_line` only;
+    /// `None` for HTTP/2, which has no reason phrase)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status_line: Option<String>,
+    /// URLs visited when redirects were followed, starting with the original (empty if none)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub redirects: Vec<String>,
+    /// Peer the response came from, and its family (`ipv4` / `ipv6`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remote_addr: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub connected_family: Option<String>,
+    /// ALPN protocol of the connection (`h2` / `http/1.1`), HTTPS only; see `alpn_protocols`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alpn_protocol: Option<String>,
+    /// Set when the request was hedged (`max_hedges`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hedge: Option<HedgeInfo>,
+    /// Every URL tried for a request with `fallback_urls`, this response's last
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub url_attempts: Vec<UrlAttempt>,
+    /// Attempts made before this one (see `max_retries`)
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub retries: u32,
+    /// Handle of the body kept by `body_as_handle` (`body_len` bytes); free with `free_body_handle`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body_handle: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body_len: Option<u64>,
+    /// Parsed 2xx body of a `json_response` request
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub json: Option<OwnedValue>,
+    /// `"base64"` when `body` holds base64 (see `base64_body`); absent for UTF-8 text
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body_encoding: Option<String>,
+    /// The request's `metadata`, untouched
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<String>,
+    /// Undecoded body when the request asked for `raw_body` (`body` is then empty)
+    #[serde(skip)]
+    pub raw_body: Option<Bytes>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HedgeInfo {
+    /// Attempts started, including the first
+    pub launched: u32,
+    /// Index of the attempt whose response was returned (0 = the first)
+    pub winner: u32,
+}
+
+/// One URL tried for a request with `fallback_urls`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UrlAttempt {
+    pub url: String,
+    /// Status of the response, if one arrived
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status_code: Option<u16>,
+    /// `HttpError` code this URL failed with (`None` for a response)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub elapsed_ms: u128,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DownloadInfo {
+    pub path: String,
+    /// Bytes written by this request
+    pub bytes_written: u64,
+    /// Size of the file afterwards
+    pub file_size: u64,
+    /// Appended to an existing partial file (206) rather than restarted
+    pub resumed: bool,
+    /// Stopped by `pause_download`; the partial file was kept for a later resume
+    #[serde(default)]
+    pub paused: bool,
+}
+
+/// One `Server-Timing` entry, e.g. `db;dur=53.2;desc="Primary DB"`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerTimingMetric {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SkippedHeader {
+    pub name: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HttpError {
     pub code: String,
     pub message: String,
     pub details: Option<OwnedValue>, // <- now owns its data, no lifetime required
+    /// The failed request's `metadata`, untouched
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<String>,
+}
+
+impl HttpResponse {
+    pub fn set_source(&mut self, source: ResponseSource) {
+        self.source = source;
+        self.from_cache = source.from_cache();
+    }
+}
+
+impl HttpError {
+    pub fn new(code: &str, message: impl Into<String>) -> Self {
+        Self { code: code.to_owned(), message: message.into(), details: None, metadata: None }
+    }
+
+    pub fn with_details(mut self, details: OwnedValue) -> Self {
+        self.details = Some(details);
+        self
+    }
+
+    /// `HttpError` view of any error surfaced by `execute_request`, coded as in `code_of`,
+    /// with the whole source chain in the message (reqwest's own is just "error sending request")
+    pub fn from_error(error: &anyhow::Error) -> Self {
+        match error.downcast_ref::<HttpError>() {
+            Some(http_error) => http_error.clone(),
+            None => Self::new(Self::code_of(error), format!("{:#}", error)),
+        }
+    }
+
+    /// An `HttpError` carried inside an `io::Error` somewhere in `error`'s source chain.
+    /// The resolver and body streams can only fail with `io::Error`, so they wrap theirs.
+    pub fn from_source_chain(error: &(dyn std::error::Error + 'static)) -> Option<Self> {
+        Self::source_chain(error).find_map(|e| e.downcast_ref::<HttpError>()).cloned()
+    }
+
+    /// `error` and its sources, stepping into `io::Error` payloads, which `source()` skips
+    fn source_chain<'e>(error: &'e (dyn std::error::Error + 'static)) -> impl Iterator<Item = &'e (dyn std::error::Error + 'static)> {
+        std::iter::successors(Some(error), |current| match current.downcast_ref::<std::io::Error>() {
+            Some(io) if io.get_ref().is_some() => io.get_ref().map(|inner| inner as &(dyn std::error::Error + 'static)),
+            _ => current.source(),
+        })
+    }
+
+    /// Stable code for any error surfaced by `execute_request`: the `HttpError` code if there
+    /// is one, else `TIMEOUT` / `TLS` / `CONNECT` / `REQUEST` for transport errors, else `UNKNOWN`.
+    /// Resolver failures already arrive as `DNS` / `DNS_TIMEOUT` (see `DnsResolver`).
+    pub fn code_of(error: &anyhow::Error) -> &str {
+        if let Some(http_error) = error.downcast_ref::<HttpError>() {
+            return &http_error.code;
+        }
+        match error.downcast_ref::<reqwest::Error>() {
+            Some(e) if e.is_timeout() => "TIMEOUT",
+            Some(e) if e.is_connect() && Self::is_tls(e) => "TLS",
+            Some(e) if e.is_connect() => "CONNECT",
+            Some(_) => "REQUEST",
+            None => "UNKNOWN",
+        }
+    }
+
+    /// Whether a handshake or certificate error from rustls is in `error`'s source chain
+    fn is_tls(error: &(dyn std::error::Error + 'static)) -> bool {
+        Self::source_chain(error).any(|e| e.is::<rustls::Error>())
+    }
+}
+
+impl fmt::Display for HttpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.code, self.message)
+    }
+}
+
+impl std::error::Error for HttpError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request() -> HttpRequest<'static> {
+        HttpRequest { url: "https://example.com/", method: "POST", decompress: true, ..HttpRequest::default() }
+    }
+
+    fn part() -> MultipartPart<'static> {
+        MultipartPart { name: "field", data: Some("value"), file_path: None, filename: None, content_type: None, gzip: false }
+    }
+
+    fn sigv4() -> AwsSigV4<'static> {
+        AwsSigV4 { access_key_id: "AKID", secret_access_key: "secret", session_token: None, region: "us-east-1", service: "s3" }
+    }
+
+    /// Asserts `request` is rejected for `method` with a message containing `conflict`
+    fn rejects(request: HttpRequest, method: Method, conflict: &str) {
+        let error = request.validate(&method).expect_err(conflict);
+        assert_eq!(error.code, "INVALID_REQUEST");
+        assert!(error.message.contains(conflict), "{:?} does not mention {:?}", error.message, conflict);
+    }
+
+    #[test]
+    fn accepts_plain_request() {
+        assert!(HttpRequest { body: Some("{}"), max_retries: 2, ..request() }.validate(&Method::POST).is_ok());
+    }
+
+    #[test]
+    fn rejects_two_body_sources() {
+        rejects(HttpRequest { body: Some("a"), body_file: Some("/tmp/b"), ..request() }, Method::POST, "body, body_file");
+    }
+
+    #[test]
+    fn rejects_multipart_on_get() {
+        rejects(HttpRequest { multipart: Some(vec![part()]), ..request() }, Method::GET, "multipart cannot be sent with GET");
+    }
+
+    #[test]
+    fn rejects_stream_length_without_stream() {
+        rejects(HttpRequest { body_stream_length: Some(4), ..request() }, Method::POST, "body_stream_length needs body_stream_id");
+    }
+
+    #[test]
+    fn rejects_resume_without_download_path() {
+        rejects(HttpRequest { resume_download: true, ..request() }, Method::GET, "resume_download needs a download path");
+    }
+
+    #[test]
+    fn rejects_headers_only_download() {
+        rejects(HttpRequest { headers_only: true, download_path: Some("/tmp/out"), ..request() }, Method::GET, "headers_only");
+    }
+
+    #[test]
+    fn rejects_passthrough_base64() {
+        rejects(HttpRequest { passthrough: true, base64_body: true, ..request() }, Method::GET, "base64_body");
+    }
+
+    #[test]
+    fn rejects_hedges_on_post() {
+        rejects(HttpRequest { max_hedges: 1, ..request() }, Method::POST, "max_hedges needs an idempotent method");
+    }
+
+    #[test]
+    fn rejects_hedges_with_download_path() {
+        rejects(HttpRequest { max_hedges: 1, download_path: Some("/tmp/out"), ..request() }, Method::GET, "max_hedges cannot be combined");
+    }
+
+    #[test]
+    fn rejects_retries_with_body_stream() {
+        rejects(HttpRequest { max_retries: 1, body_stream_id: Some(1), ..request() }, Method::PUT, "max_retries cannot be combined");
+    }
+
+    #[test]
+    fn rejects_fallbacks_with_body_stream() {
+        rejects(HttpRequest { fallback_urls: vec!["https://b.example.com/"], body_stream_id: Some(1), ..request() }, Method::PUT, "fallback_urls");
+    }
+
+    #[test]
+    fn rejects_background_passthrough() {
+        rejects(HttpRequest { background: true, passthrough: true, ..request() }, Method::GET, "background cannot be combined with passthrough");
+    }
+
+    #[cfg(not(feature = "http3"))]
+    #[test]
+    fn rejects_http3_only_without_feature() {
+        rejects(HttpRequest { http3_only: true, ..request() }, Method::GET, "http3 feature");
+    }
+
+    #[cfg(feature = "http3")]
+    #[test]
+    fn rejects_http3_only_with_proxy() {
+        rejects(HttpRequest { http3_only: true, proxy_url: Some("http://proxy:8080"), ..request() }, Method::GET, "http3_only cannot be combined with proxy_url");
+    }
+
+    #[test]
+    fn rejects_bearer_with_basic_auth() {
+        rejects(HttpRequest { bearer_token: Some("t"), basic_auth: Some(("u", "p")), ..request() }, Method::GET, "bearer_token cannot be combined");
+    }
+
+    #[test]
+    fn rejects_sigv4_with_bearer() {
+        rejects(HttpRequest { aws_sigv4: Some(sigv4()), bearer_token: Some("t"), ..request() }, Method::GET, "aws_sigv4");
+    }
+
+    #[test]
+    fn rejects_colon_in_basic_auth_username() {
+        rejects(HttpRequest { basic_auth: Some(("a:b", "p")), ..request() }, Method::GET, "cannot contain ':'");
+    }
+
+    #[test]
+    fn rejects_proxy_credentials_without_proxy() {
+        rejects(HttpRequest { proxy_username: Some("u"), ..request() }, Method::GET, "need proxy_url");
+    }
+
+    #[test]
+    fn rejects_proxy_with_force_addr() {
+        rejects(HttpRequest { proxy_url: Some("http://proxy:8080"), force_addr: Some("127.0.0.1:443"), ..request() }, Method::GET, "proxy_url cannot be combined");
+    }
+
+    #[test]
+    fn rejects_background_without_decompress() {
+        rejects(HttpRequest { decompress: false, background: true, ..request() }, Method::GET, "decompress: false");
+    }
+
+    #[test]
+    fn rejects_preserve_header_case_passthrough() {
+        rejects(HttpRequest { preserve_header_case: true, passthrough: true, ..request() }, Method::GET, "preserve_header_case cannot be combined");
+    }
+
+    #[test]
+    fn rejects_body_as_handle_json() {
+        rejects(HttpRequest { body_as_handle: true, json_response: true, ..request() }, Method::GET, "body_as_handle cannot be combined with json_response");
+    }
+
+    #[test]
+    fn rejects_streamed_response_with_fallbacks() {
+        rejects(
+            HttpRequest { response_stream_id: Some(1), fallback_urls: vec!["https://b.example.com/"], ..request() },
+            Method::GET,
+            "A streamed response cannot be combined with fallback_urls",
+        );
+    }
+
+    #[test]
+    fn rejects_json_response_headers_only() {
+        rejects(HttpRequest { json_response: true, headers_only: true, ..request() }, Method::GET, "json_response cannot be combined with headers_only");
+    }
 }
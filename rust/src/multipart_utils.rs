@@ -0,0 +1,107 @@
+use crate::file_utils::FileUtils;
+use crate::models::MultipartPart;
+use crate::progress::{RequestSent, UploadProgress};
+
+use anyhow::Result;
+use bytes::Bytes;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use futures_util::stream::{self, BoxStream, StreamExt};
+use reqwest::header::{HeaderMap, HeaderValue, CONTENT_ENCODING};
+use reqwest::multipart::{Form, Part};
+use reqwest::Body;
+use std::io::{self, Write};
+use std::path::Path;
+
+pub struct MultipartUtils;
+
+impl MultipartUtils {
+    /// Builds a streaming multipart form. File parts are read from disk lazily while
+    /// reqwest sends them, so large files never sit in memory as a whole.
+    /// With `upload_stream_id` set, per-part and aggregate progress is posted to Dart
+    /// (byte counts cover part contents only, not the multipart framing).
+    /// `sent_event_id` reports the end of the last part as `RequestSent`.
+    /// Parts flagged `gzip` are compressed up front; progress then counts compressed bytes.
+    pub async fn build_form(parts: &[MultipartPart<'_>], upload_stream_id: Option<i64>, sent_event_id: Option<i64>) -> Result<Form> {
+        let mut bodies = Vec::with_capacity(parts.len());
+        for part in parts {
+            bodies.push(Self::part_body(part).await?);
+        }
+
+        let progress = upload_stream_id.map(|id| UploadProgress::new(id, bodies.iter().map(|(_, size)| size).sum()));
+        let mut form = Form::new();
+
+        let last = parts.len().saturating_sub(1);
+        for (index, (part, (mut chunks, size))) in parts.iter().zip(bodies).enumerate() {
+            if let Some(progress) = &progress {
+                chunks = progress.track(chunks, Some(part.name.to_owned()), size);
+            }
+            if let Some(event_id) = sent_event_id.filter(|_| index == last) {
+                chunks = RequestSent::notify(chunks, event_id, size);
+            }
+
+            let mut form_part = Part::stream_with_length(Body::wrap_stream(chunks), size);
+
+            let file_name = part.filename.or_else(|| {
+                part.file_path
+                    .and_then(|path| Path::new(path).file_name())
+                    .and_then(|name| name.to_str())
+            });
+            if let Some(file_name) = file_name {
+                form_part = form_part.file_name(file_name.to_owned());
+            }
+            if let Some(content_type) = part.content_type {
+                form_part = form_part.mime_str(content_type)?;
+            }
+            if part.gzip {
+                let mut headers = HeaderMap::new();
+                headers.insert(CONTENT_ENCODING, HeaderValue::from_static("gzip"));
+                form_part = form_part.headers(headers);
+            }
+
+            form = form.part(part.name.to_owned(), form_part);
+        }
+
+        Ok(form)
+    }
+
+    /// Content stream and exact length of one part, gzipped if requested
+    async fn part_body(part: &MultipartPart<'_>) -> Result<(BoxStream<'static, io::Result<Bytes>>, u64)> {
+        let size = Self::part_size(part).await?;
+        if !part.gzip {
+            return Ok((Self::part_stream(part).await?, size));
+        }
+
+        let raw = match part.file_path {
+            Some(path) => tokio::fs::read(path).await.map_err(|e| FileUtils::io_error(path, e))?,
+            None => part.data.unwrap_or_default().as_bytes().to_vec(),
+        };
+        let compressed = Bytes::from(Self::gzip(&raw)?);
+        let size = compressed.len() as u64;
+        Ok((stream::once(async move { Ok(compressed) }).boxed(), size))
+    }
+
+    fn gzip(data: &[u8]) -> io::Result<Vec<u8>> {
+        let mut encoder = GzEncoder::new(Vec::with_capacity(data.len() / 2), Compression::default());
+        encoder.write_all(data)?;
+        encoder.finish()
+    }
+
+    async fn part_size(part: &MultipartPart<'_>) -> Result<u64> {
+        match (part.data, part.file_path) {
+            (Some(data), None) => Ok(data.len() as u64),
+            (None, Some(path)) => Ok(FileUtils::file_size(path).await?),
+            _ => Err(anyhow::anyhow!("Multipart part {} needs exactly one of data or file_path", part.name)),
+        }
+    }
+
+    async fn part_stream(part: &MultipartPart<'_>) -> Result<BoxStream<'static, io::Result<Bytes>>> {
+        if let Some(path) = part.file_path {
+            let (file_stream, _) = FileUtils::open_stream(path).await?;
+            return Ok(file_stream.boxed());
+        }
+
+        let data = Bytes::copy_from_slice(part.data.unwrap_or_default().as_bytes());
+        Ok(stream::once(async move { Ok(data) }).boxed())
+    }
+}
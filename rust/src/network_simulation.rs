@@ -0,0 +1,81 @@
+use bytes::Bytes;
+use futures_util::stream::{self, StreamExt};
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use reqwest::{Body, Response};
+use serde::Serialize;
+use std::time::Duration;
+
+/// Smallest paced slice, so low bandwidths still make steady progress
+const MIN_SLICE_BYTES: usize = 512;
+
+static SIMULATION: Lazy<RwLock<Option<NetworkSimulation>>> = Lazy::new(|| RwLock::new(None));
+
+/// Artificial slow network for QA, off by default (see `set_network_simulation`). Applies
+/// to every request sent through `HttpClient`, including cache revalidations.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct NetworkSimulation {
+    /// Delay before each request is sent
+    pub latency_ms: u64,
+    /// Cap on response body throughput (0 = unthrottled)
+    pub bandwidth_kbps: u64,
+}
+
+impl NetworkSimulation {
+    /// Turns the simulation on, or off when both values are 0
+    pub fn set(latency_ms: u64, bandwidth_kbps: u64) {
+        let simulation = (latency_ms > 0 || bandwidth_kbps > 0).then_some(Self { latency_ms, bandwidth_kbps });
+        *SIMULATION.write() = simulation;
+    }
+
+    pub fn current() -> Option<Self> {
+        *SIMULATION.read()
+    }
+
+    pub async fn delay(&self) {
+        if self.latency_ms > 0 {
+            tokio::time::sleep(Duration::from_millis(self.latency_ms)).await;
+        }
+    }
+
+    /// Re-wraps the body so it is read no faster than `bandwidth_kbps`. Status, version and
+    /// headers are kept; take anything else (URL, remote address) from `response` first.
+    pub fn throttle(&self, response: Response) -> Response {
+        if self.bandwidth_kbps == 0 {
+            return response;
+        }
+        let bytes_per_sec = self.bandwidth_kbps * 1000 / 8;
+        // ~10 slices per second
+        let slice_len = (bytes_per_sec as usize / 10).max(MIN_SLICE_BYTES);
+
+        let mut builder = hyper::Response::builder().status(response.status()).version(response.version());
+        if let Some(headers) = builder.headers_mut() {
+            *headers = response.headers().clone();
+        }
+
+        let start = tokio::time::Instant::now();
+        let mut paced = 0u64;
+        let body = response
+            .bytes_stream()
+            .flat_map(move |chunk| {
+                let slices: Vec<reqwest::Result<Bytes>> = match chunk {
+                    Ok(chunk) => (0..chunk.len()).step_by(slice_len).map(|at| Ok(chunk.slice(at..(at + slice_len).min(chunk.len())))).collect(),
+                    Err(e) => vec![Err(e)],
+                };
+                stream::iter(slices)
+            })
+            .then(move |slice| {
+                if let Ok(slice) = &slice {
+                    paced += slice.len() as u64;
+                }
+                let due = start + Duration::from_secs_f64(paced as f64 / bytes_per_sec as f64);
+                async move {
+                    tokio::time::sleep_until(due).await;
+                    slice
+                }
+            });
+
+        let throttled = builder.body(Body::wrap_stream(body)).expect("status and headers come from a valid response");
+        Response::from(throttled)
+    }
+}
@@ -0,0 +1,207 @@
+use crate::client_config::ClientConfig;
+use crate::dart_dl_ffi::{self, PortEvent};
+use crate::json;
+
+use bytes::Bytes;
+use futures_util::stream::{self, BoxStream, Stream, StreamExt};
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::Poll;
+use std::time::{Duration, Instant};
+
+/// Slice size for in-memory bodies, so progress advances while a large body is written
+const BUFFER_CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(Serialize)]
+struct UploadProgressEvent<'a> {
+    /// Multipart part name, `None` for plain bodies
+    part: Option<&'a str>,
+    part_sent: u64,
+    part_total: u64,
+    sent: u64,
+    total: u64,
+}
+
+/// Aggregated upload progress for one request, posted to the Dart port as
+/// `[upload_stream_id, PortEvent::UploadProgress, json]`.
+/// Events are throttled to `ClientSettings::progress_interval_ms`, except that the
+/// completion of every part (and of the whole upload) is always reported.
+pub struct UploadProgress {
+    stream_id: i64,
+    total: u64,
+    sent: AtomicU64,
+    interval: Duration,
+    last_emit: Mutex<Option<Instant>>,
+}
+
+impl UploadProgress {
+    pub fn new(stream_id: i64, total: u64) -> Arc<Self> {
+        let interval = Duration::from_millis(ClientConfig::settings().progress_interval_ms);
+        Arc::new(Self {
+            stream_id,
+            total,
+            sent: AtomicU64::new(0),
+            interval,
+            last_emit: Mutex::new(None),
+        })
+    }
+
+    /// Wraps a body stream so every chunk pulled by reqwest is counted towards `part`
+    pub fn track<S>(
+        self: &Arc<Self>,
+        stream: S,
+        part: Option<String>,
+        part_total: u64,
+    ) -> BoxStream<'static, io::Result<Bytes>>
+    where
+        S: Stream<Item = io::Result<Bytes>> + Send + 'static,
+    {
+        let progress = Arc::clone(self);
+        let mut part_sent = 0u64;
+
+        stream
+            .inspect(move |chunk| {
+                if let Ok(chunk) = chunk {
+                    part_sent += chunk.len() as u64;
+                    progress.advance(part.as_deref(), part_sent, part_total, chunk.len() as u64);
+                }
+            })
+            .boxed()
+    }
+
+    /// `track` for a body already in memory, handed to reqwest in zero-copy slices
+    pub fn track_bytes(self: &Arc<Self>, body: Bytes) -> BoxStream<'static, io::Result<Bytes>> {
+        let size = body.len() as u64;
+        self.track(Self::slices(body), None, size)
+    }
+
+    /// `body` as zero-copy slices of `BUFFER_CHUNK_SIZE`, so hyper pulls the next one only
+    /// once the previous is written
+    pub fn slices(body: Bytes) -> BoxStream<'static, io::Result<Bytes>> {
+        let slices = (0..body.len())
+            .step_by(BUFFER_CHUNK_SIZE)
+            .map(move |start| Ok(body.slice(start..body.len().min(start + BUFFER_CHUNK_SIZE))))
+            .collect::<Vec<_>>();
+        stream::iter(slices).boxed()
+    }
+
+    fn advance(&self, part: Option<&str>, part_sent: u64, part_total: u64, n: u64) {
+        let sent = self.sent.fetch_add(n, Ordering::Relaxed) + n;
+        let finished_part = part_sent >= part_total;
+
+        {
+            let mut last_emit = self.last_emit.lock();
+            let now = Instant::now();
+            let due = last_emit.is_none_or(|last| now.duration_since(last) >= self.interval);
+            if !due && !finished_part {
+                return;
+            }
+            *last_emit = Some(now);
+        }
+
+        let event = UploadProgressEvent { part, part_sent, part_total, sent, total: self.total };
+        if let Ok(payload) = json::to_vec(&event) {
+            dart_dl_ffi::post_message(self.stream_id, PortEvent::UploadProgress, &payload);
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct RequestSentEvent {
+    bytes_sent: u64,
+}
+
+/// Posts `[sent_event_id, PortEvent::RequestSent, {"bytes_sent": n}]` once reqwest has
+/// pulled the last body chunk, i.e. the connection (and TLS handshake) is up and the
+/// request is written; only the response is still outstanding.
+pub struct RequestSent {
+    event_id: i64,
+    total: u64,
+    sent: AtomicU64,
+    posted: AtomicBool,
+}
+
+impl RequestSent {
+    /// Wraps the stream carrying the final `total` bytes of the body. The event fires when
+    /// those bytes have been pulled, since hyper stops polling a sized body at its length.
+    pub fn notify<S>(stream: S, event_id: i64, total: u64) -> BoxStream<'static, io::Result<Bytes>>
+    where
+        S: Stream<Item = io::Result<Bytes>> + Send + 'static,
+    {
+        let tracker = Arc::new(Self { event_id, total, sent: AtomicU64::new(0), posted: AtomicBool::new(false) });
+        let on_end = Arc::clone(&tracker);
+
+        stream
+            .inspect(move |chunk| {
+                if let Ok(chunk) = chunk {
+                    let sent = tracker.sent.fetch_add(chunk.len() as u64, Ordering::Relaxed) + chunk.len() as u64;
+                    if sent >= tracker.total {
+                        tracker.post();
+                    }
+                }
+            })
+            .chain(stream::poll_fn(move |_| {
+                on_end.post();
+                Poll::Ready(None)
+            }))
+            .boxed()
+    }
+
+    fn post(&self) {
+        if self.posted.swap(true, Ordering::Relaxed) {
+            return;
+        }
+        let event = RequestSentEvent { bytes_sent: self.sent.load(Ordering::Relaxed) };
+        if let Ok(payload) = json::to_vec(&event) {
+            dart_dl_ffi::post_message(self.event_id, PortEvent::RequestSent, &payload);
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct BatchProgressEvent {
+    completed: usize,
+    total: usize,
+    succeeded: usize,
+    failed: usize,
+}
+
+/// Posts `[progress_id, PortEvent::BatchProgress, json]` after every finished request of a
+/// batch. Not throttled: batches are bounded, and the final event must never be dropped.
+pub struct BatchProgress {
+    progress_id: i64,
+    total: usize,
+    succeeded: usize,
+    failed: usize,
+}
+
+impl BatchProgress {
+    pub fn new(progress_id: i64, total: usize) -> Self {
+        Self { progress_id, total, succeeded: 0, failed: 0 }
+    }
+
+    /// Counts one finished request and posts the new totals
+    pub fn record(&mut self, succeeded: bool) {
+        if succeeded {
+            self.succeeded += 1;
+        } else {
+            self.failed += 1;
+        }
+        self.post();
+    }
+
+    pub fn post(&self) {
+        let event = BatchProgressEvent {
+            completed: self.succeeded + self.failed,
+            total: self.total,
+            succeeded: self.succeeded,
+            failed: self.failed,
+        };
+        if let Ok(payload) = json::to_vec(&event) {
+            dart_dl_ffi::post_message(self.progress_id, PortEvent::BatchProgress, &payload);
+        }
+    }
+}
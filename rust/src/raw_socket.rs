@@ -0,0 +1,143 @@
+//! Raw TCP/TLS sockets for protocol testing (cargo feature `raw-socket`).
+//!
+//! Bytes are written exactly as given: no request line, header or body normalization.
+//! Everything received is posted to the registered Dart port as
+//! `[socket_id, PortEvent::RawData, bytes]`, followed by one
+//! `[socket_id, PortEvent::RawClosed, {"error": ...}]` when the connection ends.
+
+use crate::dart_dl_ffi::{self, PortEvent};
+use crate::json;
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio_rustls::rustls::pki_types::ServerName;
+use tokio_rustls::rustls::{self, ClientConfig, RootCertStore};
+use tokio_rustls::TlsConnector;
+
+trait RawIo: AsyncRead + AsyncWrite + Send + Unpin {}
+impl<T: AsyncRead + AsyncWrite + Send + Unpin> RawIo for T {}
+
+/// Write halves of open sockets, keyed by the caller-chosen socket id
+static SOCKETS: Lazy<Mutex<HashMap<i64, UnboundedSender<Vec<u8>>>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+static TLS_CONFIG: Lazy<Arc<ClientConfig>> = Lazy::new(|| {
+    let mut roots = RootCertStore::empty();
+    roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+    let config = ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .expect("ring supports the default TLS versions")
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    Arc::new(config)
+});
+
+#[derive(Serialize)]
+struct RawClosedEvent {
+    error: Option<String>,
+}
+
+/// Opens a connection to `host:port` (TLS with SNI = `host` when `use_tls`).
+/// Returns false if the host is not UTF-8 or `socket_id` is already in use;
+/// connection failures are reported asynchronously through `RawClosed`.
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn raw_socket_open(host_ptr: *const u8, host_len: usize, port: u16, use_tls: bool, socket_id: i64) -> bool {
+    if host_ptr.is_null() || host_len == 0 { return false; }
+    let host_bytes = unsafe { std::slice::from_raw_parts(host_ptr, host_len) };
+    let Ok(host) = std::str::from_utf8(host_bytes) else { return false };
+
+    let (tx, rx) = unbounded_channel();
+    {
+        let mut sockets = SOCKETS.lock();
+        if sockets.contains_key(&socket_id) { return false; }
+        sockets.insert(socket_id, tx);
+    }
+
+    crate::RUNTIME.spawn(run_socket(host.to_owned(), port, use_tls, socket_id, rx));
+    true
+}
+
+/// Queues bytes to be written verbatim. Returns false if the socket is not open.
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn raw_socket_write(socket_id: i64, data_ptr: *const u8, data_len: usize) -> bool {
+    if data_ptr.is_null() { return false; }
+    let data = unsafe { std::slice::from_raw_parts(data_ptr, data_len) }.to_vec();
+
+    match SOCKETS.lock().get(&socket_id) {
+        Some(tx) => tx.send(data).is_ok(),
+        None => false,
+    }
+}
+
+/// Half-closes the socket: pending writes are flushed and the write side is shut down.
+/// Reading continues until the peer closes, so the response is still delivered.
+#[no_mangle]
+pub extern "C" fn raw_socket_close(socket_id: i64) -> bool {
+    SOCKETS.lock().remove(&socket_id).is_some()
+}
+
+async fn connect(host: &str, port: u16, use_tls: bool) -> std::io::Result<Box<dyn RawIo>> {
+    let tcp = TcpStream::connect((host, port)).await?;
+    tcp.set_nodelay(true)?;
+    if !use_tls {
+        return Ok(Box::new(tcp));
+    }
+
+    let server_name = ServerName::try_from(host.to_owned())
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    let tls = TlsConnector::from(TLS_CONFIG.clone()).connect(server_name, tcp).await?;
+    Ok(Box::new(tls))
+}
+
+async fn run_socket(host: String, port: u16, use_tls: bool, socket_id: i64, mut rx: UnboundedReceiver<Vec<u8>>) {
+    let stream = match connect(&host, port, use_tls).await {
+        Ok(stream) => stream,
+        Err(e) => {
+            SOCKETS.lock().remove(&socket_id);
+            post_closed(socket_id, Some(e.to_string()));
+            return;
+        }
+    };
+    let (mut reader, mut writer) = tokio::io::split(stream);
+
+    let write_loop = async move {
+        while let Some(data) = rx.recv().await {
+            writer.write_all(&data).await?;
+            writer.flush().await?;
+        }
+        writer.shutdown().await
+    };
+
+    let read_loop = async move {
+        let mut buf = vec![0u8; 16 * 1024];
+        let result = loop {
+            match reader.read(&mut buf).await {
+                Ok(0) => break Ok(()),
+                Ok(n) => {
+                    dart_dl_ffi::post_message(socket_id, PortEvent::RawData, &buf[..n]);
+                }
+                Err(e) => break Err(e),
+            }
+        };
+        // Peer is gone: dropping the sender also ends the write loop.
+        SOCKETS.lock().remove(&socket_id);
+        result
+    };
+
+    let (write_result, read_result) = tokio::join!(write_loop, read_loop);
+    let error = read_result.err().or(write_result.err()).map(|e| e.to_string());
+    post_closed(socket_id, error);
+}
+
+fn post_closed(socket_id: i64, error: Option<String>) {
+    if let Ok(payload) = json::to_vec(&RawClosedEvent { error }) {
+        dart_dl_ffi::post_message(socket_id, PortEvent::RawClosed, &payload);
+    }
+}
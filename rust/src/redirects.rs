@@ -0,0 +1,86 @@
+use crate::connect_timeout::ConnectTimeout;
+
+use parking_lot::Mutex;
+use reqwest::redirect::{Attempt, Policy};
+use std::future::Future;
+use std::sync::Arc;
+
+/// Redirects followed by one request, filled in by the client's redirect policy
+#[derive(Debug, Default)]
+pub struct RedirectLog {
+    /// Every URL visited, starting with the original one
+    pub urls: Vec<String>,
+    /// Status of the last redirect response
+    pub last_status: Option<u16>,
+}
+
+/// How far one request may be redirected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RedirectLimit {
+    /// Return the 3xx response itself (`follow_redirects: false`)
+    Stop,
+    /// The limit the client was built with
+    Client,
+    Max(usize),
+}
+
+impl RedirectLimit {
+    /// From `HttpRequest::follow_redirects` / `max_redirects` (0 keeps the client's limit)
+    pub fn for_request(follow_redirects: bool, max_redirects: usize) -> Self {
+        match (follow_redirects, max_redirects) {
+            (false, _) => RedirectLimit::Stop,
+            (true, 0) => RedirectLimit::Client,
+            (true, max) => RedirectLimit::Max(max),
+        }
+    }
+}
+
+struct Tracked {
+    log: Mutex<RedirectLog>,
+    limit: RedirectLimit,
+}
+
+tokio::task_local! {
+    // reqwest evaluates the redirect policy inside the `send()` future, so the policy
+    // sees the task-local of whichever request is being polled
+    static TRACKED: Arc<Tracked>;
+}
+
+pub struct Redirects;
+
+impl Redirects {
+    /// Follows up to `max` redirects, or the tracked request's own `RedirectLimit`, and
+    /// records each hop for `Redirects::track`
+    pub fn policy(max: usize) -> Policy {
+        Policy::custom(move |attempt| {
+            let limit = TRACKED.try_with(|tracked| tracked.limit).unwrap_or(RedirectLimit::Client);
+            let max = match limit {
+                RedirectLimit::Stop => return attempt.stop(),
+                RedirectLimit::Client => max,
+                RedirectLimit::Max(max) => max,
+            };
+            let _ = TRACKED.try_with(|tracked| Self::record(&mut tracked.log.lock(), &attempt));
+            if attempt.previous().len() > max {
+                attempt.error("too many redirects")
+            } else {
+                ConnectTimeout::redirected(attempt.url());
+                attempt.follow()
+            }
+        })
+    }
+
+    /// Runs `future` (a `send()`) under `limit` and returns the redirects it went through
+    pub async fn track<F: Future>(future: F, limit: RedirectLimit) -> (F::Output, RedirectLog) {
+        let tracked = Arc::new(Tracked { log: Mutex::new(RedirectLog::default()), limit });
+        let output = TRACKED.scope(Arc::clone(&tracked), future).await;
+        let log = std::mem::take(&mut *tracked.log.lock());
+        (output, log)
+    }
+
+    fn record(log: &mut RedirectLog, attempt: &Attempt<'_>) {
+        log.urls.clear();
+        log.urls.extend(attempt.previous().iter().map(|url| url.to_string()));
+        log.urls.push(attempt.url().to_string());
+        log.last_status = Some(attempt.status().as_u16());
+    }
+}
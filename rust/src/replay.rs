@@ -0,0 +1,70 @@
+use crate::json;
+use crate::models::HttpRequest;
+
+use bytes::Bytes;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::time::Instant;
+
+/// Max retained requests; the least recently used one is dropped first
+const MAX_RETAINED: usize = 64;
+
+/// An owned copy of a request: its JSON envelope plus the fields set by the FFI entry point
+pub struct RetainedRequest {
+    pub json: Vec<u8>,
+    pub body_bytes: Option<Bytes>,
+    pub download_path: Option<String>,
+}
+
+struct Entry {
+    request: RetainedRequest,
+    used_at: Instant,
+}
+
+static RETAINED: Lazy<Mutex<HashMap<i64, Entry>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Requests kept under their `replay_id` for `resend_request`. Ids expire once
+/// `MAX_RETAINED` newer ones have been used; retained requests hold their body and any
+/// credentials (e.g. `aws_sigv4` keys) in memory until then (or `forget`). A Dart-pushed
+/// `body_stream_id` body is consumed by the first send, so resending it fails.
+pub struct RequestReplay;
+
+impl RequestReplay {
+    /// Keeps `request` under `id`, replacing an earlier one with the same id
+    pub fn retain(id: i64, request: &HttpRequest<'_>) {
+        let Ok(json) = json::to_vec(request) else { return };
+        let request = RetainedRequest {
+            json,
+            // Copied: a borrowed body must be released before its FFI call returns
+            body_bytes: request.body_bytes.as_deref().map(Bytes::copy_from_slice),
+            download_path: request.download_path.map(str::to_owned),
+        };
+
+        let mut retained = RETAINED.lock();
+        if retained.len() >= MAX_RETAINED && !retained.contains_key(&id) {
+            let oldest = retained.iter().min_by_key(|(_, entry)| entry.used_at).map(|(id, _)| *id);
+            if let Some(oldest) = oldest {
+                retained.remove(&oldest);
+            }
+        }
+        retained.insert(id, Entry { request, used_at: Instant::now() });
+    }
+
+    /// A copy of the request retained under `id`, if it has not expired
+    pub fn get(id: i64) -> Option<RetainedRequest> {
+        let mut retained = RETAINED.lock();
+        let entry = retained.get_mut(&id)?;
+        entry.used_at = Instant::now();
+        Some(RetainedRequest {
+            json: entry.request.json.clone(),
+            body_bytes: entry.request.body_bytes.clone(),
+            download_path: entry.request.download_path.clone(),
+        })
+    }
+
+    /// Drops the request retained under `id`; returns false if there was none
+    pub fn forget(id: i64) -> bool {
+        RETAINED.lock().remove(&id).is_some()
+    }
+}
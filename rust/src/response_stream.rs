@@ -0,0 +1,36 @@
+//! Response bodies posted to the Dart port chunk by chunk (`execute_streaming_request`).
+//!
+//! Every chunk is posted as `[stream_id, PortEvent::BodyChunk, bytes]` in arrival order,
+//! then the request ends with `[stream_id, PortEvent::Response, json]` (an `HttpResponse`
+//! with an empty `body` and the streamed total in `bytes_received`) or
+//! `[stream_id, PortEvent::Error, json]`, also when the body fails part way through.
+
+use crate::dart_dl_ffi::{self, PortEvent};
+use crate::json::json;
+use crate::models::HttpError;
+
+use anyhow::Result;
+use futures_util::StreamExt;
+use reqwest::Response;
+
+pub struct ResponseStream;
+
+impl ResponseStream {
+    /// Posts the body of `response` in the chunks hyper hands out and returns the byte count.
+    /// Each chunk is copied by Dart before the next one is read, so at most one is held here;
+    /// a post Dart rejects (port closed) aborts the body with `STREAM_CLOSED`.
+    pub async fn forward(response: Response, stream_id: i64) -> Result<u64> {
+        let mut stream = response.bytes_stream();
+        let mut bytes_received = 0u64;
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk?;
+            if !dart_dl_ffi::post_message(stream_id, PortEvent::BodyChunk, &chunk) {
+                return Err(HttpError::new("STREAM_CLOSED", format!("Dart port rejected chunk of stream {}", stream_id))
+                    .with_details(json!({ "stream_id": stream_id, "bytes_received": bytes_received }))
+                    .into());
+            }
+            bytes_received += chunk.len() as u64;
+        }
+        Ok(bytes_received)
+    }
+}
@@ -0,0 +1,158 @@
+use anyhow::Result;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION};
+use reqwest::{Method, Url};
+use ring::{digest, hmac};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Payload hash for bodies that are streamed (files, multipart) and therefore not hashed
+pub const UNSIGNED_PAYLOAD: &str = "UNSIGNED-PAYLOAD";
+
+/// Credentials and scope for AWS Signature Version 4
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AwsSigV4<'a> {
+    pub access_key_id: &'a str,
+    pub secret_access_key: &'a str,
+    /// Temporary credentials (STS) also need their token signed
+    #[serde(default)]
+    pub session_token: Option<&'a str>,
+    pub region: &'a str,
+    pub service: &'a str,
+}
+
+pub struct SigV4;
+
+impl SigV4 {
+    /// Folds the URL's query and `query_params` into one query encoded the SigV4 way
+    /// (RFC 3986, spaces as `%20`, sorted), so the bytes sent are exactly the ones signed.
+    /// reqwest's `.query()` form-encodes (`+` for spaces), which would break the signature.
+    pub fn canonical_url(url: &str, query_params: &HashMap<&str, &str>) -> Result<Url> {
+        let mut url = Url::parse(url)?;
+        let mut pairs: Vec<(String, String)> = url
+            .query_pairs()
+            .map(|(name, value)| (Self::uri_encode(&name, true), Self::uri_encode(&value, true)))
+            .collect();
+        pairs.extend(query_params.iter().map(|(name, value)| (Self::uri_encode(name, true), Self::uri_encode(value, true))));
+        pairs.sort_unstable();
+
+        if pairs.is_empty() {
+            url.set_query(None);
+        } else {
+            let query: Vec<String> = pairs.into_iter().map(|(name, value)| format!("{}={}", name, value)).collect();
+            url.set_query(Some(&query.join("&")));
+        }
+        Ok(url)
+    }
+
+    /// Hex SHA-256 of an in-memory body (`None` = empty)
+    pub fn payload_hash(body: Option<&[u8]>) -> String {
+        Self::hex(digest::digest(&digest::SHA256, body.unwrap_or_default()).as_ref())
+    }
+
+    /// Adds `x-amz-date`, `x-amz-content-sha256`, `x-amz-security-token` (if any) and the
+    /// `Authorization` header. `url` must come from `canonical_url`. Signed headers are
+    /// `host`, `content-type` (if set) and every `x-amz-*` header.
+    pub fn sign(credentials: &AwsSigV4<'_>, method: &Method, url: &Url, headers: &mut HeaderMap, payload_hash: &str) -> Result<()> {
+        let (date, timestamp) = Self::timestamp(SystemTime::now());
+        headers.insert(HeaderName::from_static("x-amz-date"), HeaderValue::from_str(&timestamp)?);
+        headers.insert(HeaderName::from_static("x-amz-content-sha256"), HeaderValue::from_str(payload_hash)?);
+        if let Some(token) = credentials.session_token {
+            headers.insert(HeaderName::from_static("x-amz-security-token"), HeaderValue::from_str(token)?);
+        }
+
+        let host = match (url.host_str(), url.port()) {
+            (Some(host), Some(port)) => format!("{}:{}", host, port),
+            (Some(host), None) => host.to_owned(),
+            (None, _) => anyhow::bail!("SigV4 needs a URL with a host: {}", url),
+        };
+        let mut signed: Vec<(String, String)> = vec![("host".to_owned(), host)];
+        for (name, value) in headers.iter() {
+            let name = name.as_str();
+            if name.starts_with("x-amz-") || name == "content-type" {
+                signed.push((name.to_owned(), value.to_str()?.trim().to_owned()));
+            }
+        }
+        signed.sort_unstable();
+        let signed_headers = signed.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>().join(";");
+
+        // S3 signs the path as sent; every other service signs it URI-encoded once more
+        let path = if credentials.service == "s3" { url.path().to_owned() } else { Self::uri_encode(url.path(), false) };
+
+        let mut canonical_request = format!("{}\n{}\n{}\n", method, path, url.query().unwrap_or_default());
+        for (name, value) in &signed {
+            let _ = writeln!(canonical_request, "{}:{}", name, value);
+        }
+        let _ = write!(canonical_request, "\n{}\n{}", signed_headers, payload_hash);
+
+        let scope = format!("{}/{}/{}/aws4_request", date, credentials.region, credentials.service);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            timestamp,
+            scope,
+            Self::payload_hash(Some(canonical_request.as_bytes()))
+        );
+
+        let mut key = Self::hmac(format!("AWS4{}", credentials.secret_access_key).as_bytes(), date.as_bytes());
+        for part in [credentials.region, credentials.service, "aws4_request"] {
+            key = Self::hmac(&key, part.as_bytes());
+        }
+        let signature = Self::hex(&Self::hmac(&key, string_to_sign.as_bytes()));
+
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            credentials.access_key_id, scope, signed_headers, signature
+        );
+        let mut authorization = HeaderValue::from_str(&authorization)?;
+        authorization.set_sensitive(true);
+        headers.insert(AUTHORIZATION, authorization);
+        Ok(())
+    }
+
+    fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+        hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, key), data).as_ref().to_vec()
+    }
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().fold(String::with_capacity(bytes.len() * 2), |mut out, byte| {
+            let _ = write!(out, "{:02x}", byte);
+            out
+        })
+    }
+
+    /// RFC 3986 encoding: unreserved chars stay, everything else is `%XX`; `/` is kept
+    /// unless `encode_slash`
+    fn uri_encode(input: &str, encode_slash: bool) -> String {
+        let mut out = String::with_capacity(input.len());
+        for byte in input.bytes() {
+            match byte {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => out.push(byte as char),
+                b'/' if !encode_slash => out.push('/'),
+                _ => { let _ = write!(out, "%{:02X}", byte); }
+            }
+        }
+        out
+    }
+
+    /// (`YYYYMMDD`, `YYYYMMDD'T'HHMMSS'Z'`) in UTC
+    fn timestamp(now: SystemTime) -> (String, String) {
+        let secs = now.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default();
+        let (days, rem) = (secs / 86_400, secs % 86_400);
+
+        // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+        let z = days as i64 + 719_468;
+        let era = z.div_euclid(146_097);
+        let doe = z.rem_euclid(146_097);
+        let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + i64::from(month <= 2);
+
+        let date = format!("{:04}{:02}{:02}", year, month, day);
+        let time = format!("{}T{:02}{:02}{:02}Z", date, rem / 3_600, rem % 3_600 / 60, rem % 60);
+        (date, time)
+    }
+}
@@ -0,0 +1,115 @@
+use crate::client_config::ClientConfig;
+
+use parking_lot::Mutex;
+use reqwest::Client;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Requests in flight per host and lane
+type InFlight = Arc<Mutex<HashMap<String, Vec<usize>>>>;
+
+/// Extra HTTP/2 connections per host, see `Http2Settings::max_concurrent_streams`.
+///
+/// A client's pool keeps one HTTP/2 connection per origin, so each lane is a client with a
+/// pool of its own: lane 0 is the `HttpClient`'s client, lanes 1.. are built on first use
+/// and shared by every host. A request goes out on the first lane where its host has fewer
+/// than `max_concurrent_streams` requests in flight, else on the least busy one.
+pub struct StreamLanes {
+    extra: Mutex<Vec<Client>>,
+    in_flight: InFlight,
+}
+
+/// Holds a request's stream on its lane until dropped
+pub struct LaneGuard {
+    in_flight: InFlight,
+    host: String,
+    lane: usize,
+}
+
+impl Drop for LaneGuard {
+    fn drop(&mut self) {
+        let mut in_flight = self.in_flight.lock();
+        if let Some(counts) = in_flight.get_mut(&self.host) {
+            counts[self.lane] = counts[self.lane].saturating_sub(1);
+            if counts.iter().all(|&count| count == 0) {
+                in_flight.remove(&self.host);
+            }
+        }
+    }
+}
+
+impl StreamLanes {
+    pub fn new() -> Self {
+        Self { extra: Mutex::new(Vec::new()), in_flight: Arc::new(Mutex::new(HashMap::new())) }
+    }
+
+    /// Client to send a request to `host` with (`own` on lane 0) and the guard holding its
+    /// stream; `own` and no guard while spilling is off. Lanes are built like `resume` builds.
+    pub fn acquire(&self, host: &str, own: Client, paused: bool) -> (Client, Option<LaneGuard>) {
+        let (limit, lanes) = {
+            let settings = ClientConfig::settings();
+            (settings.http2.max_concurrent_streams, settings.http2.max_connections_per_host as usize)
+        };
+        let Some(limit) = limit.filter(|_| lanes > 1) else { return (own, None) };
+        let guard = self.take_stream(host, limit as usize, lanes);
+        if guard.lane == 0 {
+            return (own, Some(guard));
+        }
+        let mut extra = self.extra.lock();
+        while extra.len() < guard.lane {
+            extra.push(if paused { ClientConfig::build_paused_mobile_client() } else { ClientConfig::build_shared_mobile_client() });
+        }
+        (extra[guard.lane - 1].clone(), Some(guard))
+    }
+
+    fn take_stream(&self, host: &str, limit: usize, lanes: usize) -> LaneGuard {
+        let mut in_flight = self.in_flight.lock();
+        let counts = in_flight.entry(host.to_owned()).or_default();
+        if counts.len() < lanes {
+            counts.resize(lanes, 0);
+        }
+        let lane = counts[..lanes].iter().position(|&count| count < limit).unwrap_or_else(|| {
+            (0..lanes).min_by_key(|&lane| counts[lane]).unwrap_or_default()
+        });
+        counts[lane] += 1;
+        LaneGuard { in_flight: Arc::clone(&self.in_flight), host: host.to_owned(), lane }
+    }
+
+    /// Drops the lane clients, as `HttpClient` replaces its own; requests using them finish first
+    pub fn clear(&self) {
+        self.extra.lock().clear();
+    }
+}
+
+impl Default for StreamLanes {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spills_to_next_lane_once_streams_are_taken() {
+        let lanes = StreamLanes::new();
+        let mut taken: Vec<LaneGuard> = (0..5).map(|_| lanes.take_stream("chatty.test", 2, 3)).collect();
+        assert_eq!(taken.iter().map(|guard| guard.lane).collect::<Vec<_>>(), [0, 0, 1, 1, 2]);
+        // All lanes at the limit: the least busy one takes the next request
+        let mut more: Vec<LaneGuard> = (0..2).map(|_| lanes.take_stream("chatty.test", 2, 3)).collect();
+        assert_eq!(more.iter().map(|guard| guard.lane).collect::<Vec<_>>(), [2, 0]);
+        // Other hosts have lanes of their own
+        assert_eq!(lanes.take_stream("quiet.test", 2, 3).lane, 0);
+        more.clear();
+        drop(taken.remove(2));
+        assert_eq!(lanes.take_stream("chatty.test", 2, 3).lane, 1);
+    }
+
+    #[test]
+    fn finished_requests_leave_no_entry() {
+        let lanes = StreamLanes::new();
+        drop(lanes.take_stream("chatty.test", 1, 2));
+        assert!(lanes.in_flight.lock().is_empty());
+    }
+}
@@ -0,0 +1,159 @@
+use crate::client_config::ClientConfig;
+use crate::json;
+use crate::models::{HttpError, HttpResponse};
+
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Origin of every exported `ts`
+static EPOCH: Lazy<Instant> = Lazy::new(Instant::now);
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+/// Finished requests, oldest first, at most `ClientSettings::trace_buffer_requests`
+static RECENT: Lazy<Mutex<VecDeque<RequestTrace>>> = Lazy::new(|| Mutex::new(VecDeque::new()));
+
+tokio::task_local! {
+    static CURRENT: Recorder;
+}
+
+/// Phases collected for the request running on this task. Connector and resolver futures
+/// clone it when created, since hyper may finish a connect on another task.
+#[derive(Clone, Default)]
+pub struct Recorder(Arc<Mutex<Vec<Span>>>);
+
+impl Recorder {
+    /// Records phase `name` from `start` until now
+    pub fn record(&self, name: &'static str, start: Instant) {
+        self.0.lock().push(Span { name, start, end: Instant::now() });
+    }
+}
+
+struct Span {
+    name: &'static str,
+    start: Instant,
+    end: Instant,
+}
+
+struct RequestTrace {
+    id: u64,
+    name: String,
+    url: String,
+    start: Instant,
+    end: Instant,
+    status_code: Option<u16>,
+    error: Option<String>,
+    spans: Vec<Span>,
+}
+
+/// Chrome Trace Event Format complete event (`ph: "X"`), times in microseconds
+#[derive(Serialize)]
+struct TraceEvent<'a> {
+    name: &'a str,
+    cat: &'static str,
+    ph: &'static str,
+    ts: u64,
+    dur: u64,
+    pid: u32,
+    tid: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    args: Option<TraceArgs<'a>>,
+}
+
+#[derive(Serialize)]
+struct TraceArgs<'a> {
+    url: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status_code: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<&'a str>,
+}
+
+/// Per-request timing traces for `chrome://tracing` / Perfetto, on while
+/// `ClientSettings::trace_buffer_requests` is above 0. Each request is one track (`tid`)
+/// whose `METHOD url` event spans `execute_request`, with nested phases:
+/// `ttfb` (send until the response head, redirects included), `connect` inside it for a
+/// new connection (TCP + TLS; reqwest does not time the handshake apart), `dns` inside
+/// that, and `body`. Hedged or fallback attempts add their phases to the same track.
+pub struct Trace;
+
+impl Trace {
+    /// Runs `request` with a recorder and keeps its trace, evicting the oldest past the limit
+    pub async fn scope(method: &str, url: &str, request: impl Future<Output = Result<HttpResponse>>) -> Result<HttpResponse> {
+        let capacity = ClientConfig::settings().trace_buffer_requests;
+        if capacity == 0 {
+            return request.await;
+        }
+
+        Lazy::force(&EPOCH);
+        let recorder = Recorder::default();
+        let start = Instant::now();
+        let result = CURRENT.scope(recorder.clone(), request).await;
+        let trace = RequestTrace {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            name: format!("{} {}", method, url),
+            url: url.to_owned(),
+            start,
+            end: Instant::now(),
+            status_code: result.as_ref().ok().map(|response| response.status_code),
+            error: result.as_ref().err().map(|e| HttpError::code_of(e).to_owned()),
+            spans: std::mem::take(&mut *recorder.0.lock()),
+        };
+
+        let mut recent = RECENT.lock();
+        while recent.len() >= capacity {
+            recent.pop_front();
+        }
+        recent.push_back(trace);
+        result
+    }
+
+    /// Recorder of the request on this task, if it is traced
+    pub fn current() -> Option<Recorder> {
+        CURRENT.try_with(Recorder::clone).ok()
+    }
+
+    /// Records phase `name` from `start` until now on this task's request, if traced
+    pub fn phase(name: &'static str, start: Instant) {
+        if let Some(recorder) = Self::current() {
+            recorder.record(name, start);
+        }
+    }
+
+    /// The buffered requests as a JSON array of trace events, oldest first
+    pub fn export() -> Vec<u8> {
+        let micros = |at: Instant| at.saturating_duration_since(*EPOCH).as_micros() as u64;
+        let recent = RECENT.lock();
+        let mut events = Vec::with_capacity(recent.iter().map(|trace| trace.spans.len() + 1).sum());
+        for trace in recent.iter() {
+            events.push(TraceEvent {
+                name: &trace.name,
+                cat: "request",
+                ph: "X",
+                ts: micros(trace.start),
+                dur: (trace.end - trace.start).as_micros() as u64,
+                pid: 1,
+                tid: trace.id,
+                args: Some(TraceArgs { url: &trace.url, status_code: trace.status_code, error: trace.error.as_deref() }),
+            });
+            for span in &trace.spans {
+                events.push(TraceEvent {
+                    name: span.name,
+                    cat: "phase",
+                    ph: "X",
+                    ts: micros(span.start),
+                    dur: (span.end - span.start).as_micros() as u64,
+                    pid: 1,
+                    tid: trace.id,
+                    args: None,
+                });
+            }
+        }
+        json::to_vec(&events).unwrap_or_else(|_| b"[]".to_vec())
+    }
+}
@@ -0,0 +1,101 @@
+#[cfg(any(test, feature = "test-util"))]
+use bytes::Bytes;
+#[cfg(any(test, feature = "test-util"))]
+use futures_util::future::FutureExt;
+use futures_util::future::BoxFuture;
+#[cfg(any(test, feature = "test-util"))]
+use parking_lot::Mutex;
+#[cfg(any(test, feature = "test-util"))]
+use reqwest::Body;
+use reqwest::{Client, Request, Response};
+#[cfg(any(test, feature = "test-util"))]
+use std::future::Future;
+
+/// The step of `HttpClient` that puts a built request on the wire. Everything around it
+/// (headers, body, cache, download, error mapping) runs the same whichever transport is used.
+pub trait Transport: Send + Sync {
+    /// `client` is the one the request was built with (shared, passthrough or pinned)
+    fn execute(&self, client: &Client, request: Request) -> BoxFuture<'static, reqwest::Result<Response>>;
+}
+
+/// Sends through reqwest; what `HttpClient::new` and `HttpClient::shared` use
+pub struct ReqwestTransport;
+
+impl Transport for ReqwestTransport {
+    fn execute(&self, client: &Client, request: Request) -> BoxFuture<'static, reqwest::Result<Response>> {
+        Box::pin(client.execute(request))
+    }
+}
+
+#[cfg(any(test, feature = "test-util"))]
+type Handler = Box<dyn Fn(Request) -> BoxFuture<'static, hyper::Response<Body>> + Send + Sync>;
+
+/// Answers every request with a canned response and never touches the network, for tests
+/// via `HttpClient::with_transport`. Redirects are not followed: a 3xx is returned as is.
+/// Only built for tests and with the `test-util` feature.
+#[cfg(any(test, feature = "test-util"))]
+pub struct MockTransport {
+    handler: Handler,
+    requests: Mutex<Vec<String>>,
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl MockTransport {
+    pub fn new<F>(responder: F) -> Self
+    where
+        F: Fn(&Request) -> hyper::Response<Bytes> + Send + Sync + 'static,
+    {
+        Self::with_handler(move |request| {
+            let response = responder(&request).map(Body::from);
+            async move { response }
+        })
+    }
+
+    /// A mock that owns each request and answers asynchronously, e.g. to read (or stall on)
+    /// the request body, or to stream a response body
+    pub fn with_handler<F, Fut>(handler: F) -> Self
+    where
+        F: Fn(Request) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = hyper::Response<Body>> + Send + 'static,
+    {
+        Self { handler: Box::new(move |request| handler(request).boxed()), requests: Mutex::new(Vec::new()) }
+    }
+
+    /// `"METHOD url"` of every request seen so far, oldest first
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().clone()
+    }
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl Transport for MockTransport {
+    fn execute(&self, _client: &Client, request: Request) -> BoxFuture<'static, reqwest::Result<Response>> {
+        self.requests.lock().push(format!("{} {}", request.method(), request.url()));
+        let response = (self.handler)(request);
+        Box::pin(async move { Ok(Response::from(response.await)) })
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    /// Mock answering after `delay`, recording the most requests it ever had in flight
+    pub(crate) fn counting(delay: Duration) -> (MockTransport, Arc<AtomicUsize>) {
+        let (current, peak) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
+        let observed = Arc::clone(&peak);
+        let transport = MockTransport::with_handler(move |_| {
+            let (current, peak) = (Arc::clone(&current), Arc::clone(&peak));
+            async move {
+                peak.fetch_max(current.fetch_add(1, Ordering::SeqCst) + 1, Ordering::SeqCst);
+                tokio::time::sleep(delay).await;
+                current.fetch_sub(1, Ordering::SeqCst);
+                hyper::Response::new(Body::from("ok"))
+            }
+        });
+        (transport, observed)
+    }
+}
@@ -0,0 +1,111 @@
+//! Request bodies pushed from Dart chunk by chunk.
+//!
+//! `upload_stream_open(id)` creates the stream, `upload_stream_write` queues chunks and
+//! `upload_stream_close` marks the end. A request with `body_stream_id = id` consumes it.
+//! Chunks may be written before the request starts (they are buffered) or while it runs,
+//! from another isolate since the request call blocks its caller.
+
+use crate::json::json;
+use crate::models::HttpError;
+
+use bytes::Bytes;
+use futures::channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+use futures_util::stream::{self, BoxStream, StreamExt};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::io;
+
+#[derive(Default)]
+struct Slot {
+    sender: Option<UnboundedSender<Bytes>>,
+    receiver: Option<UnboundedReceiver<Bytes>>,
+}
+
+static STREAMS: Lazy<Mutex<HashMap<i64, Slot>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+pub struct UploadStream;
+
+impl UploadStream {
+    /// False if `id` is already open
+    pub fn open(id: i64) -> bool {
+        let mut streams = STREAMS.lock();
+        if streams.contains_key(&id) {
+            return false;
+        }
+        let (sender, receiver) = unbounded();
+        streams.insert(id, Slot { sender: Some(sender), receiver: Some(receiver) });
+        true
+    }
+
+    /// False if `id` is not open, already closed, or its request has finished
+    pub fn write(id: i64, chunk: Bytes) -> bool {
+        match STREAMS.lock().get(&id).and_then(|slot| slot.sender.as_ref()) {
+            Some(sender) => sender.unbounded_send(chunk).is_ok(),
+            None => false,
+        }
+    }
+
+    /// Ends the body once the queued chunks are sent. False if `id` is not open.
+    pub fn close(id: i64) -> bool {
+        let mut streams = STREAMS.lock();
+        let Some(slot) = streams.get_mut(&id) else { return false };
+        slot.sender = None;
+        if slot.receiver.is_none() {
+            streams.remove(&id);
+        }
+        true
+    }
+
+    /// Takes the body for `id`. With `declared_len`, the body fails with
+    /// `UPLOAD_LENGTH_MISMATCH` as soon as it runs past that length, or if it ends short,
+    /// so a truncated upload is never sent as complete. The final chunk therefore waits
+    /// for `upload_stream_close`.
+    pub fn take(id: i64, declared_len: Option<u64>) -> Result<BoxStream<'static, io::Result<Bytes>>, HttpError> {
+        let receiver = {
+            let mut streams = STREAMS.lock();
+            let receiver = streams.get_mut(&id).and_then(|slot| slot.receiver.take());
+            if streams.get(&id).is_some_and(|slot| slot.sender.is_none()) {
+                streams.remove(&id);
+            }
+            receiver
+        };
+        let Some(receiver) = receiver else {
+            return Err(HttpError::new("UPLOAD_STREAM_NOT_FOUND", format!("Upload stream {} is not open or already in use", id)));
+        };
+
+        let Some(declared) = declared_len else { return Ok(receiver.map(Ok).boxed()) };
+
+        // hyper stops polling once `declared` bytes are out, so the chunk that completes
+        // the body is held back until the stream is closed: extra bytes must fail the
+        // request instead of being silently cut off
+        Ok(stream::unfold(Some((receiver, 0u64)), move |state| async move {
+            let (mut receiver, mut received) = state?;
+            let Some(chunk) = receiver.next().await else {
+                return (received < declared).then(|| (Err(Self::mismatch(id, declared, received.to_string())), None));
+            };
+            received += chunk.len() as u64;
+            if received == declared {
+                while let Some(extra) = receiver.next().await {
+                    if !extra.is_empty() {
+                        return Some((Err(Self::mismatch(id, declared, format!("more than {}", declared))), None));
+                    }
+                }
+            }
+            if received > declared {
+                return Some((Err(Self::mismatch(id, declared, format!("more than {}", declared))), None));
+            }
+            Some((Ok(chunk), Some((receiver, received))))
+        })
+        .boxed())
+    }
+
+    fn mismatch(id: i64, declared: u64, received: String) -> io::Error {
+        let error = HttpError::new(
+            "UPLOAD_LENGTH_MISMATCH",
+            format!("Upload stream {} declared {} bytes but delivered {}", id, declared, received),
+        )
+        .with_details(json!({ "body_stream_id": id, "declared": declared }));
+        io::Error::other(error)
+    }
+}
@@ -1,15 +1,15 @@
 use serde::Serialize;
 use serde::de::DeserializeOwned;
 use anyhow::Result;
-use simd_json::{self, prelude::*};
+use crate::json;
 
 pub fn serialize<T: Serialize>(value: &T) -> Result<String> {
-    Ok(simd_json::to_string(value)?)
+    json::to_string(value)
 }
 
 pub fn deserialize<T: DeserializeOwned>(json: &str) -> Result<T> {
-    let mut json_string = json.to_string(); // simd-json requires a mutable buffer
-    Ok(simd_json::from_str(&mut json_string)?)
+    let mut json_bytes = json.as_bytes().to_vec(); // simd-json requires a mutable buffer
+    json::from_slice(&mut json_bytes)
 }
 
 pub fn validate_url(url: &str) -> Result<()> {
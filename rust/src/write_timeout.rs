@@ -0,0 +1,108 @@
+use bytes::Bytes;
+use futures_util::FutureExt;
+use http_body::{Body as HttpBody, Frame, SizeHint};
+use reqwest::{Body, Request};
+use std::future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::time::Instant;
+
+#[derive(Debug, Clone, Copy)]
+enum Upload {
+    Waiting,
+    Started(Instant),
+    Done,
+}
+
+/// Bounds the upload of a request body for `write_timeout_ms`: from the first chunk hyper
+/// pulls (the connection is up and the head written) to the last one, where `RequestSent`
+/// fires. Waiting for the response after that is not write time, and a request without a
+/// body has no write phase.
+pub struct WriteTimeout {
+    upload: watch::Receiver<Upload>,
+    timeout: Duration,
+}
+
+impl WriteTimeout {
+    /// Wraps the body of `request` so its upload is timed; `None` if it has no body
+    pub fn wrap(request: &mut Request, timeout: Duration) -> Option<Self> {
+        let inner = request.body_mut().take()?;
+        let (upload, receiver) = watch::channel(Upload::Waiting);
+        let remaining = inner.size_hint().exact();
+        *request.body_mut() = Some(Body::wrap(TimedBody { inner, upload, remaining }));
+        Some(Self { upload: receiver, timeout })
+    }
+
+    /// Resolves once the upload has run longer than the timeout; never if it finishes in
+    /// time or the request drops the body
+    pub async fn expired(mut self) {
+        let started = loop {
+            let upload = *self.upload.borrow_and_update();
+            match upload {
+                Upload::Started(at) => break at,
+                Upload::Done => return future::pending().await,
+                Upload::Waiting => {}
+            }
+            if self.upload.changed().await.is_err() {
+                return future::pending().await;
+            }
+        };
+        tokio::select! {
+            _ = tokio::time::sleep_until(started + self.timeout) => {}
+            _ = self.upload.wait_for(|upload| matches!(upload, Upload::Done)).map(drop) => future::pending().await,
+        }
+    }
+}
+
+struct TimedBody {
+    inner: Body,
+    upload: watch::Sender<Upload>,
+    /// Bytes left of a sized body: hyper stops pulling at its length, without a final poll
+    remaining: Option<u64>,
+}
+
+impl TimedBody {
+    fn finish(&self) {
+        self.upload.send_replace(Upload::Done);
+    }
+}
+
+impl HttpBody for TimedBody {
+    type Data = Bytes;
+    type Error = reqwest::Error;
+
+    fn poll_frame(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Frame<Bytes>, reqwest::Error>>> {
+        self.upload.send_if_modified(|upload| match upload {
+            Upload::Waiting => {
+                *upload = Upload::Started(Instant::now());
+                true
+            }
+            _ => false,
+        });
+        let polled = Pin::new(&mut self.inner).poll_frame(cx);
+        match &polled {
+            Poll::Ready(Some(Ok(frame))) => {
+                let len = frame.data_ref().map_or(0, |data| data.len() as u64);
+                if let Some(remaining) = self.remaining.as_mut() {
+                    *remaining = remaining.saturating_sub(len);
+                    if *remaining == 0 {
+                        self.finish();
+                    }
+                }
+            }
+            Poll::Ready(_) => self.finish(),
+            Poll::Pending => {}
+        }
+        polled
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}